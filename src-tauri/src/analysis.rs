@@ -0,0 +1,636 @@
+use crate::cache::{CacheKey, TranscodeCache};
+use crate::error::AppError;
+use crate::pcm::{CurrentJob, Decode};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+pub const ANALYSIS_READY_EVENT: &str = "analysis-ready";
+
+/// Bumped whenever the features change, so stale cache entries are not reused.
+const ANALYSIS_VARIANT: &str = "analysis-v1";
+/// Mono at this rate is plenty for band energies up to the top band and for beats.
+const SAMPLE_RATE: u32 = 22_050;
+/// Feature frames per second (approximately; the hop is a whole number of samples).
+const FRAME_RATE: u32 = 50;
+/// FFT window; ~93 ms at 22.05 kHz, ~11 Hz per bin so the bass bands get a few bins.
+const FFT_LEN: usize = 2048;
+/// Band edges in Hz: sub-bass, bass, low mids, mids, upper mids, presence, brilliance.
+const BAND_EDGES: [f32; 8] = [20.0, 60.0, 150.0, 400.0, 1000.0, 2500.0, 6000.0, 11_025.0];
+/// Levels are quantised over this many dB below the reference (0 = silence or quieter).
+const DYNAMIC_RANGE_DB: f32 = 60.0;
+/// Tempo search range and the tempo the estimate is biased towards.
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 180.0;
+const PREFERRED_BPM: f32 = 120.0;
+/// Onsets must exceed this many standard deviations of the onset envelope.
+const ONSET_THRESHOLD: f32 = 1.5;
+/// Onsets closer together than this are merged.
+const MIN_ONSET_GAP: f32 = 0.1;
+/// How strongly the beat tracker holds the tempo against off-beat onsets.
+const TEMPO_TIGHTNESS: f32 = 100.0;
+
+/// Time-indexed features of a track for audio-reactive visuals. Frame `i` is centred on
+/// `i / frame_rate` seconds of playback. Per-frame levels are bytes (0–255), base64-encoded.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Analysis {
+    pub frame_rate: f64,
+    pub frames: u32,
+    /// Seconds covered by the frames.
+    pub duration: f64,
+    /// Band `b` spans `band_edges[b]..band_edges[b + 1]` Hz.
+    pub band_edges: Vec<f32>,
+    /// Energy per band, frame-major (`frame * bands + band`), over the
+    /// `DYNAMIC_RANGE_DB` below each band's loudest frame.
+    pub bands_base64: String,
+    /// RMS level per frame over -60 to 0 dBFS.
+    pub loudness_base64: String,
+    /// Onset strength (spectral flux) per frame, relative to the track's strongest onset.
+    pub onset_strength_base64: String,
+    /// Onset times in seconds.
+    pub onsets: Vec<f32>,
+    /// Beat times in seconds, following `bpm`.
+    pub beats: Vec<f32>,
+    /// `None` when the track has no discernible pulse.
+    pub bpm: Option<f32>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct AnalysisReady {
+    /// The track as passed to `request_analysis`.
+    path: String,
+    start: Option<f64>,
+    end: Option<f64>,
+    analysis: Option<Analysis>,
+    error: Option<AppError>,
+}
+
+/// Iterative radix-2 FFT of a fixed power-of-two length.
+struct Fft {
+    twiddles: Vec<(f32, f32)>,
+    reversed: Vec<usize>,
+}
+
+impl Fft {
+    fn new(len: usize) -> Self {
+        let bits = len.trailing_zeros();
+        Fft {
+            twiddles: (0..len / 2)
+                .map(|k| {
+                    let angle = -2.0 * PI * k as f32 / len as f32;
+                    (angle.cos(), angle.sin())
+                })
+                .collect(),
+            reversed: (0..len)
+                .map(|i| i.reverse_bits() >> (usize::BITS - bits))
+                .collect(),
+        }
+    }
+
+    /// Transforms `re`/`im` in place.
+    fn process(&self, re: &mut [f32], im: &mut [f32]) {
+        let len = re.len();
+        for (i, &j) in self.reversed.iter().enumerate() {
+            if i < j {
+                re.swap(i, j);
+                im.swap(i, j);
+            }
+        }
+        let mut size = 2;
+        while size <= len {
+            let half = size / 2;
+            let step = len / size;
+            for chunk in (0..len).step_by(size) {
+                for k in 0..half {
+                    let (wr, wi) = self.twiddles[k * step];
+                    let (a, b) = (chunk + k, chunk + k + half);
+                    let tr = re[b] * wr - im[b] * wi;
+                    let ti = re[b] * wi + im[b] * wr;
+                    re[b] = re[a] - tr;
+                    im[b] = im[a] - ti;
+                    re[a] += tr;
+                    im[a] += ti;
+                }
+            }
+            size *= 2;
+        }
+    }
+}
+
+/// Per-frame spectrum features, accumulated while the track is decoded.
+struct Analyser {
+    fft: Fft,
+    window: Vec<f32>,
+    hop: usize,
+    /// FFT bin ranges of the bands.
+    band_bins: Vec<(usize, usize)>,
+    /// Mono samples not yet consumed by a full window.
+    samples: Vec<f32>,
+    re: Vec<f32>,
+    im: Vec<f32>,
+    /// Log-magnitude spectrum of the previous frame, for the spectral flux.
+    previous: Vec<f32>,
+    /// Band energies in dB, frame-major.
+    bands: Vec<f32>,
+    loudness: Vec<f32>,
+    flux: Vec<f32>,
+}
+
+impl Analyser {
+    fn new(sample_rate: u32) -> Self {
+        let hop = (sample_rate / FRAME_RATE).max(1) as usize;
+        let bin_hz = sample_rate as f32 / FFT_LEN as f32;
+        let nyquist_bin = FFT_LEN / 2;
+        let band_bins = BAND_EDGES
+            .windows(2)
+            .map(|edge| {
+                let low = ((edge[0] / bin_hz).ceil() as usize).clamp(1, nyquist_bin);
+                let high = ((edge[1] / bin_hz).ceil() as usize).clamp(low, nyquist_bin);
+                (low, high)
+            })
+            .collect();
+        Analyser {
+            fft: Fft::new(FFT_LEN),
+            window: (0..FFT_LEN)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_LEN as f32).cos())
+                .collect(),
+            hop,
+            band_bins,
+            // Half a window of silence first, so frame `i` is centred on sample `i * hop`.
+            samples: vec![0.0; FFT_LEN / 2],
+            re: vec![0.0; FFT_LEN],
+            im: vec![0.0; FFT_LEN],
+            previous: vec![0.0; nyquist_bin],
+            bands: Vec::new(),
+            loudness: Vec::new(),
+            flux: Vec::new(),
+        }
+    }
+
+    /// Adds mono 16-bit little-endian samples.
+    fn push(&mut self, bytes: &[u8]) {
+        self.samples.extend(
+            bytes
+                .chunks_exact(2)
+                .map(|s| f32::from(i16::from_le_bytes([s[0], s[1]])) / 32768.0),
+        );
+        self.process_frames();
+    }
+
+    fn process_frames(&mut self) {
+        let mut offset = 0;
+        while self.samples.len() - offset >= FFT_LEN {
+            let frame = &self.samples[offset..offset + FFT_LEN];
+            let mean_square = frame.iter().map(|s| s * s).sum::<f32>() / FFT_LEN as f32;
+            self.loudness.push(to_db(mean_square));
+            for (i, (&sample, &w)) in frame.iter().zip(&self.window).enumerate() {
+                self.re[i] = sample * w;
+                self.im[i] = 0.0;
+            }
+            self.fft.process(&mut self.re, &mut self.im);
+
+            let power: Vec<f32> = (0..FFT_LEN / 2)
+                .map(|k| self.re[k] * self.re[k] + self.im[k] * self.im[k])
+                .collect();
+            for &(low, high) in &self.band_bins {
+                self.bands.push(to_db(power[low..high].iter().sum()));
+            }
+            let mut flux = 0.0;
+            for (previous, &p) in self.previous.iter_mut().zip(&power) {
+                let magnitude = (1.0 + 100.0 * p.sqrt()).ln();
+                flux += (magnitude - *previous).max(0.0);
+                *previous = magnitude;
+            }
+            self.flux.push(flux);
+            offset += self.hop;
+        }
+        self.samples.drain(..offset);
+    }
+
+    fn finish(mut self, sample_rate: u32, duration: f64) -> Analysis {
+        // Half a window of silence last, so the final samples get frames of their own.
+        self.samples
+            .extend(std::iter::repeat(0.0).take(FFT_LEN / 2));
+        self.process_frames();
+        let frame_rate = f64::from(sample_rate) / self.hop as f64;
+        // Frames past the end are only there because of the padding.
+        let frames = ((duration * frame_rate).ceil() as usize + 1).min(self.flux.len());
+        self.flux.truncate(frames);
+        self.loudness.truncate(frames);
+        let band_count = BAND_EDGES.len() - 1;
+        self.bands.truncate(frames * band_count);
+        // The first frame has no predecessor; its "flux" is just the opening spectrum.
+        if let Some(first) = self.flux.first_mut() {
+            *first = 0.0;
+        }
+
+        let mut band_max = vec![f32::NEG_INFINITY; band_count];
+        for frame in self.bands.chunks_exact(band_count) {
+            for (max, &db) in band_max.iter_mut().zip(frame) {
+                *max = max.max(db);
+            }
+        }
+        let bands: Vec<u8> = self
+            .bands
+            .iter()
+            .enumerate()
+            .map(|(i, &db)| quantise(db, band_max[i % band_count]))
+            .collect();
+        let loudness: Vec<u8> = self.loudness.iter().map(|&db| quantise(db, 0.0)).collect();
+        let flux_max = self.flux.iter().copied().fold(0.0, f32::max);
+        let onset_strength: Vec<u8> = self
+            .flux
+            .iter()
+            .map(|&f| {
+                if flux_max > 0.0 {
+                    (f / flux_max * 255.0).round() as u8
+                } else {
+                    0
+                }
+            })
+            .collect();
+
+        let frame_rate_f32 = frame_rate as f32;
+        let envelope = onset_envelope(&self.flux, frame_rate_f32);
+        let onsets = pick_onsets(&envelope, frame_rate_f32)
+            .into_iter()
+            .map(|i| i as f32 / frame_rate_f32)
+            .collect();
+        let period = estimate_period(&envelope, frame_rate_f32);
+        let beats = period
+            .map(|period| track_beats(&envelope, period))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|i| i as f32 / frame_rate_f32)
+            .collect();
+
+        Analysis {
+            frame_rate,
+            frames: frames as u32,
+            duration,
+            band_edges: BAND_EDGES.to_vec(),
+            bands_base64: general_purpose::STANDARD.encode(bands),
+            loudness_base64: general_purpose::STANDARD.encode(loudness),
+            onset_strength_base64: general_purpose::STANDARD.encode(onset_strength),
+            onsets,
+            beats,
+            bpm: period.map(|period| 60.0 * frame_rate_f32 / period),
+        }
+    }
+}
+
+fn to_db(power: f32) -> f32 {
+    10.0 * power.max(1e-12).log10()
+}
+
+/// Maps `db` to 0–255 over the `DYNAMIC_RANGE_DB` below `reference`.
+fn quantise(db: f32, reference: f32) -> u8 {
+    let level = (db - reference + DYNAMIC_RANGE_DB) / DYNAMIC_RANGE_DB;
+    (level.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Local mean of `values` over `radius` frames either side.
+fn moving_average(values: &[f32], radius: usize) -> Vec<f32> {
+    let mut sums = Vec::with_capacity(values.len() + 1);
+    sums.push(0.0f64);
+    for &v in values {
+        sums.push(sums.last().unwrap() + f64::from(v));
+    }
+    (0..values.len())
+        .map(|i| {
+            let low = i.saturating_sub(radius);
+            let high = (i + radius + 1).min(values.len());
+            ((sums[high] - sums[low]) / (high - low) as f64) as f32
+        })
+        .collect()
+}
+
+fn standard_deviation(values: &[f32]) -> f32 {
+    let len = values.len().max(1) as f32;
+    let mean = values.iter().sum::<f32>() / len;
+    (values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / len).sqrt()
+}
+
+/// Local maxima of the onset envelope that stand out from its usual variation.
+fn pick_onsets(envelope: &[f32], frame_rate: f32) -> Vec<usize> {
+    let threshold = ONSET_THRESHOLD * standard_deviation(envelope);
+    let peak_radius = (frame_rate * 0.03).ceil() as usize;
+    let min_gap = (frame_rate * MIN_ONSET_GAP).ceil() as usize;
+    let mut onsets: Vec<usize> = Vec::new();
+    for i in 0..envelope.len() {
+        if envelope[i] <= threshold {
+            continue;
+        }
+        let low = i.saturating_sub(peak_radius);
+        let high = (i + peak_radius + 1).min(envelope.len());
+        if envelope[low..high].iter().any(|&e| e > envelope[i]) {
+            continue;
+        }
+        if onsets.last().is_some_and(|&last| i - last < min_gap) {
+            continue;
+        }
+        onsets.push(i);
+    }
+    onsets
+}
+
+/// The flux with its local mean removed, so steady noise and sustained notes do not count
+/// as onsets.
+fn onset_envelope(flux: &[f32], frame_rate: f32) -> Vec<f32> {
+    let average = moving_average(flux, (frame_rate * 0.25) as usize);
+    flux.iter()
+        .zip(&average)
+        .map(|(&f, &a)| (f - a).max(0.0))
+        .collect()
+}
+
+/// Beat period in frames from the autocorrelation of the onset envelope, weighted
+/// towards `PREFERRED_BPM` so half- and double-time readings lose to the usual tempo.
+fn estimate_period(envelope: &[f32], frame_rate: f32) -> Option<f32> {
+    let min_lag = (60.0 * frame_rate / MAX_BPM).floor() as usize;
+    let max_lag = (60.0 * frame_rate / MIN_BPM).ceil() as usize;
+    if min_lag < 1 || envelope.len() <= max_lag * 4 {
+        return None;
+    }
+    let correlation: Vec<f32> = (0..=max_lag + 1)
+        .map(|lag| {
+            envelope
+                .iter()
+                .zip(&envelope[lag..])
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+                / (envelope.len() - lag) as f32
+        })
+        .collect();
+    if correlation[0] <= 0.0 {
+        return None;
+    }
+    let preferred_lag = 60.0 * frame_rate / PREFERRED_BPM;
+    let weight = |lag: usize| {
+        let octaves = (lag as f32 / preferred_lag).log2();
+        (-0.5 * octaves * octaves).exp()
+    };
+    let best = (min_lag..=max_lag)
+        .max_by(|&a, &b| (correlation[a] * weight(a)).total_cmp(&(correlation[b] * weight(b))))?;
+    // Too little periodicity to call it a pulse.
+    if correlation[best] < 0.1 * correlation[0] {
+        return None;
+    }
+    // Parabolic interpolation between neighbouring lags for a fractional period.
+    let (left, centre, right) = (
+        correlation[best - 1],
+        correlation[best],
+        correlation[best + 1],
+    );
+    let curvature = left - 2.0 * centre + right;
+    let shift = if curvature < 0.0 {
+        (0.5 * (left - right) / curvature).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    };
+    Some(best as f32 + shift)
+}
+
+/// Beat frames by dynamic programming (Ellis, 2007): each frame's score is its onset
+/// strength plus the best score of a previous beat, penalised for deviating from `period`.
+fn track_beats(envelope: &[f32], period: f32) -> Vec<usize> {
+    let deviation = standard_deviation(envelope);
+    if deviation <= 0.0 {
+        return Vec::new();
+    }
+    let strength: Vec<f32> = envelope.iter().map(|v| v / deviation).collect();
+
+    let min_step = (period / 2.0).round().max(1.0) as usize;
+    let max_step = (period * 2.0).round() as usize;
+    let mut score = vec![0.0f32; strength.len()];
+    let mut previous: Vec<Option<usize>> = vec![None; strength.len()];
+    for i in 0..strength.len() {
+        let mut best: Option<(f32, usize)> = None;
+        for step in min_step..=max_step.min(i) {
+            let j = i - step;
+            let deviation = (step as f32 / period).ln();
+            let candidate = score[j] - TEMPO_TIGHTNESS * deviation * deviation;
+            match best {
+                Some((s, _)) if s >= candidate => {}
+                _ => best = Some((candidate, j)),
+            }
+        }
+        score[i] = strength[i];
+        if let Some((s, j)) = best.filter(|&(s, _)| s > 0.0) {
+            score[i] += s;
+            previous[i] = Some(j);
+        }
+    }
+
+    // The last beat is the best-scoring frame within one period of the end.
+    let tail = strength.len().saturating_sub(period.ceil() as usize);
+    let Some(mut beat) = (tail..strength.len()).max_by(|&a, &b| score[a].total_cmp(&score[b]))
+    else {
+        return Vec::new();
+    };
+    let mut beats = vec![beat];
+    while let Some(j) = previous[beat] {
+        beats.push(j);
+        beat = j;
+    }
+    beats.reverse();
+    beats
+}
+
+/// The analysis in progress, cancelled when another track is requested.
+#[derive(Default)]
+pub struct AnalysisJobs {
+    current: CurrentJob,
+}
+
+/// Decodes the track and analyses it. `Ok(None)` means it was cancelled.
+fn compute(
+    path: &Path,
+    start: Option<f64>,
+    end: Option<f64>,
+    cancelled: &AtomicBool,
+) -> Result<Option<Analysis>, AppError> {
+    let decode = Decode::new(path, start, end, 1, SAMPLE_RATE);
+    let mut analyser = Analyser::new(decode.sample_rate);
+    let frames = match decode.run(cancelled, |samples| analyser.push(samples))? {
+        Some(frames) => frames,
+        None => return Ok(None),
+    };
+    let duration = frames as f64 / f64::from(decode.sample_rate);
+    Ok(Some(analyser.finish(decode.sample_rate, duration)))
+}
+
+/// Returns the audio features of a track (band energies, loudness and onset strength per
+/// frame, onset and beat times, tempo) if they are in the transcode cache already.
+/// Otherwise analyses the track in the background at low priority, caches the result and
+/// delivers it with an `analysis-ready` event, and returns `None`. A request for another
+/// track cancels the previous one. `path` is URL-encoded like for `start_transcode_job`.
+#[tauri::command]
+pub fn request_analysis(
+    path: String,
+    start: Option<f64>,
+    end: Option<f64>,
+    app: AppHandle,
+    jobs: State<'_, AnalysisJobs>,
+    cache: State<'_, TranscodeCache>,
+) -> Result<Option<Analysis>, AppError> {
+    let decoded = urlencoding::decode(&path)
+        .map_err(|e| AppError::invalid_argument(format!("Failed to decode path: {}", e)))?
+        .into_owned();
+    let key = CacheKey::new(Path::new(&decoded), start, end, ANALYSIS_VARIANT)?;
+    if let Some(analysis) = cache.get_json(&key) {
+        return Ok(Some(analysis));
+    }
+
+    let cancelled = jobs.current.start();
+    std::thread::spawn(move || {
+        let result = compute(Path::new(&decoded), start, end, &cancelled);
+        let (analysis, error) = match result {
+            Ok(Some(analysis)) => (Some(analysis), None),
+            Ok(None) => return,
+            Err(e) => (None, Some(e)),
+        };
+        if let Some(analysis) = &analysis {
+            if let Err(e) = app.state::<TranscodeCache>().insert_json(&key, analysis) {
+                log::warn!("Failed to cache analysis: {}", e);
+            }
+        }
+        let _ = app.emit(
+            ANALYSIS_READY_EVENT,
+            AnalysisReady {
+                path,
+                start,
+                end,
+                analysis,
+                error,
+            },
+        );
+    });
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mono 16-bit little-endian PCM, as the decoder delivers it.
+    fn pcm(samples: &[f32]) -> Vec<u8> {
+        samples
+            .iter()
+            .flat_map(|s| ((s * 32767.0).round() as i16).to_le_bytes())
+            .collect()
+    }
+
+    fn analyse(samples: &[f32]) -> Analysis {
+        let mut analyser = Analyser::new(SAMPLE_RATE);
+        analyser.push(&pcm(samples));
+        analyser.finish(SAMPLE_RATE, samples.len() as f64 / f64::from(SAMPLE_RATE))
+    }
+
+    fn sine(hz: f32, seconds: f32) -> Vec<f32> {
+        let len = (seconds * SAMPLE_RATE as f32) as usize;
+        (0..len)
+            .map(|i| 0.5 * (2.0 * PI * hz * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    /// A short click every beat at `bpm`, starting a quarter second in.
+    fn click_train(bpm: f32, seconds: f32) -> Vec<f32> {
+        let mut samples = vec![0.0; (seconds * SAMPLE_RATE as f32) as usize];
+        let interval = 60.0 / bpm * SAMPLE_RATE as f32;
+        let mut at = 0.25 * SAMPLE_RATE as f32;
+        while (at as usize) + 64 < samples.len() {
+            for (i, s) in samples[at as usize..at as usize + 64]
+                .iter_mut()
+                .enumerate()
+            {
+                *s = if i % 2 == 0 { 0.9 } else { -0.9 };
+            }
+            at += interval;
+        }
+        samples
+    }
+
+    #[test]
+    fn fft_finds_a_pure_tone() {
+        let len = 64;
+        let fft = Fft::new(len);
+        let mut re: Vec<f32> = (0..len)
+            .map(|i| (2.0 * PI * 5.0 * i as f32 / len as f32).cos())
+            .collect();
+        let mut im = vec![0.0; len];
+        fft.process(&mut re, &mut im);
+        let magnitude: Vec<f32> = re.iter().zip(&im).map(|(r, i)| r.hypot(*i)).collect();
+        // A cosine splits evenly between bin k and its mirror image.
+        assert!((magnitude[5] - len as f32 / 2.0).abs() < 1e-3);
+        assert!((magnitude[len - 5] - len as f32 / 2.0).abs() < 1e-3);
+        let leaked: f32 = magnitude
+            .iter()
+            .enumerate()
+            .filter(|(k, _)| *k != 5 && *k != len - 5)
+            .map(|(_, m)| m)
+            .sum();
+        assert!(leaked < 1e-3, "{}", leaked);
+    }
+
+    #[test]
+    fn sine_lands_in_its_band() {
+        let band_count = BAND_EDGES.len() - 1;
+        for (hz, band) in [(100.0, 1), (700.0, 3), (4000.0, 5)] {
+            let mut analyser = Analyser::new(SAMPLE_RATE);
+            analyser.push(&pcm(&sine(hz, 1.0)));
+            // A frame in the middle, well clear of the silent padding.
+            let frame = &analyser.bands[band_count * 25..band_count * 26];
+            let loudest = (0..band_count)
+                .max_by(|&a, &b| frame[a].total_cmp(&frame[b]))
+                .unwrap();
+            assert_eq!(loudest, band, "{} Hz: {:?}", hz, frame);
+            for (b, &db) in frame.iter().enumerate() {
+                if b != band {
+                    assert!(frame[band] - db > 30.0, "{} Hz: {:?}", hz, frame);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn click_train_gives_its_tempo() {
+        let analysis = analyse(&click_train(120.0, 12.0));
+        let bpm = analysis.bpm.expect("no tempo");
+        assert!((bpm - 120.0).abs() < 2.0, "{}", bpm);
+
+        // One onset per click, at the click.
+        assert_eq!(analysis.onsets.len(), 24, "{:?}", analysis.onsets);
+        for (i, onset) in analysis.onsets.iter().enumerate() {
+            let click = 0.25 + 0.5 * i as f32;
+            assert!((onset - click).abs() < 0.05, "{} vs {}", onset, click);
+        }
+
+        assert!(analysis.beats.len() >= 20, "{:?}", analysis.beats);
+        for pair in analysis.beats.windows(2) {
+            assert!(
+                (pair[1] - pair[0] - 0.5).abs() < 0.05,
+                "{:?}",
+                analysis.beats
+            );
+        }
+    }
+
+    #[test]
+    fn silence_has_no_onsets_or_tempo() {
+        let analysis = analyse(&vec![0.0; SAMPLE_RATE as usize * 5]);
+        assert!(analysis.onsets.is_empty());
+        assert!(analysis.beats.is_empty());
+        assert_eq!(analysis.bpm, None);
+        let loudness = general_purpose::STANDARD
+            .decode(&analysis.loudness_base64)
+            .unwrap();
+        assert_eq!(loudness.len(), analysis.frames as usize);
+        assert!(loudness.iter().all(|&l| l == 0));
+    }
+}
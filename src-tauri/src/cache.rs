@@ -0,0 +1,545 @@
+use crate::error::AppError;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Budget used until the user configures one: 2 GiB, a few hundred tracks of CD audio.
+pub const DEFAULT_CACHE_LIMIT: u64 = 2 * 1024 * 1024 * 1024;
+/// Name of the cache directory inside the app cache directory.
+pub const CACHE_DIR: &str = "transcodes";
+/// File name of the persisted index inside the cache directory.
+const INDEX_FILE: &str = "index.json";
+/// Bump when the key derivation or output format changes, so stale entries are dropped.
+const CACHE_VERSION: u32 = 2;
+/// Prefix of the per-play WAVs older versions wrote straight into the temp directory.
+const LEGACY_PREFIX: &str = "imp_cache_";
+/// How often cache hits write their new `last_used` times back to disk. Changes that
+/// add or remove entries are saved immediately.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    /// File name inside the cache directory.
+    file: String,
+    /// The full key the hash was derived from, checked on lookup to rule out collisions.
+    source: String,
+    size: u64,
+    /// Milliseconds since the Unix epoch; the least recently used entry is evicted first.
+    last_used: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct CacheIndex {
+    version: u32,
+    entries: HashMap<String, CacheEntry>,
+}
+
+/// Identifies one transcode: the source file as it currently is on disk, the time range
+/// and the output settings.
+#[derive(Clone)]
+pub struct CacheKey {
+    hash: String,
+    source: String,
+}
+
+/// 64-bit FNV-1a. `DefaultHasher` is not guaranteed to be stable between Rust releases,
+/// which would silently invalidate the cache on every toolchain update.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+impl CacheKey {
+    /// Builds the key for transcoding `path`. Modifying the file changes its size or mtime
+    /// and therefore the key, so edited files are never served from a stale entry.
+    /// `variant` describes the output (format, channels, ...).
+    pub fn new(
+        path: &Path,
+        start: Option<f64>,
+        end: Option<f64>,
+        variant: &str,
+    ) -> Result<Self, AppError> {
+        let meta = std::fs::metadata(path).map_err(|e| AppError::io("read", Some(path), e))?;
+        let mtime = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let source = format!(
+            "{}|{}|{}|{:?}|{:?}|{}",
+            path.display(),
+            meta.len(),
+            mtime,
+            start,
+            end,
+            variant
+        );
+        Ok(CacheKey {
+            hash: format!("{:016x}", fnv1a(source.as_bytes())),
+            source,
+        })
+    }
+}
+
+struct Inner {
+    index: CacheIndex,
+    limit: u64,
+    /// Entries open streams are reading from, with the number of streams per entry.
+    pinned: HashMap<String, usize>,
+    /// When the index was last written; see `SAVE_INTERVAL`.
+    saved_at: Instant,
+}
+
+/// Tauri-managed persistent cache of transcoded audio, bounded by a byte budget with LRU
+/// eviction.
+pub struct TranscodeCache {
+    dir: PathBuf,
+    inner: Mutex<Inner>,
+    staged: AtomicU64,
+}
+
+impl TranscodeCache {
+    /// Opens the cache in `dir`, dropping index entries whose files are gone and deleting
+    /// files the index does not know (interrupted transcodes, older versions), as well as
+    /// any `imp_cache_*` files leaked into the temp directory.
+    pub fn open(dir: PathBuf, limit: u64) -> Self {
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            log::warn!("Failed to create transcode cache {}: {}", dir.display(), e);
+        }
+        let mut index = match std::fs::read(dir.join(INDEX_FILE)) {
+            Ok(bytes) => match serde_json::from_slice::<CacheIndex>(&bytes) {
+                Ok(index) if index.version == CACHE_VERSION => index,
+                Ok(_) => CacheIndex::default(),
+                Err(e) => {
+                    log::warn!("Failed to parse transcode cache index: {}", e);
+                    CacheIndex::default()
+                }
+            },
+            Err(_) => CacheIndex::default(),
+        };
+        index.version = CACHE_VERSION;
+        index.entries.retain(|_, e| dir.join(&e.file).is_file());
+
+        let known: Vec<&str> = index.entries.values().map(|e| e.file.as_str()).collect();
+        if let Ok(read_dir) = std::fs::read_dir(&dir) {
+            for entry in read_dir.flatten() {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                if name != INDEX_FILE && !known.contains(&name.as_ref()) {
+                    let _ = std::fs::remove_file(entry.path());
+                }
+            }
+        }
+        sweep_legacy_files();
+
+        let cache = TranscodeCache {
+            dir,
+            inner: Mutex::new(Inner {
+                index,
+                limit,
+                pinned: HashMap::new(),
+                saved_at: Instant::now(),
+            }),
+            staged: AtomicU64::new(0),
+        };
+        let mut inner = cache.inner.lock().unwrap();
+        cache.evict(&mut inner, None);
+        cache.save(&mut inner);
+        drop(inner);
+        cache
+    }
+
+    /// Returns the cached output for `key` and marks it as recently used. The new
+    /// `last_used` is written out with the next change, or once `SAVE_INTERVAL` has passed.
+    pub fn get(&self, key: &CacheKey) -> Option<PathBuf> {
+        let mut inner = self.inner.lock().unwrap();
+        let entry = inner.index.entries.get_mut(&key.hash)?;
+        let path = self.dir.join(&entry.file);
+        if entry.source != key.source || !path.is_file() {
+            inner.index.entries.remove(&key.hash);
+            return None;
+        }
+        entry.last_used = now_millis();
+        if inner.saved_at.elapsed() >= SAVE_INTERVAL {
+            self.save(&mut inner);
+        }
+        Some(path)
+    }
+
+    /// A new file to write the output for `key` to before handing it to `insert`. Each call
+    /// returns a different path, so concurrent transcodes of the same key do not collide.
+    /// Files left behind by transcodes that never complete are removed on the next start.
+    pub fn staging_path(&self, key: &CacheKey, extension: &str) -> PathBuf {
+        let n = self.staged.fetch_add(1, Ordering::Relaxed);
+        self.dir
+            .join(format!("{}.partial{}.{}", key.hash, n, extension))
+    }
+
+    /// Moves a finished transcode from `staged` into the cache and evicts older entries
+    /// until the cache fits its budget again.
+    pub fn insert(&self, key: &CacheKey, staged: &Path) -> Result<PathBuf, AppError> {
+        let extension = staged
+            .extension()
+            .map(|e| e.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let file = format!("{}.{}", key.hash, extension);
+        let path = self.dir.join(&file);
+        std::fs::rename(staged, &path)
+            .map_err(|e| AppError::io("store transcode", Some(&path), e))?;
+        let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.index.entries.insert(
+            key.hash.clone(),
+            CacheEntry {
+                file,
+                source: key.source.clone(),
+                size,
+                last_used: now_millis(),
+            },
+        );
+        self.evict(&mut inner, Some(&key.hash));
+        self.save(&mut inner);
+        Ok(path)
+    }
+
+    /// Returns a value cached with `insert_json` (analysis results rather than audio).
+    pub fn get_json<T: DeserializeOwned>(&self, key: &CacheKey) -> Option<T> {
+        let bytes = std::fs::read(self.get(key)?).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    /// Stores `value` as JSON under `key`, subject to the same budget as transcodes.
+    pub fn insert_json<T: Serialize>(&self, key: &CacheKey, value: &T) -> Result<(), AppError> {
+        let data = serde_json::to_vec(value).map_err(|e| AppError::Io {
+            action: "serialize cache entry".to_string(),
+            path: None,
+            message: e.to_string(),
+        })?;
+        let staged = self.staging_path(key, "json");
+        let stored = std::fs::write(&staged, data)
+            .map_err(|e| AppError::io("write cache entry", Some(&staged), e))
+            .and_then(|_| self.insert(key, &staged));
+        if stored.is_err() {
+            let _ = std::fs::remove_file(&staged);
+        }
+        stored.map(|_| ())
+    }
+
+    /// Protects the entry for `key` from eviction until the matching `unpin`, while a
+    /// stream is playing from it. Pins nest, so each `pin` needs its own `unpin`.
+    pub fn pin(&self, key: &CacheKey) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.pinned.entry(key.hash.clone()).or_default() += 1;
+    }
+
+    /// Releases a pin taken with `pin`.
+    pub fn unpin(&self, key: &CacheKey) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(count) = inner.pinned.get_mut(&key.hash) {
+            *count -= 1;
+            if *count == 0 {
+                inner.pinned.remove(&key.hash);
+            }
+        }
+    }
+
+    /// Changes the byte budget, evicting immediately if the cache is now over it.
+    pub fn set_limit(&self, limit: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.limit = limit;
+        self.evict(&mut inner, None);
+        self.save(&mut inner);
+    }
+
+    /// Whether `path` is a file managed by the cache rather than a one-off temp file.
+    pub fn contains(&self, path: &Path) -> bool {
+        path.parent() == Some(self.dir.as_path())
+    }
+
+    /// Deletes least recently used entries until the total size fits the budget. `keep`
+    /// (the entry just written, which is about to be played) and pinned entries are never
+    /// evicted.
+    fn evict(&self, inner: &mut Inner, keep: Option<&str>) {
+        let mut total: u64 = inner.index.entries.values().map(|e| e.size).sum();
+        if total <= inner.limit {
+            return;
+        }
+        let mut candidates: Vec<(String, u64)> = inner
+            .index
+            .entries
+            .iter()
+            .filter(|(hash, _)| Some(hash.as_str()) != keep && !inner.pinned.contains_key(*hash))
+            .map(|(hash, e)| (hash.clone(), e.last_used))
+            .collect();
+        candidates.sort_by_key(|(_, last_used)| *last_used);
+
+        for (hash, _) in candidates {
+            if total <= inner.limit {
+                break;
+            }
+            let entry = &inner.index.entries[&hash];
+            match std::fs::remove_file(self.dir.join(&entry.file)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                // Most likely still open for playback on Windows; try again next time.
+                Err(e) => {
+                    log::warn!("Failed to evict {}: {}", entry.file, e);
+                    continue;
+                }
+            }
+            total -= entry.size;
+            inner.index.entries.remove(&hash);
+        }
+    }
+
+    fn save(&self, inner: &mut Inner) {
+        inner.saved_at = Instant::now();
+        let file = self.dir.join(INDEX_FILE);
+        let tmp = file.with_extension("json.tmp");
+        let result = serde_json::to_vec(&inner.index)
+            .map_err(|e| e.to_string())
+            .and_then(|data| std::fs::write(&tmp, data).map_err(|e| e.to_string()))
+            .and_then(|_| std::fs::rename(&tmp, &file).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            log::warn!("Failed to save transcode cache index: {}", e);
+        }
+    }
+}
+
+/// Removes `imp_cache_*.wav` files left in the temp directory by crashed sessions of
+/// older versions.
+fn sweep_legacy_files() {
+    let Ok(read_dir) = std::fs::read_dir(std::env::temp_dir()) else {
+        return;
+    };
+    for entry in read_dir.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(LEGACY_PREFIX) && name.ends_with(".wav") {
+            match std::fs::remove_file(entry.path()) {
+                Ok(()) => log::info!("Removed leftover {}", name),
+                Err(e) => log::warn!("Failed to remove leftover {}: {}", name, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory holding the cache and the source files keys are built from.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("cache_{}_{}", std::process::id(), name));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(dir.join("sources")).unwrap();
+            Scratch(dir)
+        }
+
+        fn cache_dir(&self) -> PathBuf {
+            self.0.join(CACHE_DIR)
+        }
+
+        fn open(&self, limit: u64) -> TranscodeCache {
+            TranscodeCache::open(self.cache_dir(), limit)
+        }
+
+        fn source(&self, name: &str, contents: &str) -> PathBuf {
+            let path = self.0.join("sources").join(name);
+            std::fs::write(&path, contents).unwrap();
+            path
+        }
+
+        fn key(&self, name: &str) -> CacheKey {
+            let path = self.0.join("sources").join(name);
+            if !path.exists() {
+                std::fs::write(&path, name).unwrap();
+            }
+            CacheKey::new(&path, None, None, "wav").unwrap()
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Stores `len` bytes under `key`, as a finished transcode would.
+    fn store(cache: &TranscodeCache, key: &CacheKey, len: usize) {
+        let staged = cache.staging_path(key, "wav");
+        std::fs::write(&staged, vec![0u8; len]).unwrap();
+        cache.insert(key, &staged).unwrap();
+    }
+
+    fn set_last_used(cache: &TranscodeCache, key: &CacheKey, last_used: u64) {
+        let mut inner = cache.inner.lock().unwrap();
+        inner.index.entries.get_mut(&key.hash).unwrap().last_used = last_used;
+    }
+
+    fn cached(cache: &TranscodeCache, key: &CacheKey) -> bool {
+        cache
+            .inner
+            .lock()
+            .unwrap()
+            .index
+            .entries
+            .contains_key(&key.hash)
+    }
+
+    #[test]
+    fn fnv_matches_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn key_follows_the_file_and_options() {
+        let scratch = Scratch::new("key");
+        let path = scratch.source("track.flac", "original");
+        let key = CacheKey::new(&path, None, None, "wav").unwrap();
+        assert_eq!(
+            key.hash,
+            CacheKey::new(&path, None, None, "wav").unwrap().hash
+        );
+        assert_eq!(key.hash.len(), 16);
+        assert_ne!(
+            key.hash,
+            CacheKey::new(&path, Some(1.0), None, "wav").unwrap().hash
+        );
+        assert_ne!(
+            key.hash,
+            CacheKey::new(&path, None, None, "flac").unwrap().hash
+        );
+
+        scratch.source("track.flac", "longer contents");
+        let resized = CacheKey::new(&path, None, None, "wav").unwrap();
+        assert_ne!(key.hash, resized.hash);
+
+        // Same size, later modification time.
+        std::thread::sleep(Duration::from_millis(20));
+        scratch.source("track.flac", "LONGER CONTENTS");
+        let touched = CacheKey::new(&path, None, None, "wav").unwrap();
+        assert_ne!(resized.hash, touched.hash);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let scratch = Scratch::new("lru");
+        let cache = scratch.open(25);
+        let (a, b, c) = (scratch.key("a"), scratch.key("b"), scratch.key("c"));
+        store(&cache, &a, 10);
+        store(&cache, &b, 10);
+        set_last_used(&cache, &a, 2);
+        set_last_used(&cache, &b, 1);
+        store(&cache, &c, 10);
+        assert!(cached(&cache, &a));
+        assert!(!cached(&cache, &b));
+        assert!(cached(&cache, &c));
+        assert!(cache.get(&b).is_none());
+        assert!(cache.get(&a).unwrap().is_file());
+    }
+
+    #[test]
+    fn keeps_the_entry_just_inserted() {
+        let scratch = Scratch::new("keep");
+        let cache = scratch.open(5);
+        let (a, b) = (scratch.key("a"), scratch.key("b"));
+        store(&cache, &a, 10);
+        assert!(cached(&cache, &a));
+        store(&cache, &b, 10);
+        assert!(!cached(&cache, &a));
+        assert!(cached(&cache, &b));
+    }
+
+    #[test]
+    fn pinned_entries_survive_eviction() {
+        let scratch = Scratch::new("pin");
+        let cache = scratch.open(15);
+        let (a, b, c) = (scratch.key("a"), scratch.key("b"), scratch.key("c"));
+        store(&cache, &a, 10);
+        cache.pin(&a);
+        store(&cache, &b, 10);
+        assert!(cached(&cache, &a));
+        cache.unpin(&a);
+        store(&cache, &c, 10);
+        assert!(!cached(&cache, &a));
+    }
+
+    #[test]
+    fn shrinking_the_limit_evicts() {
+        let scratch = Scratch::new("limit");
+        let cache = scratch.open(100);
+        let (a, b) = (scratch.key("a"), scratch.key("b"));
+        store(&cache, &a, 10);
+        store(&cache, &b, 10);
+        set_last_used(&cache, &a, 1);
+        set_last_used(&cache, &b, 2);
+        cache.set_limit(10);
+        assert!(!cached(&cache, &a));
+        assert!(cached(&cache, &b));
+        cache.set_limit(0);
+        assert!(!cached(&cache, &b));
+        let left: Vec<_> = std::fs::read_dir(scratch.cache_dir())
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name())
+            .collect();
+        assert_eq!(left, [INDEX_FILE]);
+    }
+
+    #[test]
+    fn hits_do_not_rewrite_the_index() {
+        let scratch = Scratch::new("lazy");
+        let cache = scratch.open(100);
+        let a = scratch.key("a");
+        store(&cache, &a, 10);
+        let index = scratch.cache_dir().join(INDEX_FILE);
+        let saved = std::fs::read(&index).unwrap();
+        set_last_used(&cache, &a, 1);
+        assert!(cache.get(&a).is_some());
+        assert_eq!(std::fs::read(&index).unwrap(), saved);
+        assert!(cache.inner.lock().unwrap().index.entries[&a.hash].last_used > 1);
+    }
+
+    #[test]
+    fn startup_removes_unknown_and_partial_files() {
+        let scratch = Scratch::new("sweep");
+        let (a, b) = (scratch.key("a"), scratch.key("b"));
+        {
+            let cache = scratch.open(100);
+            store(&cache, &a, 10);
+            store(&cache, &b, 10);
+        }
+        let dir = scratch.cache_dir();
+        let partial = dir.join(format!("{}.partial7.wav", a.hash));
+        std::fs::write(&partial, "partial").unwrap();
+        std::fs::write(dir.join("stray.bin"), "stray").unwrap();
+        std::fs::remove_file(dir.join(format!("{}.wav", b.hash))).unwrap();
+
+        let cache = scratch.open(100);
+        assert!(!partial.exists());
+        assert!(!dir.join("stray.bin").exists());
+        assert!(cache.get(&a).is_some());
+        assert!(!cached(&cache, &b));
+    }
+}
@@ -0,0 +1,413 @@
+use crate::formats::DEFAULT_EXTENSIONS;
+use crate::tags::Tags;
+use crate::text;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// CUE timestamps are `mm:ss:ff` with 75 frames per second (CD sectors).
+const FRAMES_PER_SECOND: f64 = 75.0;
+
+#[derive(Debug, Default, Clone)]
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub genre: Option<String>,
+    pub date: Option<String>,
+    pub files: Vec<CueFile>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CueFile {
+    /// File name exactly as written in the sheet.
+    pub name: String,
+    pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// `INDEX 00` (start of the pregap), if present.
+    pub pregap: Option<f64>,
+    /// `INDEX 01` in seconds; where playback of the track starts.
+    pub start: f64,
+}
+
+/// One track of a sheet, resolved against the audio file it is cut from.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualTrack {
+    pub audio_path: PathBuf,
+    pub cue_path: PathBuf,
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub genre: Option<String>,
+    pub date: Option<String>,
+    /// Start of the pregap (`INDEX 00`) within the audio file, if the sheet has one.
+    pub pregap: Option<f64>,
+    pub start: f64,
+    /// `None` for the last track of a file, which runs to the end.
+    pub end: Option<f64>,
+}
+
+impl VirtualTrack {
+    /// Display name used where the filename would be shown for a normal file.
+    pub fn display_name(&self) -> String {
+        match &self.title {
+            Some(title) => format!("{:02}. {}", self.number, title),
+            None => {
+                let stem = self
+                    .audio_path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy();
+                format!("{} - Track {:02}", stem, self.number)
+            }
+        }
+    }
+
+    /// The sheet's information in the normalised tag model.
+    pub fn tags(&self) -> Tags {
+        Tags {
+            title: self.title.clone(),
+            artist: self.performer.clone(),
+            album: self.album.clone(),
+            album_artist: self.album_artist.clone(),
+            track_number: Some(self.number),
+            year: self.date.as_deref().and_then(crate::tags::parse_year),
+            date: self.date.clone(),
+            genre: self.genre.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Splits the arguments of a cue command, honouring double quotes.
+fn arguments(rest: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = rest.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let arg: String = chars.by_ref().take_while(|&c| c != '"').collect();
+            args.push(arg);
+        } else {
+            let mut arg = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                arg.push(c);
+                chars.next();
+            }
+            args.push(arg);
+        }
+    }
+    args
+}
+
+fn parse_time(value: &str) -> Option<f64> {
+    let mut parts = value.split(':').map(|p| p.trim().parse::<u32>());
+    let minutes = parts.next()?.ok()?;
+    let seconds = parts.next()?.ok()?;
+    let frames = parts.next().unwrap_or(Ok(0)).ok()?;
+    Some(minutes as f64 * 60.0 + seconds as f64 + frames as f64 / FRAMES_PER_SECOND)
+}
+
+fn non_empty(value: Option<&String>) -> Option<String> {
+    value
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_string())
+}
+
+/// A `TRACK` block while it is being read; kept only once it has an `INDEX 01`.
+#[derive(Default)]
+struct PendingTrack {
+    number: u32,
+    title: Option<String>,
+    performer: Option<String>,
+    pregap: Option<f64>,
+    start: Option<f64>,
+}
+
+impl PendingTrack {
+    fn finish(self, sheet: &mut CueSheet) {
+        let (Some(start), Some(file)) = (self.start, sheet.files.last_mut()) else {
+            return;
+        };
+        file.tracks.push(CueTrack {
+            number: self.number,
+            title: self.title,
+            performer: self.performer,
+            pregap: self.pregap,
+            start,
+        });
+    }
+}
+
+pub fn parse(text: &str) -> CueSheet {
+    let mut sheet = CueSheet::default();
+    let mut pending: Option<PendingTrack> = None;
+
+    for line in text.lines() {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args = arguments(rest);
+        match command.to_ascii_uppercase().as_str() {
+            "FILE" => {
+                // A track whose INDEX 01 only appears after this FILE line continues into
+                // the new file; its INDEX 00 pointed into the previous one.
+                match pending.as_mut() {
+                    Some(track) if track.start.is_none() => track.pregap = None,
+                    _ => {
+                        if let Some(track) = pending.take() {
+                            track.finish(&mut sheet);
+                        }
+                    }
+                }
+                if let Some(name) = args.first() {
+                    sheet.files.push(CueFile {
+                        name: name.clone(),
+                        tracks: Vec::new(),
+                    });
+                }
+            }
+            "TRACK" => {
+                if let Some(track) = pending.take() {
+                    track.finish(&mut sheet);
+                }
+                if let Some(number) = args.first().and_then(|n| n.parse().ok()) {
+                    pending = Some(PendingTrack {
+                        number,
+                        ..Default::default()
+                    });
+                }
+            }
+            "TITLE" => match pending.as_mut() {
+                Some(track) => track.title = non_empty(args.first()),
+                None => sheet.title = non_empty(args.first()),
+            },
+            "PERFORMER" => match pending.as_mut() {
+                Some(track) => track.performer = non_empty(args.first()),
+                None => sheet.performer = non_empty(args.first()),
+            },
+            "INDEX" => {
+                if let (Some(track), [index, time, ..]) = (pending.as_mut(), args.as_slice()) {
+                    match index.parse::<u32>() {
+                        Ok(0) => track.pregap = parse_time(time),
+                        Ok(1) => track.start = parse_time(time),
+                        _ => {}
+                    }
+                }
+            }
+            "REM" => {
+                let key = args.first().map(|k| k.to_ascii_uppercase());
+                let value = non_empty(Some(&args.get(1..).unwrap_or_default().join(" ")));
+                match key.as_deref() {
+                    Some("GENRE") => sheet.genre = value,
+                    Some("DATE") => sheet.date = value,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(track) = pending.take() {
+        track.finish(&mut sheet);
+    }
+    sheet
+}
+
+pub fn read(path: &Path) -> Option<CueSheet> {
+    let bytes = std::fs::read(path).ok()?;
+    Some(parse(&text::decode(&bytes)))
+}
+
+fn is_cue(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("cue"))
+}
+
+/// Finds the audio file a `FILE` line refers to. Rippers often write the sheet before
+/// compressing, so `Album.wav` in the sheet may really be `Album.flac` on disk.
+fn resolve_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    let direct = dir.join(name);
+    if direct.is_file() {
+        return Some(direct);
+    }
+    let wanted = Path::new(name);
+    let wanted_name = wanted.file_name()?.to_string_lossy().to_lowercase();
+    let wanted_stem = wanted.file_stem()?.to_string_lossy().to_lowercase();
+
+    let mut same_stem = None;
+    for entry in std::fs::read_dir(dir).ok()?.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+        let file_name = entry.file_name().to_string_lossy().to_lowercase();
+        if file_name == wanted_name {
+            return Some(path);
+        }
+        let stem = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        let ext = path
+            .extension()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        if stem == wanted_stem && DEFAULT_EXTENSIONS.contains(&ext.as_str()) {
+            same_stem = Some(path);
+        }
+    }
+    same_stem
+}
+
+/// Expands a cue sheet into virtual tracks for every referenced file that exists.
+pub fn expand(cue_path: &Path) -> Vec<VirtualTrack> {
+    let Some(sheet) = read(cue_path) else {
+        return Vec::new();
+    };
+    let dir = cue_path.parent().unwrap_or(Path::new("."));
+    let mut tracks = Vec::new();
+
+    for file in &sheet.files {
+        let Some(audio_path) = resolve_file(dir, &file.name) else {
+            log::warn!(
+                "{}: referenced file {} not found",
+                cue_path.display(),
+                file.name
+            );
+            continue;
+        };
+        for (i, track) in file.tracks.iter().enumerate() {
+            tracks.push(VirtualTrack {
+                audio_path: audio_path.clone(),
+                cue_path: cue_path.to_path_buf(),
+                number: track.number,
+                title: track.title.clone(),
+                performer: track.performer.clone().or_else(|| sheet.performer.clone()),
+                album: sheet.title.clone(),
+                album_artist: sheet.performer.clone(),
+                genre: sheet.genre.clone(),
+                date: sheet.date.clone(),
+                pregap: track.pregap,
+                start: track.start,
+                end: file.tracks.get(i + 1).map(|next| next.start),
+            });
+        }
+    }
+    tracks
+}
+
+/// Cue sheets directly inside `dir`.
+pub fn sheets_in_dir(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut sheets: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && is_cue(p))
+        .collect();
+    sheets.sort();
+    sheets
+}
+
+/// Looks for a sheet next to `audio_path` with a track starting at `start` seconds.
+pub fn find_track(audio_path: &Path, start: f64) -> Option<VirtualTrack> {
+    let dir = audio_path.parent()?;
+    sheets_in_dir(dir)
+        .iter()
+        .flat_map(|sheet| expand(sheet))
+        .find(|t| t.audio_path == audio_path && (t.start - start).abs() < 0.5 / FRAMES_PER_SECOND)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEET: &str = r#"REM GENRE "Jazz"
+REM DATE 1959
+PERFORMER "Miles Davis"
+TITLE "Kind of Blue"
+FILE "Kind of Blue.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "So What"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Freddie Freeloader"
+    PERFORMER "Miles Davis Sextet"
+    INDEX 00 09:20:50
+    INDEX 01 09:22:00
+"#;
+
+    #[test]
+    fn reads_sheet_and_track_fields() {
+        let sheet = parse(SHEET);
+        assert_eq!(sheet.title.as_deref(), Some("Kind of Blue"));
+        assert_eq!(sheet.performer.as_deref(), Some("Miles Davis"));
+        assert_eq!(sheet.genre.as_deref(), Some("Jazz"));
+        assert_eq!(sheet.date.as_deref(), Some("1959"));
+        assert_eq!(sheet.files.len(), 1);
+        assert_eq!(sheet.files[0].name, "Kind of Blue.wav");
+
+        let tracks = &sheet.files[0].tracks;
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].number, 1);
+        assert_eq!(tracks[0].title.as_deref(), Some("So What"));
+        assert_eq!(tracks[0].performer, None);
+        assert_eq!(tracks[0].start, 0.0);
+        assert_eq!(tracks[1].performer.as_deref(), Some("Miles Davis Sextet"));
+        assert_eq!(tracks[1].start, 562.0);
+    }
+
+    #[test]
+    fn index_frames_are_75_per_second() {
+        let sheet = parse(SHEET);
+        let pregap = sheet.files[0].tracks[1].pregap.unwrap();
+        assert!((pregap - (560.0 + 50.0 / 75.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn track_without_index_01_is_dropped() {
+        let sheet = parse("FILE \"a.flac\" WAVE\nTRACK 01 AUDIO\nTITLE \"Broken\"\nTRACK 02 AUDIO\nINDEX 01 01:00:00\n");
+        let tracks = &sheet.files[0].tracks;
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].number, 2);
+        assert_eq!(tracks[0].start, 60.0);
+    }
+
+    #[test]
+    fn track_spanning_files_drops_pregap_from_previous_file() {
+        let sheet = parse(
+            "FILE \"1.wav\" WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00\nTRACK 02 AUDIO\nINDEX 00 04:00:00\nFILE \"2.wav\" WAVE\nINDEX 01 00:00:00\n",
+        );
+        assert_eq!(sheet.files.len(), 2);
+        assert_eq!(sheet.files[0].tracks.len(), 1);
+        let track = &sheet.files[1].tracks[0];
+        assert_eq!(track.number, 2);
+        assert_eq!(track.pregap, None);
+        assert_eq!(track.start, 0.0);
+    }
+
+    #[test]
+    fn unquoted_arguments_and_lowercase_commands() {
+        let sheet = parse("performer Artist\nfile album.flac WAVE\ntrack 1 audio\ntitle Intro\nindex 1 00:01:00\n");
+        assert_eq!(sheet.performer.as_deref(), Some("Artist"));
+        assert_eq!(sheet.files[0].name, "album.flac");
+        assert_eq!(sheet.files[0].tracks[0].title.as_deref(), Some("Intro"));
+        assert_eq!(sheet.files[0].tracks[0].start, 1.0);
+    }
+}
@@ -0,0 +1,227 @@
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::process::Output;
+
+/// Stable machine-readable error codes. The frontend switches on these, so existing values
+/// must not be renamed.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCode {
+    FfmpegMissing,
+    FfmpegDownloadFailed,
+    FileNotFound,
+    PermissionDenied,
+    UnsupportedFormat,
+    TranscodeFailed,
+    InvalidArgument,
+    InvalidPlaylist,
+    FontNotFound,
+    Io,
+}
+
+/// Error returned by every Tauri command. It reaches the frontend as
+/// `{ code, message, path?, exitStatus?, stderr? }`.
+#[derive(Clone, Debug)]
+pub enum AppError {
+    /// No usable ffmpeg/ffprobe; `details` lists why each candidate was rejected.
+    FfmpegMissing {
+        details: String,
+    },
+    FfmpegDownloadFailed {
+        message: String,
+    },
+    FileNotFound {
+        path: String,
+    },
+    PermissionDenied {
+        path: String,
+    },
+    /// ffmpeg could not decode `path` (unknown container, missing decoder, corrupt data).
+    UnsupportedFormat {
+        path: String,
+        stderr: String,
+    },
+    /// ffmpeg ran but failed for another reason.
+    TranscodeFailed {
+        path: String,
+        exit_status: Option<i32>,
+        stderr: String,
+    },
+    InvalidArgument {
+        message: String,
+    },
+    InvalidPlaylist {
+        path: String,
+        message: String,
+    },
+    FontNotFound {
+        name: String,
+    },
+    /// Any other I/O failure; `action` says what was being attempted.
+    Io {
+        action: String,
+        path: Option<String>,
+        message: String,
+    },
+}
+
+/// ffmpeg messages that mean the input itself cannot be decoded, as opposed to the
+/// conversion failing.
+const UNSUPPORTED_MARKERS: &[&str] = &[
+    "Invalid data found when processing input",
+    "could not find codec parameters",
+    "Decoder not found",
+    "Unknown decoder",
+    "not currently supported",
+    "Unsupported codec",
+    "does not contain any stream",
+];
+
+impl AppError {
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        AppError::InvalidArgument {
+            message: message.into(),
+        }
+    }
+
+    /// Classifies an I/O error from attempting `action` on `path`.
+    pub fn io(action: &str, path: Option<&Path>, error: io::Error) -> Self {
+        let path_string = path.map(|p| p.display().to_string());
+        match (error.kind(), path_string) {
+            (io::ErrorKind::NotFound, Some(path)) => AppError::FileNotFound { path },
+            (io::ErrorKind::PermissionDenied, Some(path)) => AppError::PermissionDenied { path },
+            (_, path) => AppError::Io {
+                action: action.to_string(),
+                path,
+                message: error.to_string(),
+            },
+        }
+    }
+
+    /// Turns the output of an ffmpeg run on `input` that did not succeed into an error.
+    pub fn from_ffmpeg(input: &Path, output: &Output) -> Self {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        let path = input.display().to_string();
+        if !input.exists() {
+            AppError::FileNotFound { path }
+        } else if UNSUPPORTED_MARKERS.iter().any(|m| stderr.contains(m)) {
+            AppError::UnsupportedFormat { path, stderr }
+        } else {
+            AppError::TranscodeFailed {
+                path,
+                exit_status: output.status.code(),
+                stderr,
+            }
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            AppError::FfmpegMissing { .. } => ErrorCode::FfmpegMissing,
+            AppError::FfmpegDownloadFailed { .. } => ErrorCode::FfmpegDownloadFailed,
+            AppError::FileNotFound { .. } => ErrorCode::FileNotFound,
+            AppError::PermissionDenied { .. } => ErrorCode::PermissionDenied,
+            AppError::UnsupportedFormat { .. } => ErrorCode::UnsupportedFormat,
+            AppError::TranscodeFailed { .. } => ErrorCode::TranscodeFailed,
+            AppError::InvalidArgument { .. } => ErrorCode::InvalidArgument,
+            AppError::InvalidPlaylist { .. } => ErrorCode::InvalidPlaylist,
+            AppError::FontNotFound { .. } => ErrorCode::FontNotFound,
+            AppError::Io { .. } => ErrorCode::Io,
+        }
+    }
+
+    fn path(&self) -> Option<&str> {
+        match self {
+            AppError::FileNotFound { path }
+            | AppError::PermissionDenied { path }
+            | AppError::UnsupportedFormat { path, .. }
+            | AppError::TranscodeFailed { path, .. }
+            | AppError::InvalidPlaylist { path, .. } => Some(path),
+            AppError::Io { path, .. } => path.as_deref(),
+            _ => None,
+        }
+    }
+
+    fn stderr(&self) -> Option<&str> {
+        match self {
+            AppError::UnsupportedFormat { stderr, .. }
+            | AppError::TranscodeFailed { stderr, .. } => {
+                Some(stderr).filter(|s| !s.is_empty()).map(String::as_str)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::FfmpegMissing { details } => {
+                write!(f, "ffmpeg is not available: {}", details)
+            }
+            AppError::FfmpegDownloadFailed { message } => {
+                write!(f, "Failed to download ffmpeg: {}", message)
+            }
+            AppError::FileNotFound { path } => write!(f, "File not found: {}", path),
+            AppError::PermissionDenied { path } => write!(f, "Permission denied: {}", path),
+            AppError::UnsupportedFormat { path, .. } => {
+                write!(f, "Unsupported or corrupt audio file: {}", path)
+            }
+            AppError::TranscodeFailed {
+                path, exit_status, ..
+            } => match exit_status {
+                Some(code) => write!(f, "ffmpeg failed on {} (exit status {})", path, code),
+                None => write!(f, "ffmpeg failed on {}", path),
+            },
+            AppError::InvalidArgument { message } => f.write_str(message),
+            AppError::InvalidPlaylist { path, message } => {
+                write!(f, "Invalid playlist {}: {}", path, message)
+            }
+            AppError::FontNotFound { name } => write!(f, "Font family '{}' not found", name),
+            AppError::Io {
+                action,
+                path: Some(path),
+                message,
+            } => write!(f, "Failed to {} {}: {}", action, path, message),
+            AppError::Io {
+                action,
+                path: None,
+                message,
+            } => write!(f, "Failed to {}: {}", action, message),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload<'a> {
+    code: ErrorCode,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_status: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stderr: Option<&'a str>,
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let exit_status = match self {
+            AppError::TranscodeFailed { exit_status, .. } => *exit_status,
+            _ => None,
+        };
+        ErrorPayload {
+            code: self.code(),
+            message: self.to_string(),
+            path: self.path(),
+            exit_status,
+            stderr: self.stderr(),
+        }
+        .serialize(serializer)
+    }
+}
@@ -0,0 +1,382 @@
+use crate::error::AppError;
+use crate::settings::Settings;
+use serde::Serialize;
+use std::io::Read;
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+pub const FFMPEG_DOWNLOAD_PROGRESS_EVENT: &str = "ffmpeg-download-progress";
+pub const FFMPEG_DOWNLOAD_FINISHED_EVENT: &str = "ffmpeg-download-finished";
+
+/// Oldest ffmpeg major version we accept; older builds lack options we rely on.
+const MIN_MAJOR_VERSION: u32 = 4;
+/// How often the size of a download in progress is reported.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+/// How much of a run's stderr is kept: error messages and the `ebur128` summary come at
+/// the end.
+const STDERR_TAIL_LEN: usize = 64 * 1024;
+
+/// Where a working ffmpeg was found, in the order the sources are tried.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ToolSource {
+    /// Paths from the settings.
+    Configured,
+    /// Next to the app executable, either bundled or downloaded there.
+    Sidecar,
+    /// Found on the system `PATH`.
+    System,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FfmpegTools {
+    pub ffmpeg: PathBuf,
+    pub ffprobe: PathBuf,
+    /// Version string as printed by `ffmpeg -version`, e.g. "7.0.1" or "N-113402-g...".
+    pub version: String,
+    pub source: ToolSource,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FfmpegStatus {
+    pub tools: Option<FfmpegTools>,
+    /// Why each candidate was rejected, when none was usable.
+    pub error: Option<AppError>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct DownloadProgress {
+    /// "resolving", "downloading", "unpacking" or "verifying".
+    stage: &'static str,
+    downloaded_bytes: u64,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct DownloadFinished {
+    tools: Option<FfmpegTools>,
+    error: Option<AppError>,
+}
+
+struct Resolver {
+    configured: Option<(PathBuf, PathBuf)>,
+    /// The session's resolution, including a failed one; cleared when the settings change,
+    /// on an explicit refresh and after a download.
+    resolved: Option<Result<FfmpegTools, AppError>>,
+}
+
+static RESOLVER: Mutex<Resolver> = Mutex::new(Resolver {
+    configured: None,
+    resolved: None,
+});
+
+/// Only one download may run at a time.
+static DOWNLOADING: AtomicBool = AtomicBool::new(false);
+
+fn executable(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}{}", name, std::env::consts::EXE_SUFFIX))
+}
+
+/// Applies the ffmpeg paths from `settings` and forgets the cached resolution.
+pub fn configure(settings: &Settings) {
+    let configured = settings.ffmpeg_path.as_ref().map(|ffmpeg| {
+        let ffmpeg = PathBuf::from(ffmpeg);
+        let ffprobe = match &settings.ffprobe_path {
+            Some(ffprobe) => PathBuf::from(ffprobe),
+            None => executable(ffmpeg.parent().unwrap_or(Path::new("")), "ffprobe"),
+        };
+        (ffmpeg, ffprobe)
+    });
+    let mut resolver = RESOLVER.lock().unwrap();
+    resolver.configured = configured;
+    resolver.resolved = None;
+}
+
+/// `CREATE_NO_WINDOW`.
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+/// `BELOW_NORMAL_PRIORITY_CLASS`.
+#[cfg(windows)]
+const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x0000_4000;
+
+fn hidden_command(program: &Path) -> Command {
+    #[cfg_attr(not(windows), allow(unused_mut))]
+    let mut cmd = Command::new(program);
+    #[cfg(windows)]
+    cmd.creation_flags(CREATE_NO_WINDOW);
+    cmd
+}
+
+/// Runs `<program> -version` and returns the version from its first line.
+fn tool_version(program: &Path, name: &str) -> Result<String, String> {
+    let output = hidden_command(program)
+        .arg("-version")
+        .output()
+        .map_err(|e| format!("{}: {}", program.display(), e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = stdout
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix(&format!("{} version ", name)))
+        .and_then(|rest| rest.split_whitespace().next())
+        .filter(|_| output.status.success())
+        .ok_or_else(|| format!("{}: not a working {} build", program.display(), name))?;
+
+    // Release builds are "7.0.1" or "n7.0.1"; git builds ("N-113402-g...") are recent.
+    let major = version
+        .trim_start_matches('n')
+        .split(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|m| m.parse::<u32>().ok());
+    if let Some(major) = major.filter(|&m| m < MIN_MAJOR_VERSION) {
+        return Err(format!(
+            "{}: version {} is too old (need {} or newer)",
+            program.display(),
+            major,
+            MIN_MAJOR_VERSION
+        ));
+    }
+    Ok(version.to_string())
+}
+
+fn validate(ffmpeg: PathBuf, ffprobe: PathBuf, source: ToolSource) -> Result<FfmpegTools, String> {
+    let version = tool_version(&ffmpeg, "ffmpeg")?;
+    tool_version(&ffprobe, "ffprobe")?;
+    Ok(FfmpegTools {
+        ffmpeg,
+        ffprobe,
+        version,
+        source,
+    })
+}
+
+/// Tries the configured paths, the sidecar directory and the system `PATH` in turn.
+fn find(configured: Option<(PathBuf, PathBuf)>) -> Result<FfmpegTools, AppError> {
+    let mut candidates = Vec::new();
+    if let Some((ffmpeg, ffprobe)) = configured {
+        candidates.push((ffmpeg, ffprobe, ToolSource::Configured));
+    }
+    if let Ok(dir) = ffmpeg_sidecar::paths::sidecar_dir() {
+        candidates.push((
+            executable(&dir, "ffmpeg"),
+            executable(&dir, "ffprobe"),
+            ToolSource::Sidecar,
+        ));
+    }
+    candidates.push((
+        PathBuf::from("ffmpeg"),
+        PathBuf::from("ffprobe"),
+        ToolSource::System,
+    ));
+
+    let mut errors = Vec::new();
+    for (ffmpeg, ffprobe, source) in candidates {
+        // A sidecar that was never installed is the normal case, not worth reporting.
+        if source == ToolSource::Sidecar && !ffmpeg.is_file() {
+            continue;
+        }
+        match validate(ffmpeg, ffprobe, source) {
+            Ok(tools) => return Ok(tools),
+            Err(e) => errors.push(e),
+        }
+    }
+    Err(AppError::FfmpegMissing {
+        details: errors.join("; "),
+    })
+}
+
+/// Returns the ffmpeg/ffprobe pair for this session, resolving it on first use.
+pub fn resolve() -> Result<FfmpegTools, AppError> {
+    let configured = {
+        let resolver = RESOLVER.lock().unwrap();
+        if let Some(resolved) = &resolver.resolved {
+            return resolved.clone();
+        }
+        resolver.configured.clone()
+    };
+    // Validation spawns processes, so it runs without holding the lock.
+    let resolved = find(configured.clone());
+    match &resolved {
+        Ok(tools) => log::info!(
+            "Using ffmpeg {} from {}",
+            tools.version,
+            tools.ffmpeg.display()
+        ),
+        Err(e) => log::warn!("{}", e),
+    }
+    let mut resolver = RESOLVER.lock().unwrap();
+    if resolver.configured == configured {
+        resolver.resolved = Some(resolved.clone());
+    }
+    resolved
+}
+
+/// An `ffmpeg` command using the resolved binary, with no console window on Windows.
+pub fn ffmpeg_command() -> Result<Command, AppError> {
+    Ok(hidden_command(&resolve()?.ffmpeg))
+}
+
+/// An `ffprobe` command using the resolved binary, with no console window on Windows.
+pub fn ffprobe_command() -> Result<Command, AppError> {
+    Ok(hidden_command(&resolve()?.ffprobe))
+}
+
+/// Runs a command from `ffmpeg_command` below normal priority, for background work. Only
+/// done on Windows; elsewhere callers limit ffmpeg's threads instead.
+pub fn lower_priority(cmd: &mut Command) {
+    #[cfg(windows)]
+    cmd.creation_flags(CREATE_NO_WINDOW | BELOW_NORMAL_PRIORITY_CLASS);
+    #[cfg(not(windows))]
+    let _ = cmd;
+}
+
+/// Reads `stderr` to the end on a thread of its own, so ffmpeg never blocks on a full
+/// pipe while the caller reads stdout, and returns the last `STDERR_TAIL_LEN` bytes.
+pub fn drain_stderr(stderr: impl Read + Send + 'static) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || read_tail(stderr, STDERR_TAIL_LEN))
+}
+
+fn read_tail(mut reader: impl Read, limit: usize) -> Vec<u8> {
+    let mut tail = Vec::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => tail.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+        // Trim in batches rather than on every read.
+        if tail.len() > 2 * limit {
+            tail.drain(..tail.len() - limit);
+        }
+    }
+    tail.drain(..tail.len().saturating_sub(limit));
+    tail
+}
+
+/// Reports which ffmpeg would be used. `refresh` forgets the cached resolution first.
+#[tauri::command]
+pub fn get_ffmpeg_status(refresh: bool) -> FfmpegStatus {
+    if refresh {
+        RESOLVER.lock().unwrap().resolved = None;
+    }
+    match resolve() {
+        Ok(tools) => FfmpegStatus {
+            tools: Some(tools),
+            error: None,
+        },
+        Err(e) => FfmpegStatus {
+            tools: None,
+            error: Some(e),
+        },
+    }
+}
+
+fn download(app: &AppHandle) -> Result<FfmpegTools, AppError> {
+    let emit = |stage, downloaded_bytes| {
+        let _ = app.emit(
+            FFMPEG_DOWNLOAD_PROGRESS_EVENT,
+            DownloadProgress {
+                stage,
+                downloaded_bytes,
+            },
+        );
+    };
+
+    emit("resolving", 0);
+    let failed = |message: String| AppError::FfmpegDownloadFailed { message };
+    let url = ffmpeg_sidecar::download::ffmpeg_download_url()
+        .map_err(|e| failed(format!("no build available for this platform: {}", e)))?;
+    let dir = ffmpeg_sidecar::paths::sidecar_dir()
+        .map_err(|e| failed(format!("cannot locate the sidecar directory: {}", e)))?;
+    std::fs::create_dir_all(&dir).map_err(|e| AppError::io("create", Some(&dir), e))?;
+
+    // The archive is saved under the last segment of the URL; its growing size is the
+    // only progress the download function gives us.
+    let archive = dir.join(url.rsplit('/').next().unwrap_or("ffmpeg-download"));
+    let done = Arc::new(AtomicBool::new(false));
+    let poller = {
+        let done = done.clone();
+        let archive = archive.clone();
+        let app = app.clone();
+        std::thread::spawn(move || {
+            while !done.load(Ordering::Relaxed) {
+                let size = std::fs::metadata(&archive).map(|m| m.len()).unwrap_or(0);
+                let _ = app.emit(
+                    FFMPEG_DOWNLOAD_PROGRESS_EVENT,
+                    DownloadProgress {
+                        stage: "downloading",
+                        downloaded_bytes: size,
+                    },
+                );
+                std::thread::sleep(PROGRESS_INTERVAL);
+            }
+        })
+    };
+    let downloaded = ffmpeg_sidecar::download::download_ffmpeg_package(url, &dir);
+    done.store(true, Ordering::Relaxed);
+    let _ = poller.join();
+    let archive = downloaded.map_err(|e| failed(e.to_string()))?;
+
+    let size = std::fs::metadata(&archive).map(|m| m.len()).unwrap_or(0);
+    emit("unpacking", size);
+    ffmpeg_sidecar::download::unpack_ffmpeg(&archive, &dir)
+        .map_err(|e| failed(format!("cannot unpack the archive: {}", e)))?;
+
+    emit("verifying", size);
+    RESOLVER.lock().unwrap().resolved = None;
+    resolve()
+}
+
+/// Downloads ffmpeg next to the executable in the background. Only ever runs when the
+/// user asks for it; progress arrives as `ffmpeg-download-progress` events and the
+/// outcome as one `ffmpeg-download-finished`. Returns `false` if a download is already
+/// running.
+#[tauri::command]
+pub fn download_ffmpeg(app: AppHandle) -> bool {
+    if DOWNLOADING.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+    std::thread::spawn(move || {
+        let finished = match download(&app) {
+            Ok(tools) => DownloadFinished {
+                tools: Some(tools),
+                error: None,
+            },
+            Err(e) => {
+                log::warn!("{}", e);
+                DownloadFinished {
+                    tools: None,
+                    error: Some(e),
+                }
+            }
+        };
+        DOWNLOADING.store(false, Ordering::SeqCst);
+        let _ = app.emit(FFMPEG_DOWNLOAD_FINISHED_EVENT, finished);
+    });
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tail_keeps_the_last_bytes() {
+        let text: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        assert_eq!(read_tail(&text[..], 1000), &text[text.len() - 1000..]);
+        assert_eq!(read_tail(&text[..10], 1000), &text[..10]);
+        assert!(read_tail(&[][..], 1000).is_empty());
+    }
+}
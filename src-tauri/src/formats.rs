@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::Path;
+
+/// Extensions recognised as audio when a library root has no override. Everything here
+/// is decodable by the ffmpeg pipeline in `transcode::prepare`. WebM is left out: most of
+/// it is video, so it is sniffed like files without an extension.
+pub const DEFAULT_EXTENSIONS: &[&str] = &[
+    "mp3", "wav", "flac", "m4a", "ogg", "oga", "opus", "aac", "alac", "aiff", "aif", "aifc", "ape",
+    "wv", "wma", "dsf", "dff", "mka", "tta",
+];
+
+/// Extensions that commonly sit next to music and are never worth sniffing.
+const NON_AUDIO_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "bmp", "webp", "tif", "tiff", "txt", "cue", "lrc", "srt", "ttml",
+    "log", "nfo", "pdf", "m3u", "m3u8", "pls", "xspf", "ini", "db", "json", "xml", "html", "md",
+    "accurip", "sfv", "md5", "ffp", "toc", "url", "exe", "zip", "rar", "7z", "mp4", "m4v", "mov",
+    "3gp", "heic", "heif", "avif", "mkv",
+];
+
+/// ISO-BMFF brands that only ever label audio files.
+const AUDIO_BRANDS: &[&[u8; 4]] = &[b"M4A ", b"M4B ", b"M4P ", b"F4A ", b"F4B "];
+
+/// Per-root format settings. `extensions: None` means `DEFAULT_EXTENSIONS`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatOptions {
+    pub extensions: Option<Vec<String>>,
+    /// Whether files with a missing or unrecognised extension are checked by magic bytes.
+    pub sniff_content: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            extensions: None,
+            sniff_content: true,
+        }
+    }
+}
+
+/// Decides whether a file under one library root should be indexed.
+#[derive(Clone)]
+pub struct FormatMatcher {
+    extensions: Vec<String>,
+    sniff_content: bool,
+}
+
+impl FormatMatcher {
+    pub fn new(options: &FormatOptions) -> Self {
+        let extensions = match &options.extensions {
+            Some(list) => list
+                .iter()
+                .map(|e| e.trim_start_matches('.').to_lowercase())
+                .collect(),
+            None => DEFAULT_EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+        };
+        FormatMatcher {
+            extensions,
+            sniff_content: options.sniff_content,
+        }
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let ext = path
+            .extension()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_lowercase());
+        match ext {
+            Some(ext) if self.extensions.contains(&ext) => true,
+            Some(ext) if NON_AUDIO_EXTENSIONS.contains(&ext.as_str()) => false,
+            _ => self.sniff_content && sniff(path).is_some(),
+        }
+    }
+}
+
+impl Default for FormatMatcher {
+    fn default() -> Self {
+        FormatMatcher::new(&FormatOptions::default())
+    }
+}
+
+/// Identifies an audio container from its first bytes. Returns a short format name.
+pub fn sniff(path: &Path) -> Option<&'static str> {
+    let mut header = [0u8; 64];
+    let mut file = std::fs::File::open(path).ok()?;
+    let mut len = 0;
+    // Read until the buffer is full or the file ends; tiny files simply fail to match.
+    while len < header.len() {
+        match file.read(&mut header[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(_) => return None,
+        }
+    }
+    sniff_bytes(&header[..len])
+}
+
+fn sniff_bytes(b: &[u8]) -> Option<&'static str> {
+    let at = |offset: usize, magic: &[u8]| {
+        b.len() >= offset + magic.len() && &b[offset..offset + magic.len()] == magic
+    };
+
+    if at(0, b"fLaC") {
+        Some("flac")
+    } else if at(0, b"OggS") {
+        Some("ogg")
+    } else if at(0, b"RIFF") && at(8, b"WAVE") {
+        Some("wav")
+    } else if at(0, b"FORM") && (at(8, b"AIFF") || at(8, b"AIFC")) {
+        Some("aiff")
+    } else if at(4, b"ftyp") {
+        // Plain `mp42`/`isom` files are usually video, so only audio brands count.
+        ftyp_brands(b)
+            .any(|brand| AUDIO_BRANDS.contains(&brand))
+            .then_some("m4a")
+    } else if at(0, b"ID3") {
+        Some("mp3")
+    } else if at(0, b"MAC ") {
+        Some("ape")
+    } else if at(0, b"wvpk") {
+        Some("wv")
+    } else if at(0, b"TTA1") {
+        Some("tta")
+    } else if at(0, b"DSD ") {
+        Some("dsf")
+    } else if at(0, b"FRM8") {
+        Some("dff")
+    } else if at(0, b"ADIF") {
+        Some("aac")
+    } else if at(0, &[0x30, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11]) {
+        // ASF header object GUID
+        Some("wma")
+    } else if at(0, &[0x1A, 0x45, 0xDF, 0xA3]) {
+        // EBML: only Matroska audio is worth probing; video containers carry an extension.
+        (ebml_doc_type(b) == Some(b"matroska".as_slice())).then_some("mka")
+    } else if b.len() >= 2 && b[0] == 0xFF && b[1] & 0xF6 == 0xF0 {
+        // ADTS sync word with layer bits 00
+        Some("aac")
+    } else if b.len() >= 2 && b[0] == 0xFF && b[1] & 0xE0 == 0xE0 && (b[1] >> 1) & 0x03 != 0 {
+        // MPEG audio frame sync without an ID3 tag
+        Some("mp3")
+    } else {
+        None
+    }
+}
+
+/// Major brand followed by the compatible brands of a leading `ftyp` box, as far as
+/// they fit in `b`.
+fn ftyp_brands(b: &[u8]) -> impl Iterator<Item = &[u8; 4]> {
+    let size = b
+        .get(..4)
+        .map(|s| u32::from_be_bytes([s[0], s[1], s[2], s[3]]) as usize)
+        .unwrap_or(0);
+    let end = size.min(b.len());
+    let major = b.get(8..12).into_iter();
+    let compatible = b.get(16..end).unwrap_or(&[]).chunks_exact(4);
+    major
+        .chain(compatible)
+        .filter_map(|chunk| <&[u8; 4]>::try_from(chunk).ok())
+}
+
+/// Reads the DocType string from an EBML header, if it sits within `b`.
+fn ebml_doc_type(b: &[u8]) -> Option<&[u8]> {
+    let pos = b.windows(2).position(|w| w == [0x42, 0x82])? + 2;
+    // One-byte VINT sizes cover every DocType in practice ("matroska", "webm").
+    let size = *b.get(pos)?;
+    if size & 0x80 == 0 {
+        return None;
+    }
+    let len = (size & 0x7F) as usize;
+    b.get(pos + 1..pos + 1 + len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let size = 16 + 4 * compatible.len();
+        let mut b = (size as u32).to_be_bytes().to_vec();
+        b.extend_from_slice(b"ftyp");
+        b.extend_from_slice(major);
+        b.extend_from_slice(&[0, 0, 0, 0]);
+        for brand in compatible {
+            b.extend_from_slice(*brand);
+        }
+        b
+    }
+
+    fn ebml(doc_type: &[u8]) -> Vec<u8> {
+        let mut b = vec![
+            0x1A, 0x45, 0xDF, 0xA3, 0xA3, 0x42, 0x86, 0x81, 0x01, 0x42, 0x82,
+        ];
+        b.push(0x80 | doc_type.len() as u8);
+        b.extend_from_slice(doc_type);
+        b
+    }
+
+    #[test]
+    fn ftyp_requires_an_audio_brand() {
+        assert_eq!(sniff_bytes(&ftyp(b"M4A ", &[b"isom"])), Some("m4a"));
+        assert_eq!(
+            sniff_bytes(&ftyp(b"mp42", &[b"isom", b"M4B "])),
+            Some("m4a")
+        );
+        assert_eq!(sniff_bytes(&ftyp(b"mp42", &[b"isom", b"avc1"])), None);
+        assert_eq!(sniff_bytes(&ftyp(b"heic", &[b"mif1", b"heic"])), None);
+        assert_eq!(sniff_bytes(&ftyp(b"avif", &[b"mif1"])), None);
+    }
+
+    #[test]
+    fn ebml_requires_matroska_doc_type() {
+        assert_eq!(sniff_bytes(&ebml(b"matroska")), Some("mka"));
+        assert_eq!(sniff_bytes(&ebml(b"webm")), None);
+    }
+
+    #[test]
+    fn video_and_image_extensions_are_not_sniffed() {
+        let matcher = FormatMatcher::default();
+        for name in [
+            "clip.mp4",
+            "clip.mov",
+            "photo.heic",
+            "photo.avif",
+            "film.mkv",
+        ] {
+            assert!(!matcher.matches(Path::new(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn webm_is_decided_by_doc_type() {
+        let dir = std::env::temp_dir().join(format!("formats_{}_webm", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let video = dir.join("clip.webm");
+        let audio = dir.join("song.webm");
+        std::fs::write(&video, ebml(b"webm")).unwrap();
+        std::fs::write(&audio, ebml(b"matroska")).unwrap();
+
+        let matcher = FormatMatcher::default();
+        let matched = (matcher.matches(&video), matcher.matches(&audio));
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(matched, (false, true));
+    }
+}
@@ -0,0 +1,234 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// How much of an MP3 (after any ID3v2 tag) is searched for the first frame.
+const FRAME_SEARCH_LEN: u64 = 8 * 1024;
+/// A gapless length further than this (in seconds) from ffprobe's estimate is assumed to
+/// come from a stale or bogus tag and ignored.
+const MAX_DURATION_DIFFERENCE: f64 = 1.0;
+
+/// Encoder delay and padding of a lossy file, in samples at the source sample rate. The
+/// decoder drops the delay itself; the padding is cut by playing exactly `samples`.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GaplessInfo {
+    pub delay: u32,
+    pub padding: u32,
+    /// Samples of actual audio, without delay and padding.
+    pub samples: Option<u64>,
+}
+
+impl GaplessInfo {
+    /// Exact length in seconds, if it is known and plausible next to ffprobe's `duration`.
+    pub fn duration(&self, sample_rate: Option<u32>, duration: Option<f64>) -> Option<f64> {
+        let exact = self.samples? as f64 / f64::from(sample_rate.filter(|&r| r > 0)?);
+        match duration {
+            Some(duration) if (duration - exact).abs() > MAX_DURATION_DIFFERENCE => None,
+            _ => Some(exact),
+        }
+    }
+}
+
+/// Reads the gapless information of `path`: the LAME/Xing header of MP3s, otherwise an
+/// iTunes `iTunSMPB` tag (AAC, and MP3s encoded by iTunes).
+pub fn read(
+    path: &Path,
+    codec: Option<&str>,
+    tags: &HashMap<String, String>,
+) -> Option<GaplessInfo> {
+    let lame = match codec {
+        Some("mp3") | Some("mp3float") => lame_header(path),
+        _ => None,
+    };
+    lame.or_else(|| {
+        tags.iter()
+            .find(|(k, _)| k.ends_with("itunsmpb"))
+            .and_then(|(_, v)| parse_itunsmpb(v))
+    })
+}
+
+/// Parses an `iTunSMPB` value: hex fields for a reserved word, delay, padding and the
+/// original sample count, e.g. ` 00000000 00000840 000001CA 0000000000A2B1C8 ...`.
+fn parse_itunsmpb(value: &str) -> Option<GaplessInfo> {
+    let mut fields = value.split_whitespace().skip(1);
+    let delay = u32::from_str_radix(fields.next()?, 16).ok()?;
+    let padding = u32::from_str_radix(fields.next()?, 16).ok()?;
+    let samples = u64::from_str_radix(fields.next()?, 16).ok()?;
+    Some(GaplessInfo {
+        delay,
+        padding,
+        samples: (samples > 0).then_some(samples),
+    })
+}
+
+fn be32(bytes: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(..4)?.try_into().ok()?))
+}
+
+/// Reads delay and padding from the LAME extension of the Xing/Info header in the first
+/// MP3 frame. LAME and ffmpeg (`Lavc`/`Lavf`) both write it.
+fn lame_header(path: &Path) -> Option<GaplessInfo> {
+    let mut file = File::open(path).ok()?;
+    let mut id3 = [0u8; 10];
+    file.read_exact(&mut id3).ok()?;
+    let mut offset = 0u64;
+    if id3.starts_with(b"ID3") {
+        let size = id3[6..10]
+            .iter()
+            .fold(0u64, |size, &b| (size << 7) | u64::from(b & 0x7f));
+        let footer = if id3[5] & 0x10 != 0 { 10 } else { 0 };
+        offset = 10 + size + footer;
+    }
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    let mut buf = Vec::new();
+    file.take(FRAME_SEARCH_LEN).read_to_end(&mut buf).ok()?;
+
+    let pos =
+        (0..buf.len().saturating_sub(4)).find(|&i| buf[i] == 0xff && buf[i + 1] & 0xe0 == 0xe0)?;
+    let header = &buf[pos..pos + 4];
+    // 3 = MPEG-1, 2 = MPEG-2, 0 = MPEG-2.5, 1 is reserved.
+    let version = (header[1] >> 3) & 3;
+    let layer3 = (header[1] >> 1) & 3 == 1;
+    if !layer3 || version == 1 {
+        return None;
+    }
+    let mpeg1 = version == 3;
+    let mono = header[3] >> 6 == 3;
+    let side_info = match (mpeg1, mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+
+    let mut p = pos + 4 + side_info;
+    let tag = buf.get(p..p + 4)?;
+    if tag != b"Xing" && tag != b"Info" {
+        return None;
+    }
+    let flags = be32(buf.get(p + 4..)?)?;
+    p += 8;
+    let frames = if flags & 1 != 0 {
+        let frames = be32(buf.get(p..)?)?;
+        p += 4;
+        Some(frames)
+    } else {
+        None
+    };
+    for (flag, len) in [(2, 4), (4, 100), (8, 4)] {
+        if flags & flag != 0 {
+            p += len;
+        }
+    }
+
+    let lame = buf.get(p..p + 24)?;
+    if !(lame.starts_with(b"LAME") || lame.starts_with(b"Lavc") || lame.starts_with(b"Lavf")) {
+        return None;
+    }
+    let delay = (u32::from(lame[21]) << 4) | (u32::from(lame[22]) >> 4);
+    let padding = (u32::from(lame[22] & 0x0f) << 8) | u32::from(lame[23]);
+    let samples_per_frame: u64 = if mpeg1 { 1152 } else { 576 };
+    Some(GaplessInfo {
+        delay,
+        padding,
+        samples: frames
+            .filter(|&f| f > 0)
+            .map(|f| (u64::from(f) * samples_per_frame).saturating_sub(u64::from(delay + padding))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn itunsmpb_fields_are_hex() {
+        let info = parse_itunsmpb(
+            " 00000000 00000840 000001CA 0000000000A2B1C8 00000000 00000000 00000000 00000000",
+        )
+        .unwrap();
+        assert_eq!(
+            info,
+            GaplessInfo {
+                delay: 0x840,
+                padding: 0x1CA,
+                samples: Some(0xA2B1C8),
+            }
+        );
+    }
+
+    #[test]
+    fn itunsmpb_without_sample_count() {
+        let info = parse_itunsmpb("00000000 00000840 00000000 0000000000000000").unwrap();
+        assert_eq!(info.delay, 0x840);
+        assert_eq!(info.padding, 0);
+        assert_eq!(info.samples, None);
+    }
+
+    #[test]
+    fn malformed_itunsmpb_is_ignored() {
+        assert_eq!(parse_itunsmpb(""), None);
+        assert_eq!(parse_itunsmpb("00000000 00000840"), None);
+        assert_eq!(parse_itunsmpb("00000000 zzzz 00000000 00000000"), None);
+    }
+
+    #[test]
+    fn tag_is_used_for_non_mp3_codecs() {
+        let tags = HashMap::from([(
+            "----:com.apple.itunes:itunsmpb".to_string(),
+            " 00000000 00000840 00000100 0000000000010000".to_string(),
+        )]);
+        let info = read(Path::new("missing.m4a"), Some("aac"), &tags).unwrap();
+        assert_eq!(info.delay, 0x840);
+        assert_eq!(info.samples, Some(0x10000));
+        assert_eq!(
+            read(Path::new("missing.m4a"), Some("aac"), &HashMap::new()),
+            None
+        );
+    }
+
+    #[test]
+    fn lame_header_gives_delay_padding_and_length() {
+        // MPEG-1 layer III, 128 kbit/s, 44.1 kHz, stereo.
+        let mut frame = vec![0xFF, 0xFB, 0x90, 0x00];
+        frame.extend_from_slice(&[0; 32]); // side info
+        frame.extend_from_slice(b"Info");
+        frame.extend_from_slice(&0x0Fu32.to_be_bytes()); // frames, bytes, TOC, quality
+        frame.extend_from_slice(&100u32.to_be_bytes());
+        frame.extend_from_slice(&[0; 4 + 100 + 4]);
+        let mut lame = [0u8; 24];
+        lame[..9].copy_from_slice(b"LAME3.100");
+        // 12 bits each: delay 576, padding 1152.
+        lame[21..24].copy_from_slice(&[0x24, 0x04, 0x80]);
+        frame.extend_from_slice(&lame);
+        frame.resize(417, 0);
+
+        let path = std::env::temp_dir().join(format!("gapless_{}.mp3", std::process::id()));
+        std::fs::write(&path, &frame).unwrap();
+        let info = read(&path, Some("mp3"), &HashMap::new());
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(
+            info,
+            Some(GaplessInfo {
+                delay: 576,
+                padding: 1152,
+                samples: Some(100 * 1152 - 576 - 1152),
+            })
+        );
+    }
+
+    #[test]
+    fn implausible_duration_is_rejected() {
+        let info = GaplessInfo {
+            delay: 0,
+            padding: 0,
+            samples: Some(441_000),
+        };
+        assert_eq!(info.duration(Some(44_100), Some(10.02)), Some(10.0));
+        assert_eq!(info.duration(Some(44_100), Some(12.0)), None);
+        assert_eq!(info.duration(None, Some(10.0)), None);
+    }
+}
@@ -1,46 +1,47 @@
 use base64::{engine::general_purpose, Engine as _};
-use serde::{Deserialize, Serialize};
-use std::borrow::Cow;
+use serde::Serialize;
 use std::collections::HashMap;
-use std::process::Command;
+use std::path::Path;
 use tempfile::tempdir;
-use encoding_rs::{GBK, UTF_16LE};
 use font_kit::source::SystemSource;
-#[cfg(windows)]
-use std::os::windows::process::CommandExt;
 // use font_kit::family::Family; // no longer needed
 use font_kit::handle::Handle;
 use font_kit::properties::{Style, Weight};
-use walkdir::WalkDir;
-
-#[derive(Serialize)]
-struct AudioFile {
-    path: String,
-    name: String,
-}
-
-#[tauri::command]
-fn scan_music_folder(path: String) -> Result<Vec<AudioFile>, String> {
-    let mut files = Vec::new();
-    let supported_extensions = ["mp3", "wav", "flac", "m4a", "ogg"];
-
-    for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        if path.is_file() {
-            if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-                if supported_extensions.contains(&ext.to_lowercase().as_str()) {
-                    files.push(AudioFile {
-                        path: path.to_string_lossy().to_string(),
-                        name: path.file_stem().unwrap_or_default().to_string_lossy().to_string(),
-                    });
-                }
-            }
-        }
-    }
-    // Simple sort
-    files.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
-    Ok(files)
-}
+use tauri::Manager;
+use cache::TranscodeCache;
+use error::AppError;
+use stream::StreamRegistry;
+use transcode::{Prefetcher, TranscodeJobs};
+use metadata::{AudioProperties, Metadata, TrackMetadata, PLAYBACK_CHANNELS};
+
+mod analysis;
+mod cache;
+mod cue;
+mod error;
+mod ffmpeg;
+mod formats;
+mod gapless;
+mod library;
+mod loudness;
+mod lyrics;
+mod metadata;
+mod pairing;
+mod pcm;
+mod playlist;
+mod profile;
+mod romanize;
+mod scan;
+mod settings;
+mod sidecar;
+mod stream;
+mod subtitles;
+mod tag_reader;
+mod tags;
+mod text;
+mod transcode;
+mod ttml;
+mod watcher;
+mod waveform;
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -67,7 +68,7 @@ fn check_lang_support(font: &font_kit::font::Font, lang_code: &str) -> bool {
 }
 
 #[tauri::command]
-fn get_system_fonts() -> Result<CategorizedFonts, String> {
+fn get_system_fonts() -> Result<CategorizedFonts, AppError> {
     let source = SystemSource::new();
     let mut font_names: HashMap<String, (bool, bool)> = HashMap::new(); // (is_zh, is_ja)
 
@@ -86,7 +87,11 @@ fn get_system_fonts() -> Result<CategorizedFonts, String> {
             }
         }
     } else {
-        return Err("Failed to query system fonts.".into());
+        return Err(AppError::Io {
+            action: "query system fonts".to_string(),
+            path: None,
+            message: "font enumeration failed".to_string(),
+        });
     }
 
     // Second pass: Categorize based on the collected information
@@ -121,7 +126,7 @@ fn get_system_fonts() -> Result<CategorizedFonts, String> {
                     ja_fonts.push(name);
                 } else if !looks_chinese(&name) {
                     // Name doesn't look Chinese; if it's ascii treat as English, else put to other.
-                    if name.chars().all(|c| c.is_ascii()) {
+                    if name.is_ascii() {
                         en_fonts.push(name);
                     } else {
                         other_fonts.push(name);
@@ -148,7 +153,7 @@ fn get_system_fonts() -> Result<CategorizedFonts, String> {
                     ja_fonts.push(name);
                 } else if looks_chinese(&name) {
                     zh_fonts.push(name);
-                } else if name.chars().all(|c| c.is_ascii()) {
+                } else if name.is_ascii() {
                     en_fonts.push(name);
                 } else {
                     other_fonts.push(name);
@@ -173,11 +178,11 @@ fn get_system_fonts() -> Result<CategorizedFonts, String> {
 
 /// A command that takes a font family name and returns the font data as a Base64 string.
 #[tauri::command]
-fn get_font_data(font_name: String) -> Result<String, String> {
+fn get_font_data(font_name: String) -> Result<String, AppError> {
     let source = SystemSource::new();
     let family = source
         .select_family_by_name(&font_name)
-        .map_err(|e| format!("Font family '{}' not found: {}", font_name, e))?;
+        .map_err(|_| AppError::FontNotFound { name: font_name.clone() })?;
 
     // Pick the first font in the family that is Normal style & weight if possible.
     let mut chosen_handle: Option<Handle> = None;
@@ -197,12 +202,12 @@ fn get_font_data(font_name: String) -> Result<String, String> {
         }
     }
 
-    let handle = chosen_handle.ok_or_else(|| format!("No fonts found in family '{}'.", font_name))?;
+    let handle = chosen_handle.ok_or(AppError::FontNotFound { name: font_name })?;
 
     // Extract bytes from the handle.
     let font_bytes = match handle {
         Handle::Path { ref path, .. } => std::fs::read(path)
-            .map_err(|e| format!("Failed to read font file: {}", e))?,
+            .map_err(|e| AppError::io("read font file", Some(path), e))?,
         Handle::Memory { bytes, .. } => bytes.to_vec(),
     };
 
@@ -210,471 +215,79 @@ fn get_font_data(font_name: String) -> Result<String, String> {
 }
 
 
-#[derive(Deserialize, Debug)]
-struct FFProbeOutput {
-    streams: Vec<Stream>,
-    format: Format,
-}
-
-#[derive(Deserialize, Debug)]
-struct Stream {
-    codec_type: String,
-    // The `tags` field might be missing if there are no tags.
-    #[serde(default)]
-    tags: HashMap<String, String>,
-}
-
-#[derive(Deserialize, Debug)]
-struct Format {
-    // The `tags` field might be missing if there are no tags.
-    #[serde(default)]
-    tags: HashMap<String, String>,
-}
-
-#[derive(serde::Serialize, Clone)]
-#[serde(rename_all = "camelCase")]
-struct Metadata {
-    title: Option<String>,
-    artist: Option<String>,
-    mime_type: Option<String>,
-}
-
 #[derive(serde::Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 struct ProcessedFile {
     metadata: Metadata,
+    properties: AudioProperties,
     playback_data_base64: String,
     album_art_base64: Option<String>,
-    lyrics: Option<String>,
-}
-
-#[derive(serde::Serialize, Clone)]
-#[serde(rename_all = "camelCase")]
-struct PreparedFile {
-    metadata: Metadata,
-    cache_path: String,
-    album_art_base64: Option<String>,
-    lyrics: Option<String>,
+    lyrics: Option<lyrics::Lyrics>,
 }
 
 #[tauri::command]
-fn process_audio_file(path: String) -> Result<ProcessedFile, String> {
+fn process_audio_file(path: String) -> Result<ProcessedFile, AppError> {
     // Decode the URL-encoded path received from the frontend to prevent corruption.
     let path_decoded = urlencoding::decode(&path)
-        .map_err(|e| format!("Failed to decode path: {}", e))?
+        .map_err(|e| AppError::invalid_argument(format!("Failed to decode path: {}", e)))?
         .into_owned();
 
     let path = path_decoded;
 
-    // 1. Download ffmpeg/ffprobe if not already present.
-    // This also adds the binaries to the PATH for the current process.
-    ffmpeg_sidecar::download::auto_download()
-        .map_err(|e| format!("Failed to download ffmpeg: {}", e))?;
-
-    // 2. Run ffprobe to get metadata
-    let mut ffprobe_cmd = Command::new("ffprobe");
-    ffprobe_cmd.arg("-v")
-        .arg("quiet")
-        .arg("-print_format")
-        .arg("json")
-        .arg("-show_format")
-        .arg("-show_streams")
-        .arg("-i")
-        .arg(&path);
-    
-    #[cfg(windows)]
-    ffprobe_cmd.creation_flags(0x08000000);
+    // 1. Read tags, lyrics and cover art; none of this needs ffmpeg
+    let TrackMetadata { mut metadata, lyrics, properties, .. } = metadata::read(Path::new(&path), None);
+    let album_art_base64 = metadata::album_art(Path::new(&path), &mut metadata)?;
 
-    let ffprobe_output = ffprobe_cmd.output()
-        .map_err(|e| format!("Failed to execute ffprobe: {}", e))?;
-
-    let mut metadata = Metadata {
-        title: None,
-        artist: None,
-        mime_type: Some("image/jpeg".to_string()), // Default, might be overwritten
-    };
-    let mut lyrics = None;
-
-    if ffprobe_output.status.success() {
-        // Here we attempt to decode the output from ffprobe.
-        // The output could be in UTF-8, UTF-16 (on Windows), or a legacy
-        // codepage like GBK (on Chinese Windows systems). We try them in order.
-        let ffprobe_json: Cow<'_, str> = 
-            // 1. Try UTF-8 first.
-            if let Ok(s) = std::str::from_utf8(&ffprobe_output.stdout) {
-                Cow::Borrowed(s)
-            } else {
-                // 2. If not UTF-8, try UTF-16LE.
-                let (decoded_utf16, _, had_errors_utf16) = UTF_16LE.decode(&ffprobe_output.stdout);
-                if !had_errors_utf16 {
-                    // If decoding as UTF-16LE had no errors, it was likely the correct encoding.
-                    decoded_utf16
-                } else {
-                    // 3. If UTF-16LE also had errors, fall back to GBK as the last resort.
-                    let (decoded_gbk, _, had_errors_gbk) = GBK.decode(&ffprobe_output.stdout);
-                    if had_errors_gbk {
-                        eprintln!("[WARN] Failed to decode metadata as UTF-8, UTF-16LE, or GBK. Some characters may be incorrect.");
-                    }
-                    decoded_gbk
-                }
-            };
-
-        if let Ok(probe_data) = serde_json::from_str::<FFProbeOutput>(&ffprobe_json) {
-            // Combine tags from format and streams (sometimes metadata is in one or the other)
-            let mut combined_tags = probe_data.format.tags;
-            for stream in probe_data.streams {
-                if stream.codec_type == "audio" {
-                    combined_tags.extend(stream.tags);
-                    break; // Assume first audio stream is the one we want
-                }
-            }
-
-            metadata.title = combined_tags.get("title").cloned();
-            metadata.artist = combined_tags.get("artist").or_else(|| combined_tags.get("ARTIST")).cloned();
-            // 1) Try common keys
-            lyrics = combined_tags
-                .get("lyrics")
-                .or_else(|| combined_tags.get("LYRICS"))
-                .cloned();
-
-            // 2) If still none, search for any key that starts with "lyrics" (case-insensitive),
-            //    e.g. "lyrics-XXX" which is often produced by some DAWs.
-            if lyrics.is_none() {
-                for (k, v) in &combined_tags {
-                    if k.to_lowercase().starts_with("lyrics") {
-                        lyrics = Some(v.clone());
-                        break;
-                    }
-                }
-            }
-
-            // If ffprobe returns an empty or whitespace-only string for title or artist, treat it as missing.
-            if metadata
-                .title
-                .as_ref()
-                .map(|t| t.trim().is_empty() || t.contains('\u{FFFD}'))
-                .unwrap_or(false)
-            {
-                metadata.title = None;
-            }
-            if metadata
-                .artist
-                .as_ref()
-                .map(|a| a.trim().is_empty() || a.contains('\u{FFFD}'))
-                .unwrap_or(false)
-            {
-                metadata.artist = None;
-            }
-
-        } else {
-            eprintln!("Failed to parse ffprobe JSON output.");
-        }
-    } else {
-        eprintln!(
-            "ffprobe exited with non-zero status: {}",
-            String::from_utf8_lossy(&ffprobe_output.stderr)
-        );
-    }
-    
-    // Fallback if title or artist is still None
-    if let Some(file_stem_os) = std::path::Path::new(&path).file_stem() {
-        let file_stem_str = file_stem_os.to_string_lossy();
-
-        // Fallback for title
-        if metadata.title.is_none() {
-            metadata.title = Some(file_stem_str.to_string());
-        }
-
-        // If artist missing or invalid, and filename contains dash, attempt to parse "artist - title"
-        if metadata.artist.is_none() {
-            let parts: Vec<&str> = file_stem_str
-                .split('-')
-                .map(|s| s.trim())
-                .filter(|s| !s.is_empty())
-                .collect();
-            if parts.len() >= 2 {
-                metadata.artist = Some(parts[0].to_string());
-
-                // If our title still等于整个文件名，把它替换为去掉 artist 的剩余部分
-                if let Some(ref t) = metadata.title {
-                    if t == &file_stem_str {
-                        metadata.title = Some(parts[1..].join(" - "));
-                    }
-                }
-            }
-        }
-    }
-
-    // 3. Extract album art using ffmpeg
-    let temp_dir_art = tempdir().map_err(|e| format!("Failed to create temp dir for art: {}", e))?;
-    let art_output_path = temp_dir_art.path().join("cover.jpg");
-
-    let mut art_cmd = Command::new("ffmpeg");
-    art_cmd.arg("-hide_banner")
-        .arg("-loglevel")
-        .arg("error")
-        .arg("-i")
-        .arg(&path)
-        .arg("-an") // no audio
-        .arg("-vcodec")
-        .arg("copy")
-        .arg(art_output_path.to_str().unwrap());
+    // 2. Make sure a usable ffmpeg/ffprobe is available. Downloading is left to the user.
+    ffmpeg::resolve()?;
 
-    #[cfg(windows)]
-    art_cmd.creation_flags(0x08000000);
-
-    let art_output = art_cmd.output();
-
-    let mut album_art_base64 = None;
-    if let Ok(output) = art_output {
-        if output.status.success() {
-            if let Ok(art_data) = std::fs::read(&art_output_path) {
-                album_art_base64 = Some(general_purpose::STANDARD.encode(&art_data));
-            }
-        }
-    }
-
-    // 4. Transcode audio to WAV for playback
-    let temp_dir_wav = tempdir().map_err(|e| format!("Failed to create temp dir for wav: {}", e))?;
+    // 3. Transcode audio to WAV for playback
+    let temp_dir_wav = tempdir().map_err(|e| AppError::io("create a temp dir for wav", None, e))?;
     let wav_output_path = temp_dir_wav.path().join("playback.wav");
 
-    let mut wav_cmd = Command::new("ffmpeg");
+    let mut wav_cmd = ffmpeg::ffmpeg_command()?;
     wav_cmd.arg("-hide_banner")
         .arg("-loglevel")
         .arg("error")
         .arg("-i")
         .arg(&path)
         .arg("-ac")
-        .arg("2")
+        .arg(PLAYBACK_CHANNELS.to_string())
         .arg("-y")
-        .arg(wav_output_path.to_str().unwrap());
+        .arg(&wav_output_path);
 
-    #[cfg(windows)]
-    wav_cmd.creation_flags(0x08000000);
-        
-    let wav_status = wav_cmd.status()
-        .map_err(|e| format!("ffmpeg command for wav failed to run: {}", e))?;
+    let wav_output = wav_cmd.output()
+        .map_err(|e| AppError::io("run ffmpeg", None, e))?;
 
-    if !wav_status.success() {
-        return Err("ffmpeg command for wav failed".to_string());
+    if !wav_output.status.success() {
+        return Err(AppError::from_ffmpeg(Path::new(&path), &wav_output));
     }
 
     let wav_data = std::fs::read(&wav_output_path)
-        .map_err(|e| format!("Failed to read temporary wav file: {}", e))?;
+        .map_err(|e| AppError::io("read temporary wav file", Some(&wav_output_path), e))?;
 
     let playback_data_base64 = general_purpose::STANDARD.encode(&wav_data);
 
     Ok(ProcessedFile {
         metadata,
+        properties,
         playback_data_base64,
         album_art_base64,
-        lyrics,
-    })
-}
-
-#[tauri::command]
-fn prepare_audio_file(path: String) -> Result<PreparedFile, String> {
-    // Decode the URL-encoded path received from the frontend.
-    let path_decoded = urlencoding::decode(&path)
-        .map_err(|e| format!("Failed to decode path: {}", e))?
-        .into_owned();
-
-    let path = path_decoded;
-
-    // Ensure ffmpeg / ffprobe is available
-    ffmpeg_sidecar::download::auto_download()
-        .map_err(|e| format!("Failed to download ffmpeg: {}", e))?;
-
-    // ========== 1. 获取元数据 & 歌词（重用逻辑） ==========
-    // This reuses the same logic from process_audio_file to keep behaviour consistent.
-    let mut metadata = Metadata {
-        title: None,
-        artist: None,
-        mime_type: Some("image/jpeg".to_string()),
-    };
-    let mut lyrics = None;
-
-    // --- ffprobe metadata extraction (copy from existing implementation) ---
-    let mut ffprobe_cmd = std::process::Command::new("ffprobe");
-    ffprobe_cmd
-        .arg("-v")
-        .arg("quiet")
-        .arg("-print_format")
-        .arg("json")
-        .arg("-show_format")
-        .arg("-show_streams")
-        .arg("-i")
-        .arg(&path);
-
-    #[cfg(windows)]
-    ffprobe_cmd.creation_flags(0x08000000);
-
-    let ffprobe_output = ffprobe_cmd
-        .output()
-        .map_err(|e| format!("Failed to execute ffprobe: {}", e))?;
-
-    if ffprobe_output.status.success() {
-        let ffprobe_json: std::borrow::Cow<'_, str> = if let Ok(s) = std::str::from_utf8(&ffprobe_output.stdout) {
-            std::borrow::Cow::Borrowed(s)
-        } else {
-            let (decoded_utf16, _, had_errors_utf16) = UTF_16LE.decode(&ffprobe_output.stdout);
-            if !had_errors_utf16 {
-                decoded_utf16
-            } else {
-                let (decoded_gbk, _, _had_errors_gbk) = GBK.decode(&ffprobe_output.stdout);
-                decoded_gbk
-            }
-        };
-
-        if let Ok(probe_data) = serde_json::from_str::<FFProbeOutput>(&ffprobe_json) {
-            let mut combined_tags = probe_data.format.tags;
-            for stream in probe_data.streams {
-                if stream.codec_type == "audio" {
-                    combined_tags.extend(stream.tags);
-                    break;
-                }
-            }
-
-            metadata.title = combined_tags.get("title").cloned();
-            metadata.artist = combined_tags
-                .get("artist")
-                .or_else(|| combined_tags.get("ARTIST"))
-                .cloned();
-
-            lyrics = combined_tags
-                .get("lyrics")
-                .or_else(|| combined_tags.get("LYRICS"))
-                .cloned();
-
-            if lyrics.is_none() {
-                for (k, v) in &combined_tags {
-                    if k.to_lowercase().starts_with("lyrics") {
-                        lyrics = Some(v.clone());
-                        break;
-                    }
-                }
-            }
-
-            if metadata
-                .title
-                .as_ref()
-                .map(|t| t.trim().is_empty() || t.contains('\u{FFFD}'))
-                .unwrap_or(false)
-            {
-                metadata.title = None;
-            }
-            if metadata
-                .artist
-                .as_ref()
-                .map(|a| a.trim().is_empty() || a.contains('\u{FFFD}'))
-                .unwrap_or(false)
-            {
-                metadata.artist = None;
-            }
-        }
-    }
-
-    // Fallback to filename for title / artist if still missing
-    if let Some(file_stem_os) = std::path::Path::new(&path).file_stem() {
-        let file_stem_str = file_stem_os.to_string_lossy();
-        if metadata.title.is_none() {
-            metadata.title = Some(file_stem_str.to_string());
-        }
-        if metadata.artist.is_none() {
-            let parts: Vec<&str> = file_stem_str
-                .split('-')
-                .map(|s| s.trim())
-                .filter(|s| !s.is_empty())
-                .collect();
-            if parts.len() >= 2 {
-                metadata.artist = Some(parts[0].to_string());
-                if let Some(ref t) = metadata.title {
-                    if t == &file_stem_str {
-                        metadata.title = Some(parts[1..].join(" - "));
-                    }
-                }
-            }
-        }
-    }
-
-    // ========== 2. 提取封面 ==========
-    let art_tempdir = tempfile::tempdir().map_err(|e| format!("Failed to create temp dir: {}", e))?;
-    let art_output_path = art_tempdir.path().join("cover.jpg");
-
-    let mut art_cmd = std::process::Command::new("ffmpeg");
-    art_cmd
-        .arg("-hide_banner")
-        .arg("-loglevel")
-        .arg("error")
-        .arg("-i")
-        .arg(&path)
-        .arg("-an")
-        .arg("-vcodec")
-        .arg("copy")
-        .arg(art_output_path.to_str().unwrap());
-
-    #[cfg(windows)]
-    art_cmd.creation_flags(0x08000000);
-
-    let mut album_art_base64 = None;
-    if art_cmd.output().map(|o| o.status.success()).unwrap_or(false) {
-        if let Ok(data) = std::fs::read(&art_output_path) {
-            album_art_base64 = Some(general_purpose::STANDARD.encode(&data));
-        }
-    }
-
-    // ========== 3. 转码音频为 WAV，并保存到临时文件 ==========
-    // 使用系统临时目录，确保路径在 $TEMP 范围，方便 assetProtocol 访问。
-    let timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map_err(|e| format!("Time error: {}", e))?
-        .as_millis();
-    let cache_path = std::env::temp_dir().join(format!("imp_cache_{}.wav", timestamp));
-    let cache_path_str = cache_path
-        .to_str()
-        .ok_or_else(|| "Failed to convert cache path to string".to_string())?
-        .to_owned();
-
-    let mut wav_cmd = std::process::Command::new("ffmpeg");
-    wav_cmd
-        .arg("-hide_banner")
-        .arg("-loglevel")
-        .arg("error")
-        .arg("-i")
-        .arg(&path)
-        .arg("-ac")
-        .arg("2")
-        .arg("-y")
-        .arg(&cache_path_str);
-
-    #[cfg(windows)]
-    wav_cmd.creation_flags(0x08000000);
-
-    let status = wav_cmd
-        .status()
-        .map_err(|e| format!("ffmpeg command failed to run: {}", e))?;
-
-    if !status.success() {
-        return Err("ffmpeg command for wav failed".to_string());
-    }
-
-    Ok(PreparedFile {
-        metadata,
-        cache_path: cache_path_str,
-        album_art_base64,
-        lyrics,
+        lyrics: lyrics.as_deref().map(lyrics::parse),
     })
 }
 
+/// Deletes a one-off temp file. Files owned by the transcode cache are kept for replay and
+/// left to its eviction.
 #[tauri::command]
-fn cleanup_cached_file(path: String) -> Result<(), String> {
-    if path.trim().is_empty() {
+fn cleanup_cached_file(path: String, cache: tauri::State<'_, TranscodeCache>) -> Result<(), AppError> {
+    if path.trim().is_empty() || cache.contains(Path::new(&path)) {
         return Ok(());
     }
     match std::fs::remove_file(&path) {
         Ok(_) => Ok(()),
         Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
-        Err(e) => Err(format!("Failed to remove cached file: {}", e)),
+        Err(e) => Err(AppError::io("remove cached file", Some(Path::new(&path)), e)),
     }
 }
 
@@ -683,15 +296,54 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
+        .manage(scan::ScanJobs::default())
+        .manage(StreamRegistry::default())
+        .manage(TranscodeJobs::default())
+        .manage(Prefetcher::default())
+        .manage(waveform::WaveformJobs::default())
+        .manage(analysis::AnalysisJobs::default())
+        .register_asynchronous_uri_scheme_protocol(stream::STREAM_SCHEME, stream::handle_request)
         .invoke_handler(tauri::generate_handler![
             process_audio_file,
             get_system_fonts,
             get_font_data,
-            prepare_audio_file,
             cleanup_cached_file,
-            scan_music_folder
+            library::scan_music_folder,
+            library::get_library_roots,
+            library::remove_library_root,
+            library::get_library_tracks,
+            library::get_library_albums,
+            library::get_library_artists,
+            library::get_library_root_formats,
+            library::set_library_root_formats,
+            library::get_default_audio_extensions,
+            scan::start_library_scan,
+            scan::cancel_library_scan,
+            playlist::import_playlist,
+            playlist::export_playlist,
+            settings::get_settings,
+            settings::set_settings,
+            ffmpeg::get_ffmpeg_status,
+            ffmpeg::download_ffmpeg,
+            stream::close_audio_stream,
+            transcode::start_transcode_job,
+            transcode::cancel_transcode_job,
+            transcode::prefetch_tracks,
+            waveform::request_waveform,
+            analysis::request_analysis
         ])
         .setup(|app| {
+            let data_dir = app.path().app_data_dir()?;
+            let settings = settings::SettingsState::load(&data_dir);
+            ffmpeg::configure(&settings.get());
+            let cache_dir = app.path().app_cache_dir()?.join(cache::CACHE_DIR);
+            app.manage(TranscodeCache::open(cache_dir, settings.get().transcode_cache_limit()));
+            app.manage(settings);
+            app.manage(library::LibraryState::load(&data_dir));
+            app.manage(loudness::LoudnessStore::load(&data_dir));
+            let library_watcher = watcher::LibraryWatcher::start(app.handle());
+            app.manage(library_watcher);
+
             if cfg!(debug_assertions) {
                 app.handle().plugin(
                     tauri_plugin_log::Builder::default()
@@ -0,0 +1,940 @@
+use crate::cue::{self, VirtualTrack};
+use crate::error::AppError;
+use crate::formats::{FormatMatcher, FormatOptions, DEFAULT_EXTENSIONS};
+use crate::metadata;
+use crate::tags::Tags;
+use crate::watcher::LibraryWatcher;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use tauri::State;
+use walkdir::WalkDir;
+
+/// File name of the persisted index inside the app data directory.
+const LIBRARY_FILE: &str = "library.json";
+/// Bump whenever `TrackRecord` changes in a way old files cannot be read as.
+const LIBRARY_VERSION: u32 = 2;
+
+#[derive(Serialize, Clone)]
+pub struct AudioFile {
+    pub path: String,
+    pub name: String,
+    /// Time range within `path` for tracks cut from a single-file rip by a cue sheet.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<f64>,
+}
+
+/// One indexed file. `size` and `mtime` decide whether a rescan has to probe it again.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackRecord {
+    pub path: String,
+    pub name: String,
+    pub root: String,
+    pub size: u64,
+    pub mtime: u64,
+    #[serde(flatten)]
+    pub tags: Tags,
+    pub duration: Option<f64>,
+    /// Sheet this track was cut from; `None` for ordinary files.
+    #[serde(default)]
+    pub cue_sheet: Option<String>,
+    #[serde(default)]
+    pub cue_track: Option<u32>,
+    #[serde(default)]
+    pub start: Option<f64>,
+    #[serde(default)]
+    pub end: Option<f64>,
+}
+
+impl TrackRecord {
+    /// Index key: the path, plus the track number for tracks cut from one file.
+    pub(crate) fn key(&self) -> String {
+        match self.cue_track {
+            Some(number) => format!("{}#{}", self.path, number),
+            None => self.path.clone(),
+        }
+    }
+
+    pub(crate) fn audio_file(&self) -> AudioFile {
+        AudioFile {
+            path: self.path.clone(),
+            name: self.name.clone(),
+            start: self.start,
+            end: self.end,
+        }
+    }
+
+    /// Whether this record belongs to `path`, either as the file itself, a file below it,
+    /// or a track of a cue sheet at or below it.
+    fn lies_under(&self, path: &Path) -> bool {
+        Path::new(&self.path).starts_with(path)
+            || self
+                .cue_sheet
+                .as_deref()
+                .is_some_and(|sheet| Path::new(sheet).starts_with(path))
+    }
+
+    /// Artist used for album grouping: album artist first, then track artist.
+    fn grouping_artist(&self) -> Option<&str> {
+        self.tags
+            .album_artist
+            .as_deref()
+            .or(self.tags.artist.as_deref())
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumSummary {
+    pub title: String,
+    pub artist: Option<String>,
+    pub year: Option<u32>,
+    pub track_count: usize,
+    pub tracks: Vec<String>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtistSummary {
+    pub name: String,
+    pub album_count: usize,
+    pub track_count: usize,
+}
+
+#[derive(Serialize, Default, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct LibraryIndex {
+    version: u32,
+    roots: Vec<String>,
+    /// Format overrides per root; roots without an entry use `FormatOptions::default()`.
+    #[serde(default)]
+    root_formats: HashMap<String, FormatOptions>,
+    /// Keyed by `TrackRecord::key`.
+    tracks: BTreeMap<String, TrackRecord>,
+}
+
+/// Tauri-managed handle to the on-disk library index.
+pub struct LibraryState {
+    index: Mutex<LibraryIndex>,
+    file: PathBuf,
+}
+
+impl LibraryState {
+    /// Loads `library.json` from `data_dir`, starting empty if it is missing or unreadable.
+    pub fn load(data_dir: &Path) -> Self {
+        let file = data_dir.join(LIBRARY_FILE);
+        // The version is checked on its own first: older files may not parse as the
+        // current `TrackRecord` at all.
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32,
+        }
+        let mut index = match std::fs::read(&file) {
+            Ok(bytes) => match serde_json::from_slice::<Version>(&bytes) {
+                Ok(v) if v.version != LIBRARY_VERSION => {
+                    log::info!("Library index version changed, rebuilding on next scan");
+                    LibraryIndex::default()
+                }
+                _ => match serde_json::from_slice::<LibraryIndex>(&bytes) {
+                    Ok(index) => index,
+                    Err(e) => {
+                        log::warn!("Failed to parse library index {}: {}", file.display(), e);
+                        LibraryIndex::default()
+                    }
+                },
+            },
+            Err(_) => LibraryIndex::default(),
+        };
+        // Whatever is saved from here on is in the current format, even if no scan ran.
+        index.version = LIBRARY_VERSION;
+        LibraryState {
+            index: Mutex::new(index),
+            file,
+        }
+    }
+
+    fn save(&self, index: &LibraryIndex) -> Result<(), AppError> {
+        if let Some(dir) = self.file.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| AppError::io("create library directory", Some(dir), e))?;
+        }
+        let data = serde_json::to_vec(index).map_err(|e| AppError::Io {
+            action: "serialize library index".to_string(),
+            path: None,
+            message: e.to_string(),
+        })?;
+        // Write next to the real file and rename, so a crash never leaves a truncated index.
+        let tmp = self.file.with_extension("json.tmp");
+        std::fs::write(&tmp, data)
+            .map_err(|e| AppError::io("write library index", Some(&tmp), e))?;
+        std::fs::rename(&tmp, &self.file)
+            .map_err(|e| AppError::io("replace library index", Some(&self.file), e))
+    }
+
+    /// Copies the records currently indexed under `root`, so the scan can run without the lock.
+    fn snapshot_root(&self, root: &str) -> HashMap<String, TrackRecord> {
+        let index = self.index.lock().unwrap();
+        index
+            .tracks
+            .values()
+            .filter(|t| t.root == root)
+            .map(|t| (t.key(), t.clone()))
+            .collect()
+    }
+
+    /// Replaces everything indexed under `root` with `records` and persists the result.
+    fn replace_root(&self, root: &str, records: Vec<TrackRecord>) -> Result<(), AppError> {
+        let mut index = self.index.lock().unwrap();
+        if !index.roots.iter().any(|r| r == root) {
+            index.roots.push(root.to_string());
+        }
+        index.tracks.retain(|_, t| t.root != root);
+        for record in records {
+            index.tracks.insert(record.key(), record);
+        }
+        self.save(&index)
+    }
+
+    pub(crate) fn roots(&self) -> Vec<String> {
+        self.index.lock().unwrap().roots.clone()
+    }
+
+    /// Writes the current index to disk; used after incremental updates from the watcher.
+    pub(crate) fn persist(&self) -> Result<(), AppError> {
+        let index = self.index.lock().unwrap();
+        self.save(&index)
+    }
+
+    /// Stores format settings for `root`; see `set_library_root_formats`.
+    fn set_root_formats(&self, root: String, options: FormatOptions) -> Result<(), AppError> {
+        let mut index = self.index.lock().unwrap();
+        index.root_formats.insert(root, options);
+        self.save(&index)
+    }
+
+    fn matcher_for(&self, root: &str) -> FormatMatcher {
+        let index = self.index.lock().unwrap();
+        index
+            .root_formats
+            .get(root)
+            .map(FormatMatcher::new)
+            .unwrap_or_default()
+    }
+
+    /// The registered root containing `path`, preferring the most specific one.
+    fn root_for(&self, path: &Path) -> Option<String> {
+        let index = self.index.lock().unwrap();
+        index
+            .roots
+            .iter()
+            .filter(|root| path.starts_with(root))
+            .max_by_key(|root| root.len())
+            .cloned()
+    }
+
+    /// Indexes `path` (a file, or every file below a directory) if it lies inside a root.
+    pub(crate) fn index_path(&self, path: &Path) -> IndexChanges {
+        let mut changes = IndexChanges::default();
+        let Some(root) = self.root_for(path) else {
+            return changes;
+        };
+        let matcher = self.matcher_for(&root);
+        let lookup = |key: &str| self.index.lock().unwrap().tracks.get(key).cloned();
+        let walker = Walker {
+            root: &root,
+            matcher: &matcher,
+            known: &lookup,
+        };
+
+        // Probing happens inside the walk, so collect first and take the lock afterwards.
+        let mut found = Vec::new();
+        let Some(covered) = walker.walk(path, &mut (), &mut |record, status| {
+            found.push((record, status))
+        }) else {
+            return changes;
+        };
+
+        let mut index = self.index.lock().unwrap();
+        for (record, status) in found {
+            index.tracks.insert(record.key(), record.clone());
+            match status {
+                RecordStatus::Added => changes.added.push(record),
+                RecordStatus::Updated => changes.updated += 1,
+                RecordStatus::Unchanged => {}
+            }
+        }
+        // A new cue sheet replaces the whole-file record of the rip it describes.
+        for audio in covered {
+            if let Some(record) = index.tracks.remove(audio.to_string_lossy().as_ref()) {
+                changes.removed.push(record);
+            }
+        }
+        changes
+    }
+
+    /// Drops `path` (or everything below it) from the index, returning the removed records.
+    pub(crate) fn remove_path(&self, path: &Path) -> Vec<TrackRecord> {
+        let mut index = self.index.lock().unwrap();
+        let keys: Vec<String> = index
+            .tracks
+            .iter()
+            .filter(|(_, t)| t.lies_under(path))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.iter()
+            .filter_map(|key| index.tracks.remove(key))
+            .collect()
+    }
+
+    /// Re-keys records after `from` was renamed to `to`, keeping their probed tags.
+    /// Returns `(old record, moved record)` pairs plus the records that were dropped
+    /// because they now lie outside every root or have an unsupported extension.
+    pub(crate) fn move_path(
+        &self,
+        from: &Path,
+        to: &Path,
+    ) -> (Vec<(TrackRecord, TrackRecord)>, Vec<TrackRecord>) {
+        let root = self.root_for(to);
+        let matcher = root.as_deref().map(|r| self.matcher_for(r));
+        let rebase = |old: &str| {
+            let rel = Path::new(old).strip_prefix(from).unwrap_or(Path::new(""));
+            let new_path = if rel.as_os_str().is_empty() {
+                to.to_path_buf()
+            } else {
+                to.join(rel)
+            };
+            new_path.to_string_lossy().to_string()
+        };
+
+        let mut index = self.index.lock().unwrap();
+        let keys: Vec<String> = index
+            .tracks
+            .iter()
+            .filter(|(_, t)| t.lies_under(from))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        let mut moved = Vec::new();
+        let mut dropped = Vec::new();
+        for key in keys {
+            let Some(old) = index.tracks.remove(&key) else {
+                continue;
+            };
+            let mut record = old.clone();
+            if Path::new(&old.path).starts_with(from) {
+                record.path = rebase(&old.path);
+            }
+            if let Some(sheet) = old
+                .cue_sheet
+                .as_deref()
+                .filter(|s| Path::new(s).starts_with(from))
+            {
+                record.cue_sheet = Some(rebase(sheet));
+            }
+            // Cue tracks keep their sheet-derived name; the audio inside is unchanged.
+            let supported = record.cue_sheet.is_some()
+                || matcher
+                    .as_ref()
+                    .is_some_and(|m| m.matches(Path::new(&record.path)));
+            let Some(root) = root.clone().filter(|_| supported) else {
+                dropped.push(old);
+                continue;
+            };
+            if record.cue_sheet.is_none() {
+                record.name = Path::new(&record.path)
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string();
+            }
+            record.root = root;
+            index.tracks.insert(record.key(), record.clone());
+            moved.push((old, record));
+        }
+        (moved, dropped)
+    }
+}
+
+/// What an incremental `index_path` changed.
+#[derive(Default)]
+pub(crate) struct IndexChanges {
+    pub added: Vec<TrackRecord>,
+    pub updated: usize,
+    /// Whole-file records replaced by the tracks of a cue sheet.
+    pub removed: Vec<TrackRecord>,
+}
+
+fn file_stamp(meta: &std::fs::Metadata) -> (u64, u64) {
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    (meta.len(), mtime)
+}
+
+fn build_record(path: &Path, root: &str, size: u64, mtime: u64) -> TrackRecord {
+    let name = path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let mut record = TrackRecord {
+        path: path.to_string_lossy().to_string(),
+        name,
+        root: root.to_string(),
+        size,
+        mtime,
+        tags: Tags::default(),
+        duration: None,
+        cue_sheet: None,
+        cue_track: None,
+        start: None,
+        end: None,
+    };
+    if let Ok(probe) = metadata::probe(path) {
+        record.tags = probe.tags();
+        record.duration = probe.properties.duration;
+    }
+    record
+}
+
+/// Builds the record for one cue track. `size`/`mtime` are those of the audio file.
+fn cue_record(track: &VirtualTrack, root: &str, size: u64, mtime: u64) -> TrackRecord {
+    TrackRecord {
+        path: track.audio_path.to_string_lossy().to_string(),
+        name: track.display_name(),
+        root: root.to_string(),
+        size,
+        mtime,
+        tags: track.tags(),
+        duration: track.end.map(|end| end - track.start),
+        cue_sheet: Some(track.cue_path.to_string_lossy().to_string()),
+        cue_track: Some(track.number),
+        start: Some(track.start),
+        end: track.end,
+    }
+}
+
+/// Receives progress from `scan_root`. The unit type is the silent observer used by the
+/// blocking `scan_music_folder` command.
+pub(crate) trait ScanObserver {
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+    fn directory(&mut self, _path: &Path) {}
+    fn file(&mut self, _record: &TrackRecord) {}
+    fn error(&mut self, path: Option<&Path>, message: String) {
+        log::warn!(
+            "Skipping {}: {}",
+            path.map(|p| p.display().to_string()).unwrap_or_default(),
+            message
+        );
+    }
+}
+
+impl ScanObserver for () {}
+
+/// Whether a record produced by a walk is new, was re-probed, or was carried over.
+enum RecordStatus {
+    Added,
+    Updated,
+    Unchanged,
+}
+
+/// Produces the records for everything indexable below a path of one root, expanding
+/// cue sheets into their tracks and hiding the whole-file rips they describe.
+struct Walker<'a> {
+    root: &'a str,
+    matcher: &'a FormatMatcher,
+    /// Looks up what the index currently holds for a record key.
+    known: &'a dyn Fn(&str) -> Option<TrackRecord>,
+}
+
+/// Bookkeeping shared by one walk.
+#[derive(Default)]
+struct WalkState {
+    /// Audio files represented by cue tracks rather than a record of their own.
+    covered: HashSet<PathBuf>,
+    /// Keys already emitted; rips often ship the same sheet in two encodings.
+    seen: HashSet<String>,
+}
+
+impl Walker<'_> {
+    fn status(&self, key: &str, size: u64, mtime: u64) -> (RecordStatus, Option<TrackRecord>) {
+        match (self.known)(key) {
+            Some(existing) if existing.size == size && existing.mtime == mtime => {
+                (RecordStatus::Unchanged, Some(existing))
+            }
+            Some(_) => (RecordStatus::Updated, None),
+            None => (RecordStatus::Added, None),
+        }
+    }
+
+    /// Walks `start` (a directory, or a single file) and hands every record to `emit`.
+    /// Returns the audio files that are covered by cue sheets, or `None` if cancelled.
+    fn walk(
+        &self,
+        start: &Path,
+        observer: &mut dyn ScanObserver,
+        emit: &mut dyn FnMut(TrackRecord, RecordStatus),
+    ) -> Option<HashSet<PathBuf>> {
+        let mut state = WalkState::default();
+
+        // A single file may belong to a sheet next to it (or be the sheet itself).
+        if start.is_file() {
+            let sheets = match start.extension().and_then(|e| e.to_str()) {
+                Some(ext) if ext.eq_ignore_ascii_case("cue") => vec![start.to_path_buf()],
+                _ => start.parent().map(cue::sheets_in_dir).unwrap_or_default(),
+            };
+            for sheet in sheets {
+                self.expand_sheet(&sheet, &mut state, observer, emit);
+            }
+        }
+
+        for entry in WalkDir::new(start) {
+            if observer.is_cancelled() {
+                return None;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    observer.error(e.path(), e.to_string());
+                    continue;
+                }
+            };
+            let path = entry.path();
+            if entry.file_type().is_dir() {
+                observer.directory(path);
+                // Sheets are expanded on entering their directory, before its files are
+                // visited, so a covered rip is never reported as a track of its own.
+                for sheet in cue::sheets_in_dir(path) {
+                    self.expand_sheet(&sheet, &mut state, observer, emit);
+                }
+                continue;
+            }
+            if !entry.file_type().is_file()
+                || !self.matcher.matches(path)
+                || state.covered.contains(path)
+            {
+                continue;
+            }
+            let meta = match entry.metadata() {
+                Ok(meta) => meta,
+                Err(e) => {
+                    observer.error(Some(path), e.to_string());
+                    continue;
+                }
+            };
+            let (size, mtime) = file_stamp(&meta);
+            let key = path.to_string_lossy().to_string();
+            if !state.seen.insert(key.clone()) {
+                continue;
+            }
+
+            let (status, existing) = self.status(&key, size, mtime);
+            let record = existing.unwrap_or_else(|| build_record(path, self.root, size, mtime));
+            observer.file(&record);
+            emit(record, status);
+        }
+        Some(state.covered)
+    }
+
+    fn expand_sheet(
+        &self,
+        sheet: &Path,
+        state: &mut WalkState,
+        observer: &mut dyn ScanObserver,
+        emit: &mut dyn FnMut(TrackRecord, RecordStatus),
+    ) {
+        // Whole-file durations, probed at most once per file, for each file's last track.
+        let mut durations: HashMap<PathBuf, Option<f64>> = HashMap::new();
+
+        for track in cue::expand(sheet) {
+            let meta = match std::fs::metadata(&track.audio_path) {
+                Ok(meta) => meta,
+                Err(e) => {
+                    observer.error(Some(&track.audio_path), e.to_string());
+                    continue;
+                }
+            };
+            let (size, mtime) = file_stamp(&meta);
+            state.covered.insert(track.audio_path.clone());
+
+            let mut record = cue_record(&track, self.root, size, mtime);
+            let key = record.key();
+            if !state.seen.insert(key.clone()) {
+                continue;
+            }
+            let (status, existing) = self.status(&key, size, mtime);
+            // The sheet is cheap to re-read, so only the probed duration is carried over.
+            if let Some(existing) =
+                existing.filter(|e| e.start == record.start && e.end == record.end)
+            {
+                record.duration = existing.duration;
+            }
+            if record.duration.is_none() {
+                let total = *durations
+                    .entry(track.audio_path.clone())
+                    .or_insert_with(|| {
+                        metadata::probe(&track.audio_path)
+                            .ok()
+                            .and_then(|p| p.properties.duration)
+                    });
+                record.duration = total.map(|total| (total - track.start).max(0.0));
+            }
+            observer.file(&record);
+            emit(record, status);
+        }
+    }
+}
+
+/// Walks `root` and returns the full record set for it, re-probing only files whose
+/// size or mtime differ from `known`. Returns `None` if the observer cancelled the walk.
+fn scan_root(
+    root: &str,
+    known: &HashMap<String, TrackRecord>,
+    matcher: &FormatMatcher,
+    observer: &mut dyn ScanObserver,
+) -> Option<(Vec<TrackRecord>, ScanSummary)> {
+    let lookup = |key: &str| known.get(key).cloned();
+    let walker = Walker {
+        root,
+        matcher,
+        known: &lookup,
+    };
+    let mut records = Vec::new();
+    let mut summary = ScanSummary::default();
+
+    walker.walk(Path::new(root), observer, &mut |record, status| {
+        match status {
+            RecordStatus::Added => summary.added += 1,
+            RecordStatus::Updated => summary.updated += 1,
+            RecordStatus::Unchanged => summary.unchanged += 1,
+        }
+        records.push(record);
+    })?;
+    summary.removed = known
+        .len()
+        .saturating_sub(summary.unchanged + summary.updated);
+    Some((records, summary))
+}
+
+impl LibraryState {
+    /// Scans `root` incrementally and commits the result to the index. A cancelled scan
+    /// leaves the index untouched and returns `Ok(None)`.
+    pub(crate) fn index_root(
+        &self,
+        root: &str,
+        observer: &mut dyn ScanObserver,
+    ) -> Result<Option<(Vec<AudioFile>, ScanSummary)>, AppError> {
+        // Tags are optional for indexing, so a missing ffmpeg only costs us metadata.
+        if let Err(e) = crate::ffmpeg::resolve() {
+            log::warn!("ffprobe unavailable, indexing without tags: {}", e);
+        }
+
+        let known = self.snapshot_root(root);
+        let matcher = self.matcher_for(root);
+        let Some((records, summary)) = scan_root(root, &known, &matcher, observer) else {
+            return Ok(None);
+        };
+        log::info!("Scanned {}: {:?}", root, summary);
+
+        let mut files: Vec<AudioFile> = records.iter().map(TrackRecord::audio_file).collect();
+        self.replace_root(root, records)?;
+
+        files.sort_by_key(|f| f.name.to_lowercase());
+        Ok(Some((files, summary)))
+    }
+}
+
+#[tauri::command]
+pub fn scan_music_folder(
+    path: String,
+    library: State<'_, LibraryState>,
+    watcher: State<'_, LibraryWatcher>,
+) -> Result<Vec<AudioFile>, AppError> {
+    let (files, _) = library.index_root(&path, &mut ())?.unwrap_or_default();
+    watcher.watch(&path);
+    Ok(files)
+}
+
+#[tauri::command]
+pub fn get_library_roots(library: State<'_, LibraryState>) -> Vec<String> {
+    library.roots()
+}
+
+#[tauri::command]
+pub fn remove_library_root(
+    path: String,
+    library: State<'_, LibraryState>,
+    watcher: State<'_, LibraryWatcher>,
+) -> Result<(), AppError> {
+    watcher.unwatch(&path);
+    let mut index = library.index.lock().unwrap();
+    index.roots.retain(|r| r != &path);
+    index.root_formats.remove(&path);
+    index.tracks.retain(|_, t| t.root != path);
+    library.save(&index)
+}
+
+/// Format settings for one root; the defaults if it has never been customised.
+#[tauri::command]
+pub fn get_library_root_formats(path: String, library: State<'_, LibraryState>) -> FormatOptions {
+    let index = library.index.lock().unwrap();
+    index.root_formats.get(&path).cloned().unwrap_or_default()
+}
+
+/// Stores format settings for `path`. They take effect on the next scan of that root.
+#[tauri::command]
+pub fn set_library_root_formats(
+    path: String,
+    options: FormatOptions,
+    library: State<'_, LibraryState>,
+) -> Result<(), AppError> {
+    library.set_root_formats(path, options)
+}
+
+#[tauri::command]
+pub fn get_default_audio_extensions() -> Vec<String> {
+    DEFAULT_EXTENSIONS.iter().map(|e| e.to_string()).collect()
+}
+
+/// Returns indexed tracks, optionally restricted to one album and/or artist.
+#[tauri::command]
+pub fn get_library_tracks(
+    album: Option<String>,
+    artist: Option<String>,
+    library: State<'_, LibraryState>,
+) -> Vec<TrackRecord> {
+    let index = library.index.lock().unwrap();
+    let mut tracks: Vec<TrackRecord> = index
+        .tracks
+        .values()
+        .filter(|t| album.is_none() || t.tags.album == album)
+        .filter(|t| artist.is_none() || t.tags.artist == artist || t.tags.album_artist == artist)
+        .cloned()
+        .collect();
+    tracks.sort_by(|a, b| {
+        (
+            a.tags.disc_number,
+            a.tags.track_number,
+            a.name.to_lowercase(),
+        )
+            .cmp(&(
+                b.tags.disc_number,
+                b.tags.track_number,
+                b.name.to_lowercase(),
+            ))
+    });
+    tracks
+}
+
+#[tauri::command]
+pub fn get_library_albums(library: State<'_, LibraryState>) -> Vec<AlbumSummary> {
+    let index = library.index.lock().unwrap();
+    let mut albums: BTreeMap<(String, String), AlbumSummary> = BTreeMap::new();
+    for track in index.tracks.values() {
+        let Some(title) = track.tags.album.clone() else {
+            continue;
+        };
+        let artist = track.grouping_artist().map(|a| a.to_string());
+        let key = (
+            title.to_lowercase(),
+            artist.clone().unwrap_or_default().to_lowercase(),
+        );
+        let album = albums.entry(key).or_insert_with(|| AlbumSummary {
+            title,
+            artist,
+            year: None,
+            track_count: 0,
+            tracks: Vec::new(),
+        });
+        if album.year.is_none() {
+            album.year = track.tags.year;
+        }
+        album.track_count += 1;
+        album.tracks.push(track.path.clone());
+    }
+    albums.into_values().collect()
+}
+
+#[tauri::command]
+pub fn get_library_artists(library: State<'_, LibraryState>) -> Vec<ArtistSummary> {
+    let index = library.index.lock().unwrap();
+    // name (lower-cased) -> (display name, album titles, track count)
+    let mut artists: BTreeMap<String, (String, Vec<String>, usize)> = BTreeMap::new();
+    for track in index.tracks.values() {
+        let Some(name) = track.grouping_artist() else {
+            continue;
+        };
+        let entry = artists
+            .entry(name.to_lowercase())
+            .or_insert_with(|| (name.to_string(), Vec::new(), 0));
+        if let Some(album) = &track.tags.album {
+            if !entry.1.contains(album) {
+                entry.1.push(album.clone());
+            }
+        }
+        entry.2 += 1;
+    }
+    artists
+        .into_values()
+        .map(|(name, albums, track_count)| ArtistSummary {
+            name,
+            album_count: albums.len(),
+            track_count,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory with a music root and a data directory for `library.json`.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("library_{}_{}", std::process::id(), name));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(dir.join("music")).unwrap();
+            std::fs::create_dir_all(dir.join("data")).unwrap();
+            Scratch(dir)
+        }
+
+        fn root(&self) -> String {
+            self.0.join("music").to_string_lossy().to_string()
+        }
+
+        fn write(&self, name: &str, contents: &str) {
+            std::fs::write(self.0.join("music").join(name), contents).unwrap();
+        }
+
+        fn load(&self) -> LibraryState {
+            LibraryState::load(&self.0.join("data"))
+        }
+
+        fn index_file(&self) -> PathBuf {
+            self.0.join("data").join(LIBRARY_FILE)
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn scan(library: &LibraryState, root: &str) -> ScanSummary {
+        library.index_root(root, &mut ()).unwrap().unwrap().1
+    }
+
+    fn counts(summary: &ScanSummary) -> (usize, usize, usize, usize) {
+        (
+            summary.added,
+            summary.updated,
+            summary.removed,
+            summary.unchanged,
+        )
+    }
+
+    #[test]
+    fn rescan_only_probes_changed_files() {
+        let scratch = Scratch::new("rescan");
+        let root = scratch.root();
+        scratch.write("a.flac", "first");
+        scratch.write("b.mp3", "second");
+        scratch.write("cover.jpg", "image");
+        let library = scratch.load();
+
+        assert_eq!(counts(&scan(&library, &root)), (2, 0, 0, 0));
+        assert_eq!(counts(&scan(&library, &root)), (0, 0, 0, 2));
+
+        // Same mtime second, but a different size.
+        scratch.write("a.flac", "first, re-tagged");
+        std::fs::remove_file(scratch.0.join("music").join("b.mp3")).unwrap();
+        scratch.write("c.ogg", "third");
+        assert_eq!(counts(&scan(&library, &root)), (1, 1, 1, 0));
+
+        let sizes: Vec<(String, u64)> = library
+            .index
+            .lock()
+            .unwrap()
+            .tracks
+            .values()
+            .map(|t| (t.name.clone(), t.size))
+            .collect();
+        assert_eq!(sizes, [("a".to_string(), 16), ("c".to_string(), 5)]);
+    }
+
+    #[test]
+    fn index_survives_a_restart() {
+        let scratch = Scratch::new("restart");
+        let root = scratch.root();
+        scratch.write("a.flac", "first");
+        scan(&scratch.load(), &root);
+
+        let library = scratch.load();
+        assert_eq!(library.roots(), [root.as_str()]);
+        assert_eq!(counts(&scan(&library, &root)), (0, 0, 0, 1));
+    }
+
+    #[test]
+    fn formats_saved_before_any_scan_are_kept() {
+        let scratch = Scratch::new("formats");
+        let options = FormatOptions {
+            extensions: Some(vec!["flac".to_string()]),
+            sniff_content: false,
+        };
+        scratch
+            .load()
+            .set_root_formats(scratch.root(), options)
+            .unwrap();
+
+        let library = scratch.load();
+        let index = library.index.lock().unwrap();
+        let kept = &index.root_formats[&scratch.root()];
+        assert_eq!(kept.extensions.as_deref(), Some(&["flac".to_string()][..]));
+        assert!(!kept.sniff_content);
+    }
+
+    #[test]
+    fn other_versions_are_discarded() {
+        let scratch = Scratch::new("version");
+        let root = scratch.root();
+        let stale = serde_json::json!({
+            "version": LIBRARY_VERSION - 1,
+            "roots": [root],
+            "tracks": {},
+        });
+        std::fs::write(scratch.index_file(), stale.to_string()).unwrap();
+        assert!(scratch.load().roots().is_empty());
+
+        std::fs::write(scratch.index_file(), "not json").unwrap();
+        assert!(scratch.load().roots().is_empty());
+
+        let current = serde_json::json!({
+            "version": LIBRARY_VERSION,
+            "roots": [root],
+            "tracks": {},
+        });
+        std::fs::write(scratch.index_file(), current.to_string()).unwrap();
+        assert_eq!(scratch.load().roots(), [root.as_str()]);
+    }
+}
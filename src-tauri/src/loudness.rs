@@ -0,0 +1,468 @@
+use crate::error::AppError;
+use crate::ffmpeg;
+use crate::tags::{self, Tags};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+pub const LOUDNESS_MEASURED_EVENT: &str = "loudness-measured";
+
+/// File name of the persisted measurements inside the app data directory.
+const LOUDNESS_FILE: &str = "loudness.json";
+/// Bump when `Measurement` or the keys change in a way old files cannot be read as.
+const LOUDNESS_VERSION: u32 = 1;
+/// ReplayGain 2.0 reference level, in LUFS.
+const REFERENCE_LUFS: f64 = -18.0;
+/// R128 gain tags are relative to -23 LUFS, 5 dB below the ReplayGain reference.
+const R128_OFFSET_DB: f64 = 5.0;
+
+/// Which gain the player applies.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum GainMode {
+    #[default]
+    Off,
+    Track,
+    Album,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum LoudnessSource {
+    /// `REPLAYGAIN_*` tags.
+    ReplayGain,
+    /// `R128_*` tags (Opus).
+    R128,
+    /// Measured with ffmpeg's `ebur128` filter.
+    Measured,
+}
+
+/// ReplayGain-style adjustment for a track or album.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Gain {
+    /// dB to add to reach the -18 LUFS reference.
+    pub gain: f64,
+    /// Linear peak, 1.0 being full scale; a true peak when measured.
+    pub peak: Option<f64>,
+    pub source: LoudnessSource,
+}
+
+impl Gain {
+    /// The gain, reduced where needed so the peak does not clip.
+    pub fn clipless(&self) -> f64 {
+        match self.peak {
+            Some(peak) if peak > 0.0 => self.gain.min(-20.0 * peak.log10()),
+            _ => self.gain,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Loudness {
+    pub track: Option<Gain>,
+    pub album: Option<Gain>,
+}
+
+impl Loudness {
+    /// The gain to apply in `mode`, falling back to the other kind when one is missing.
+    pub fn gain(&self, mode: GainMode) -> Option<Gain> {
+        match mode {
+            GainMode::Off => None,
+            GainMode::Track => self.track.or(self.album),
+            GainMode::Album => self.album.or(self.track),
+        }
+    }
+
+    /// Reads ReplayGain and R128 tags from the probed tags. For a track cut from a single
+    /// file by a cue sheet (`whole_file` false) the track values describe the whole file
+    /// and are ignored; the album values still apply.
+    pub fn from_tags(raw: &HashMap<String, String>, whole_file: bool) -> Self {
+        let map: HashMap<String, &str> = raw
+            .iter()
+            .map(|(k, v)| (tags::normalise_key(k), v.as_str()))
+            .collect();
+        // "-6.54 dB" or "0.988831"; the unit is optional.
+        let number = |key: &str| -> Option<f64> {
+            map.get(key)?
+                .split_whitespace()
+                .next()?
+                .parse()
+                .ok()
+                .filter(|v: &f64| v.is_finite())
+        };
+        let gain = |kind: &str| -> Option<Gain> {
+            if let Some(gain) = number(&format!("replaygain{}gain", kind)) {
+                return Some(Gain {
+                    gain,
+                    peak: number(&format!("replaygain{}peak", kind)),
+                    source: LoudnessSource::ReplayGain,
+                });
+            }
+            // Q7.8 fixed point.
+            number(&format!("r128{}gain", kind)).map(|q| Gain {
+                gain: q / 256.0 + R128_OFFSET_DB,
+                peak: None,
+                source: LoudnessSource::R128,
+            })
+        };
+        Loudness {
+            track: gain("track").filter(|_| whole_file),
+            album: gain("album"),
+        }
+    }
+
+    /// Fills values missing from the tags with measurements.
+    pub fn or_measured(self, track: Option<&Measurement>, album: Option<&Measurement>) -> Self {
+        Loudness {
+            track: self.track.or_else(|| track.map(Measurement::gain)),
+            album: self.album.or_else(|| album.map(Measurement::gain)),
+        }
+    }
+}
+
+/// Result of an `ebur128` run.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Measurement {
+    /// Integrated loudness in LUFS.
+    pub integrated: f64,
+    /// True peak in dBTP; `None` for digital silence.
+    pub true_peak: Option<f64>,
+    /// Seconds; weights the track in its album.
+    pub duration: f64,
+}
+
+impl Measurement {
+    fn gain(&self) -> Gain {
+        Gain {
+            gain: REFERENCE_LUFS - self.integrated,
+            peak: self.true_peak.map(|db| 10f64.powf(db / 20.0)),
+            source: LoudnessSource::Measured,
+        }
+    }
+}
+
+/// An ffmpeg command measuring the loudness of `path` (or of `start..end` of it), with
+/// progress on stdout. The result is printed to stderr; see `parse_summary`.
+pub fn measure_command(
+    path: &Path,
+    start: Option<f64>,
+    end: Option<f64>,
+) -> Result<Command, AppError> {
+    let mut cmd = ffmpeg::ffmpeg_command()?;
+    cmd.arg("-hide_banner")
+        .arg("-nostats")
+        .arg("-progress")
+        .arg("pipe:1");
+    if let Some(start) = start {
+        cmd.arg("-ss").arg(format!("{:.3}", start));
+    }
+    cmd.arg("-i").arg(path);
+    if let Some(end) = end {
+        cmd.arg("-t")
+            .arg(format!("{:.3}", end - start.unwrap_or(0.0)));
+    }
+    cmd.arg("-vn")
+        .arg("-af")
+        // The summary is all that is read; per-frame lines would be ten a second.
+        .arg("ebur128=peak=true:framelog=quiet")
+        .arg("-f")
+        .arg("null")
+        .arg("-");
+    Ok(cmd)
+}
+
+/// Reads integrated loudness and true peak from the summary `ebur128` logs at the end:
+///
+/// ```text
+///   Integrated loudness:
+///     I:         -16.3 LUFS
+///   ...
+///   True peak:
+///     Peak:        0.4 dBFS
+/// ```
+pub fn parse_summary(stderr: &str, duration: Option<f64>) -> Option<Measurement> {
+    let summary = &stderr[stderr.rfind("Summary:")?..];
+    let value = |section: &str, label: &str| -> Option<f64> {
+        summary[summary.find(section)?..]
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(label))?
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()
+    };
+    Some(Measurement {
+        integrated: value("Integrated loudness:", "I:").filter(|i: &f64| i.is_finite())?,
+        true_peak: value("True peak:", "Peak:").filter(|p: &f64| p.is_finite()),
+        duration: duration.unwrap_or(0.0),
+    })
+}
+
+/// Identifies a measured track and the album it belongs to.
+pub struct TrackKey {
+    key: String,
+    /// (size, mtime) of the file; a changed file is measured again.
+    stamp: (u64, u64),
+    /// Album artist, album and directory; `None` without an album tag.
+    album: Option<String>,
+    /// Tracks expected on the album, from the track-total tag.
+    album_tracks: Option<u32>,
+}
+
+impl TrackKey {
+    pub fn new(
+        path: &Path,
+        start: Option<f64>,
+        end: Option<f64>,
+        tags: &Tags,
+    ) -> Result<Self, AppError> {
+        let meta = std::fs::metadata(path).map_err(|e| AppError::io("read", Some(path), e))?;
+        let mtime = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let album = tags.album.as_ref().map(|album| {
+            format!(
+                "{}|{}|{}",
+                path.parent()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default(),
+                tags.album_artist
+                    .as_deref()
+                    .or(tags.artist.as_deref())
+                    .unwrap_or(""),
+                album
+            )
+        });
+        Ok(TrackKey {
+            key: format!("{}|{:?}|{:?}", path.display(), start, end),
+            stamp: (meta.len(), mtime),
+            album,
+            album_tracks: tags.track_total,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct TrackEntry {
+    stamp: (u64, u64),
+    album: Option<String>,
+    measurement: Measurement,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct LoudnessIndex {
+    version: u32,
+    tracks: HashMap<String, TrackEntry>,
+    /// Combined measurements of albums whose tracks have all been measured.
+    albums: HashMap<String, Measurement>,
+}
+
+/// Tauri-managed store of loudness measurements, per track and per album.
+pub struct LoudnessStore {
+    index: Mutex<LoudnessIndex>,
+    file: PathBuf,
+}
+
+impl LoudnessStore {
+    /// Loads `loudness.json` from `data_dir`, starting empty if it is missing or unreadable.
+    pub fn load(data_dir: &Path) -> Self {
+        let file = data_dir.join(LOUDNESS_FILE);
+        let index = match std::fs::read(&file) {
+            Ok(bytes) => match serde_json::from_slice::<LoudnessIndex>(&bytes) {
+                Ok(index) if index.version == LOUDNESS_VERSION => index,
+                Ok(_) => LoudnessIndex::default(),
+                Err(e) => {
+                    log::warn!("Failed to parse loudness store {}: {}", file.display(), e);
+                    LoudnessIndex::default()
+                }
+            },
+            Err(_) => LoudnessIndex::default(),
+        };
+        LoudnessStore {
+            index: Mutex::new(index),
+            file,
+        }
+    }
+
+    /// Stored measurements of the track and its album, if any.
+    pub fn get(&self, key: &TrackKey) -> (Option<Measurement>, Option<Measurement>) {
+        let index = self.index.lock().unwrap();
+        let track = index
+            .tracks
+            .get(&key.key)
+            .filter(|e| e.stamp == key.stamp)
+            .map(|e| e.measurement);
+        let album = key
+            .album
+            .as_ref()
+            .and_then(|album| index.albums.get(album))
+            .copied();
+        (track, album)
+    }
+
+    /// Stores a track measurement. Once as many tracks of its album as the track-total
+    /// tag promises are measured, the album measurement is derived from them: the
+    /// duration-weighted energy mean of their loudness and the highest peak.
+    pub fn insert(&self, key: &TrackKey, measurement: Measurement) {
+        let mut index = self.index.lock().unwrap();
+        index.version = LOUDNESS_VERSION;
+        index.tracks.insert(
+            key.key.clone(),
+            TrackEntry {
+                stamp: key.stamp,
+                album: key.album.clone(),
+                measurement,
+            },
+        );
+        if let Some(album) = &key.album {
+            let members: Vec<Measurement> = index
+                .tracks
+                .values()
+                .filter(|e| e.album.as_ref() == Some(album))
+                .map(|e| e.measurement)
+                .collect();
+            let complete = key
+                .album_tracks
+                .is_some_and(|total| members.len() >= total as usize);
+            let duration: f64 = members.iter().map(|m| m.duration).sum();
+            if complete && duration > 0.0 {
+                let energy: f64 = members
+                    .iter()
+                    .map(|m| m.duration * 10f64.powf(m.integrated / 10.0))
+                    .sum();
+                let combined = Measurement {
+                    integrated: 10.0 * (energy / duration).log10(),
+                    true_peak: members.iter().filter_map(|m| m.true_peak).reduce(f64::max),
+                    duration,
+                };
+                index.albums.insert(album.clone(), combined);
+            }
+        }
+        if let Err(e) = self.save(&index) {
+            log::warn!("Failed to save loudness store: {}", e);
+        }
+    }
+
+    fn save(&self, index: &LoudnessIndex) -> Result<(), AppError> {
+        if let Some(dir) = self.file.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| AppError::io("create loudness directory", Some(dir), e))?;
+        }
+        let data = serde_json::to_vec(index).map_err(|e| AppError::Io {
+            action: "serialize loudness store".to_string(),
+            path: None,
+            message: e.to_string(),
+        })?;
+        let tmp = self.file.with_extension("json.tmp");
+        std::fs::write(&tmp, data)
+            .map_err(|e| AppError::io("write loudness store", Some(&tmp), e))?;
+        std::fs::rename(&tmp, &self.file)
+            .map_err(|e| AppError::io("replace loudness store", Some(&self.file), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STDERR: &str = "\
+[Parsed_ebur128_0 @ 0x5581] t: 0.4  TARGET:-23 LUFS    M:-120.7 S:-120.7     I: -70.0 LUFS       LRA:   0.0 LU  FTPK: -3.2 -3.4 dBFS  TPK: -3.2 -3.4 dBFS
+[Parsed_ebur128_0 @ 0x5581] Summary:
+
+  Integrated loudness:
+    I:         -16.3 LUFS
+    Threshold: -26.6 LUFS
+
+  Loudness range:
+    LRA:         6.2 LU
+    Threshold: -36.6 LUFS
+    LRA low:   -21.0 LUFS
+    LRA high:  -14.8 LUFS
+
+  True peak:
+    Peak:        0.4 dBFS
+";
+
+    #[test]
+    fn summary_values_ignore_progress_lines() {
+        let measurement = parse_summary(STDERR, Some(215.0)).unwrap();
+        assert_eq!(
+            measurement,
+            Measurement {
+                integrated: -16.3,
+                true_peak: Some(0.4),
+                duration: 215.0,
+            }
+        );
+    }
+
+    #[test]
+    fn summary_after_long_frame_log() {
+        // Hours of per-frame lines, far more than a pipe holds, before the summary.
+        let frame = STDERR.lines().next().unwrap();
+        let mut stderr = format!("{}\n", frame).repeat(100_000);
+        stderr.push_str(STDERR);
+        let tail = crate::ffmpeg::drain_stderr(std::io::Cursor::new(stderr.into_bytes()))
+            .join()
+            .unwrap();
+        assert!(tail.len() < 100_000);
+        let measurement = parse_summary(&String::from_utf8_lossy(&tail), None).unwrap();
+        assert_eq!(measurement.integrated, -16.3);
+        assert_eq!(measurement.true_peak, Some(0.4));
+    }
+
+    #[test]
+    fn silent_peak_is_missing() {
+        let stderr = STDERR.replace("Peak:        0.4 dBFS", "Peak:       -inf dBFS");
+        let measurement = parse_summary(&stderr, None).unwrap();
+        assert_eq!(measurement.true_peak, None);
+        assert_eq!(measurement.duration, 0.0);
+    }
+
+    #[test]
+    fn summary_is_required() {
+        assert_eq!(parse_summary("", None), None);
+        let truncated = &STDERR[..STDERR.find("Summary:").unwrap()];
+        assert_eq!(parse_summary(truncated, None), None);
+        let no_integrated = STDERR.replace("I:         -16.3 LUFS", "I:         nan LUFS");
+        assert_eq!(parse_summary(&no_integrated, None), None);
+    }
+
+    #[test]
+    fn measured_gain_is_relative_to_reference() {
+        let gain = parse_summary(STDERR, None).unwrap().gain();
+        assert!((gain.gain - -1.7).abs() < 1e-9);
+        assert!((gain.peak.unwrap() - 10f64.powf(0.02)).abs() < 1e-9);
+        assert_eq!(gain.source, LoudnessSource::Measured);
+    }
+
+    #[test]
+    fn replaygain_and_r128_tags() {
+        let raw = HashMap::from([
+            ("REPLAYGAIN_TRACK_GAIN".to_string(), "-6.54 dB".to_string()),
+            ("REPLAYGAIN_TRACK_PEAK".to_string(), "0.988831".to_string()),
+            ("R128_ALBUM_GAIN".to_string(), "-512".to_string()),
+        ]);
+        let loudness = Loudness::from_tags(&raw, true);
+        let track = loudness.track.unwrap();
+        assert_eq!(track.gain, -6.54);
+        assert_eq!(track.peak, Some(0.988831));
+        assert_eq!(track.source, LoudnessSource::ReplayGain);
+        let album = loudness.album.unwrap();
+        assert_eq!(album.gain, 3.0);
+        assert_eq!(album.source, LoudnessSource::R128);
+
+        // A cue track only inherits album values from its whole-file tags.
+        assert_eq!(Loudness::from_tags(&raw, false).track, None);
+    }
+}
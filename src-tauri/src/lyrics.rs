@@ -0,0 +1,484 @@
+use crate::{pairing, subtitles, ttml};
+use serde::Serialize;
+
+/// A last word with no closing timestamp lasts until the next line, or this long (in
+/// seconds) after it starts when there is no later line.
+const LAST_WORD_DURATION: f64 = 1.0;
+const FINAL_WORD_DURATION: f64 = 2.0;
+
+/// A word (or syllable) of a line with word-level timing, in seconds. `text` keeps its
+/// surrounding spaces, so the words of a line concatenate to the line as written.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricWord {
+    pub text: String,
+    pub start_time: f64,
+    pub end_time: f64,
+}
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricLine {
+    /// Seconds from the start of the track, with the file's `[offset:]` applied.
+    pub time: f64,
+    pub text: String,
+    /// Empty unless the line has word-level timing.
+    pub words: Vec<LyricWord>,
+    /// The line in another language: marked as such by the format (TTML), a line sharing
+    /// this one's time (bilingual LRC and subtitles), or a separate lyrics tag or file.
+    pub translation: Option<String>,
+    /// Transliteration of `text` into Latin script (romaji, pinyin), found the same ways
+    /// or generated by `romanize::fill`.
+    pub romanization: Option<String>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum LyricsSourceKind {
+    /// Read from the audio file's tags.
+    Embedded,
+    /// Read from a lyrics file found by `sidecar::find`.
+    Sidecar,
+}
+
+/// Where a track's lyrics came from.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricsSource {
+    pub kind: LyricsSourceKind,
+    /// The sidecar file; `None` for embedded lyrics.
+    pub path: Option<String>,
+}
+
+/// A parsed lyrics file: its ID tags and the timed lines in playback order, each with its
+/// translation and romanization paired in by `pairing::pair_lines`.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Lyrics {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Who made the lyrics file (`[by:]`).
+    pub author: Option<String>,
+    /// `[offset:]` in milliseconds, already applied to all times.
+    pub offset_ms: i64,
+    pub lines: Vec<LyricLine>,
+    /// Plain text without timestamps: every line is at 0, and the lyrics are shown as a
+    /// static page rather than following playback.
+    pub unsynced: bool,
+    /// Set by `sidecar::resolve`; `None` straight from a parser.
+    pub source: Option<LyricsSource>,
+}
+
+/// Parses a timestamp body such as `01:23.45`, `01:23:45` or `01:23` to seconds. One to
+/// three fraction digits are read as tenths, hundredths or milliseconds.
+fn parse_timestamp(stamp: &str) -> Option<f64> {
+    let (minutes, rest) = stamp.trim().split_once(':')?;
+    let (seconds, fraction) = match rest.split_once(['.', ':']) {
+        Some((seconds, fraction)) => (seconds, fraction),
+        None => (rest, ""),
+    };
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !all_digits(minutes) || !all_digits(seconds) || seconds.len() > 2 {
+        return None;
+    }
+    if !fraction.is_empty() && (!all_digits(fraction) || fraction.len() > 3) {
+        return None;
+    }
+    let millis = if fraction.is_empty() {
+        0
+    } else {
+        format!("{:0<3}", fraction).parse::<u32>().ok()?
+    };
+    let minutes: u32 = minutes.parse().ok()?;
+    let seconds: u32 = seconds.parse().ok()?;
+    Some(f64::from(minutes) * 60.0 + f64::from(seconds) + f64::from(millis) / 1000.0)
+}
+
+/// Splits `text` at timestamps enclosed in `open`/`close` into the text before the first
+/// one and `(time, text)` segments.
+fn split_stamps(text: &str, open: char, close: char) -> (&str, Vec<(f64, &str)>) {
+    // (time, where the stamp starts, where its text starts) of each stamp.
+    let mut stamps = Vec::new();
+    let mut pos = 0;
+    while let Some(start) = text[pos..].find(open).map(|i| pos + i) {
+        let Some(end) = text[start..].find(close).map(|i| start + i) else {
+            break;
+        };
+        match parse_timestamp(&text[start + 1..end]) {
+            Some(time) => {
+                stamps.push((time, start, end + 1));
+                pos = end + 1;
+            }
+            // Not a timestamp; keep it as text and look further on.
+            None => pos = start + 1,
+        }
+    }
+    let lead = &text[..stamps.first().map_or(text.len(), |s| s.1)];
+    let segments = stamps
+        .iter()
+        .enumerate()
+        .map(|(i, &(time, _, text_start))| {
+            let text_end = stamps.get(i + 1).map_or(text.len(), |s| s.1);
+            (time, &text[text_start..text_end])
+        })
+        .collect();
+    (lead, segments)
+}
+
+/// Word timings of a line's text: enhanced LRC `<mm:ss.xx>` stamps or, as some editors
+/// write them, further `[mm:ss.xx]` stamps inside the text. Returns the words (the end of
+/// the last one unknown, `NaN`) and the line text without stamps.
+fn parse_words(time: f64, text: &str) -> Option<(Vec<LyricWord>, String)> {
+    let (lead, segments) = match split_stamps(text, '<', '>') {
+        (lead, segments) if !segments.is_empty() => (lead, segments),
+        _ => split_stamps(text, '[', ']'),
+    };
+    if segments.is_empty() {
+        return None;
+    }
+    let mut words = Vec::new();
+    if !lead.trim().is_empty() {
+        words.push(LyricWord {
+            text: lead.to_string(),
+            start_time: time,
+            end_time: segments[0].0,
+        });
+    }
+    for (i, &(start, text)) in segments.iter().enumerate() {
+        let end = segments.get(i + 1).map_or(f64::NAN, |&(next, _)| next);
+        if text.is_empty() {
+            // A trailing stamp with nothing after it marks the end of the previous word.
+            if let Some(previous) = words.last_mut().filter(|w| w.end_time.is_nan()) {
+                previous.end_time = start;
+            }
+            continue;
+        }
+        words.push(LyricWord {
+            text: text.to_string(),
+            start_time: start,
+            end_time: end,
+        });
+    }
+    let line: String = words.iter().map(|w| w.text.as_str()).collect();
+    Some((words, line.trim().to_string()))
+}
+
+/// Parses LRC lyrics: `[mm:ss.xx]` line timestamps (several on a line repeat it), the
+/// `[ti:]`, `[ar:]`, `[al:]`, `[by:]` and `[offset:]` ID tags, and word-level timing from
+/// enhanced (A2) `<mm:ss.xx>` stamps. Lines without a timestamp are ignored.
+pub fn parse_lrc(text: &str) -> Lyrics {
+    let mut lyrics = Lyrics::default();
+    // Timed entries, including empty ones, which only mark where the previous line ends.
+    let mut entries: Vec<LyricLine> = Vec::new();
+    for line in text.split(['\r', '\n']) {
+        let mut rest = line.trim();
+        let mut times = Vec::new();
+        while let Some(tag) = rest.strip_prefix('[') {
+            let Some(len) = tag.find(']') else {
+                break;
+            };
+            let body = &tag[..len];
+            if let Some(time) = parse_timestamp(body) {
+                times.push(time);
+                rest = tag[len + 1..].trim_start();
+                continue;
+            }
+            // ID tags stand on their own line; after a timestamp this is just text.
+            if times.is_empty() {
+                if let Some((key, value)) = body.split_once(':') {
+                    let value = value.trim();
+                    let value = (!value.is_empty()).then(|| value.to_string());
+                    match key.trim().to_ascii_lowercase().as_str() {
+                        "ti" => lyrics.title = value,
+                        "ar" => lyrics.artist = value,
+                        "al" => lyrics.album = value,
+                        "by" => lyrics.author = value,
+                        "offset" => {
+                            lyrics.offset_ms = value.and_then(|v| v.parse().ok()).unwrap_or(0)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            break;
+        }
+        let Some(&first) = times.first() else {
+            continue;
+        };
+        let (words, text) = match parse_words(first, rest) {
+            Some((words, text)) => (words, text),
+            None => (Vec::new(), rest.trim().to_string()),
+        };
+        for &time in &times {
+            // Word times are written for the first occurrence; shift them for repeats.
+            let shift = time - first;
+            let words = words
+                .iter()
+                .map(|w| LyricWord {
+                    text: w.text.clone(),
+                    start_time: w.start_time + shift,
+                    end_time: w.end_time + shift,
+                })
+                .collect();
+            entries.push(LyricLine {
+                time,
+                text: text.clone(),
+                words,
+                ..LyricLine::default()
+            });
+        }
+    }
+
+    // Stable, so lines sharing a time keep their file order.
+    entries.sort_by(|a, b| a.time.total_cmp(&b.time));
+    for i in 0..entries.len() {
+        let time = entries[i].time;
+        let next = entries[i + 1..].iter().map(|e| e.time).find(|&t| t > time);
+        if let Some(last) = entries[i].words.last_mut().filter(|w| w.end_time.is_nan()) {
+            last.end_time = match next {
+                Some(next) if next > last.start_time => next,
+                Some(_) => last.start_time + LAST_WORD_DURATION,
+                None => last.start_time + FINAL_WORD_DURATION,
+            };
+        }
+    }
+
+    // A positive offset shows the lyrics earlier.
+    let offset = lyrics.offset_ms as f64 / 1000.0;
+    for entry in &mut entries {
+        entry.time = (entry.time - offset).max(0.0);
+        for word in &mut entry.words {
+            word.start_time = (word.start_time - offset).max(0.0);
+            word.end_time = (word.end_time - offset).max(0.0);
+        }
+    }
+    entries.retain(|e| !e.text.is_empty());
+    lyrics.lines = entries;
+    lyrics
+}
+
+/// Reads lyrics without any timestamps (`.txt` files, most unsynchronised lyrics tags) as
+/// untimed lines, keeping the ID tags `parse_lrc` found in `lyrics`.
+fn parse_plain(text: &str, lyrics: Lyrics) -> Lyrics {
+    let is_id_tag = |line: &str| line.starts_with('[') && line.ends_with(']') && line.contains(':');
+    let lines = text
+        .split(['\r', '\n'])
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !is_id_tag(line))
+        .map(|line| LyricLine {
+            text: line.to_string(),
+            ..LyricLine::default()
+        })
+        .collect();
+    Lyrics {
+        lines,
+        unsynced: true,
+        ..lyrics
+    }
+}
+
+/// The lyrics and subtitle formats `parse` understands.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LyricsFormat {
+    Lrc,
+    Ttml,
+    /// SRT and WebVTT, which differ only in details the parser tolerates.
+    Srt,
+    Ass,
+}
+
+impl LyricsFormat {
+    /// Recognises the format from the content, so mislabelled files and lyrics embedded
+    /// in tags work too. Anything unrecognised is read as LRC.
+    pub fn detect(text: &str) -> Self {
+        let start = text.trim_start_matches('\u{feff}').trim_start();
+        let head = start
+            .char_indices()
+            .nth(512)
+            .map_or(start, |(i, _)| &start[..i]);
+        if start.starts_with('<') && (head.contains("<tt") || head.contains("<?xml")) {
+            return LyricsFormat::Ttml;
+        }
+        if start.starts_with("WEBVTT") {
+            return LyricsFormat::Srt;
+        }
+        if head.to_ascii_lowercase().contains("[script info]") {
+            return LyricsFormat::Ass;
+        }
+        // An SRT starts with a cue number and a timing line.
+        let mut lines = start.lines().map(str::trim).filter(|l| !l.is_empty());
+        let numbered = lines
+            .next()
+            .is_some_and(|l| l.bytes().all(|b| b.is_ascii_digit()));
+        if numbered && lines.next().is_some_and(|l| l.contains("-->")) {
+            return LyricsFormat::Srt;
+        }
+        LyricsFormat::Lrc
+    }
+}
+
+/// Parses lyrics in any supported format into the common timed model, with translation
+/// and romanization lines paired with the lines they belong to. LRC without a single
+/// timestamp is plain text and comes back `unsynced`.
+pub fn parse(text: &str) -> Lyrics {
+    let mut lyrics = match LyricsFormat::detect(text) {
+        LyricsFormat::Lrc => match parse_lrc(text) {
+            lyrics if lyrics.lines.is_empty() => return parse_plain(text, lyrics),
+            lyrics => lyrics,
+        },
+        LyricsFormat::Ttml => ttml::parse(text),
+        LyricsFormat::Srt => subtitles::parse_srt(text),
+        LyricsFormat::Ass => subtitles::parse_ass(text),
+    };
+    lyrics.lines = pairing::pair_lines(std::mem::take(&mut lyrics.lines));
+    lyrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str, start_time: f64, end_time: f64) -> LyricWord {
+        LyricWord {
+            text: text.to_string(),
+            start_time,
+            end_time,
+        }
+    }
+
+    #[test]
+    fn timestamp_fractions() {
+        assert_eq!(parse_timestamp("01:23.45"), Some(83.45));
+        assert_eq!(parse_timestamp("01:23.4"), Some(83.4));
+        assert_eq!(parse_timestamp("01:23.456"), Some(83.456));
+        assert_eq!(parse_timestamp("01:23:45"), Some(83.45));
+        assert_eq!(parse_timestamp("01:23"), Some(83.0));
+        assert_eq!(parse_timestamp("ar:Artist"), None);
+        assert_eq!(parse_timestamp("01:234"), None);
+    }
+
+    #[test]
+    fn id_tags_and_sorted_lines() {
+        let lyrics = parse_lrc(
+            "[ti:Song]\n[ar:Artist]\n[al:Album]\n[by:Someone]\n[00:05.00]Second\r\n[00:01.00]First\nno timestamp\n",
+        );
+        assert_eq!(lyrics.title.as_deref(), Some("Song"));
+        assert_eq!(lyrics.artist.as_deref(), Some("Artist"));
+        assert_eq!(lyrics.album.as_deref(), Some("Album"));
+        assert_eq!(lyrics.author.as_deref(), Some("Someone"));
+        let lines: Vec<(f64, &str)> = lyrics
+            .lines
+            .iter()
+            .map(|l| (l.time, l.text.as_str()))
+            .collect();
+        assert_eq!(lines, [(1.0, "First"), (5.0, "Second")]);
+    }
+
+    #[test]
+    fn multiple_stamps_repeat_the_line() {
+        let lyrics = parse_lrc("[00:10.00][00:30.00]Chorus\n[00:20.00]Verse\n");
+        let lines: Vec<(f64, &str)> = lyrics
+            .lines
+            .iter()
+            .map(|l| (l.time, l.text.as_str()))
+            .collect();
+        assert_eq!(lines, [(10.0, "Chorus"), (20.0, "Verse"), (30.0, "Chorus")]);
+    }
+
+    #[test]
+    fn positive_offset_shows_lyrics_earlier() {
+        let lyrics =
+            parse_lrc("[offset:+500]\n[00:00.20]Intro\n[00:02.00]<00:02.00>Hel<00:02.50>lo\n");
+        assert_eq!(lyrics.offset_ms, 500);
+        assert_eq!(lyrics.lines[0].time, 0.0);
+        assert_eq!(lyrics.lines[1].time, 1.5);
+        assert_eq!(
+            lyrics.lines[1].words,
+            [word("Hel", 1.5, 2.0), word("lo", 2.0, 4.0)]
+        );
+    }
+
+    #[test]
+    fn enhanced_word_timing() {
+        let lyrics =
+            parse_lrc("[00:01.00]<00:01.00>Never <00:01.50>gonna <00:02.00>give\n[00:04.00]Next\n");
+        let line = &lyrics.lines[0];
+        assert_eq!(line.text, "Never gonna give");
+        // The last word runs until the next line.
+        assert_eq!(
+            line.words,
+            [
+                word("Never ", 1.0, 1.5),
+                word("gonna ", 1.5, 2.0),
+                word("give", 2.0, 4.0),
+            ]
+        );
+        assert!(lyrics.lines[1].words.is_empty());
+    }
+
+    #[test]
+    fn trailing_stamp_ends_the_last_word() {
+        let lyrics = parse_lrc("[00:01.00]<00:01.00>One <00:01.40>two<00:01.90>\n[00:05.00]Next\n");
+        assert_eq!(
+            lyrics.lines[0].words,
+            [word("One ", 1.0, 1.4), word("two", 1.4, 1.9)]
+        );
+    }
+
+    #[test]
+    fn last_word_of_last_line_gets_a_default_length() {
+        let lyrics = parse_lrc("[00:01.00]<00:01.00>Bye");
+        assert_eq!(lyrics.lines[0].words, [word("Bye", 1.0, 3.0)]);
+    }
+
+    #[test]
+    fn bracket_word_stamps_and_repeated_word_lines() {
+        let lyrics = parse_lrc("[00:01.00][00:11.00]A [00:01.50]B\n[00:20.00]\n");
+        assert_eq!(lyrics.lines.len(), 2);
+        assert_eq!(lyrics.lines[0].text, "A B");
+        assert_eq!(
+            lyrics.lines[0].words,
+            [word("A ", 1.0, 1.5), word("B", 1.5, 11.0)]
+        );
+        // The repeat shifts word times; an empty entry only marks where a line ends.
+        assert_eq!(
+            lyrics.lines[1].words,
+            [word("A ", 11.0, 11.5), word("B", 11.5, 20.0)]
+        );
+    }
+
+    #[test]
+    fn plain_text_is_unsynced() {
+        let lyrics = parse("[ar:Someone]\r\nFirst line\r\n\r\n  Second line  \r\n[Chorus]\n");
+        assert!(lyrics.unsynced);
+        assert_eq!(lyrics.artist.as_deref(), Some("Someone"));
+        let texts: Vec<&str> = lyrics.lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, ["First line", "Second line", "[Chorus]"]);
+        assert!(lyrics.lines.iter().all(|l| l.time == 0.0));
+        assert!(!parse("[00:01.00]Timed").unsynced);
+    }
+
+    #[test]
+    fn detect_formats() {
+        assert_eq!(LyricsFormat::detect("[00:01.00]Hi"), LyricsFormat::Lrc);
+        assert_eq!(
+            LyricsFormat::detect("\u{feff}<?xml version=\"1.0\"?><tt>"),
+            LyricsFormat::Ttml
+        );
+        assert_eq!(LyricsFormat::detect("WEBVTT\n\n"), LyricsFormat::Srt);
+        assert_eq!(
+            LyricsFormat::detect("1\n00:00:01,000 --> 00:00:02,000\nHi"),
+            LyricsFormat::Srt
+        );
+        assert_eq!(LyricsFormat::detect("[Script Info]\n"), LyricsFormat::Ass);
+    }
+
+    #[test]
+    fn detect_multibyte_text_past_the_sniffed_head() {
+        let text = format!("[ti:{}]\n[00:01.00]歌词", "歌".repeat(400));
+        assert_eq!(LyricsFormat::detect(&text), LyricsFormat::Lrc);
+        let text = format!("<tt>{}", "é".repeat(600));
+        assert_eq!(LyricsFormat::detect(&text), LyricsFormat::Ttml);
+    }
+}
@@ -0,0 +1,450 @@
+use crate::cue;
+use crate::error::AppError;
+use crate::ffmpeg;
+use crate::gapless::{self, GaplessInfo};
+use crate::loudness::Loudness;
+use crate::tag_reader;
+use crate::tags::Tags;
+use base64::{engine::general_purpose, Engine as _};
+use encoding_rs::{GBK, UTF_16LE};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Metadata shown by the player for the current track.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Metadata {
+    #[serde(flatten)]
+    pub tags: Tags,
+    /// MIME type of the embedded cover art; `None` until `album_art` found one.
+    pub mime_type: Option<String>,
+}
+
+/// Number of channels `transcode::prepare` decodes to (`-ac`) with the default profile.
+pub const PLAYBACK_CHANNELS: u32 = 2;
+
+/// Codecs that decode bit-exactly. Anything in neither list is reported as unknown.
+const LOSSLESS_CODECS: &[&str] = &[
+    "flac",
+    "alac",
+    "ape",
+    "wavpack",
+    "tta",
+    "tak",
+    "shorten",
+    "mlp",
+    "truehd",
+    "wmalossless",
+];
+const LOSSY_CODECS: &[&str] = &[
+    "mp3",
+    "mp3float",
+    "mp2",
+    "aac",
+    "aac_latm",
+    "vorbis",
+    "opus",
+    "wmav1",
+    "wmav2",
+    "wmapro",
+    "wmavoice",
+    "ac3",
+    "eac3",
+    "dts",
+    "amr_nb",
+    "amr_wb",
+    "speex",
+    "musepack7",
+    "musepack8",
+    "atrac3",
+    "atrac3p",
+    "cook",
+    "nellymoser",
+];
+
+/// Technical properties of the first audio stream.
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioProperties {
+    /// Seconds; for a time range, the length of that range.
+    pub duration: Option<f64>,
+    /// ffmpeg codec name, e.g. "flac", "aac", "pcm_s24le".
+    pub codec: Option<String>,
+    /// ffmpeg demuxer name, e.g. "flac", "mov,mp4,m4a,3gp,3g2,mj2".
+    pub container: Option<String>,
+    pub sample_rate: Option<u32>,
+    /// Only known for lossless and PCM streams; lossy codecs have no inherent bit depth.
+    pub bit_depth: Option<u32>,
+    pub channels: Option<u32>,
+    pub channel_layout: Option<String>,
+    /// Bits per second, of the stream if known, otherwise of the whole file.
+    pub bitrate: Option<u64>,
+    /// `None` for codecs we do not classify.
+    pub lossless: Option<bool>,
+    /// Whether decoding to `PLAYBACK_CHANNELS` changes the channel layout (downmixed
+    /// surround, or mono duplicated to both sides). Files prepared for playback report
+    /// it against the channel count of the transcode profile instead.
+    pub channel_layout_changed: bool,
+    /// Encoder delay/padding of lossy files that record it; `duration` is then exact.
+    pub gapless: Option<GaplessInfo>,
+}
+
+impl AudioProperties {
+    /// Narrows the properties to the `start..end` range of the file, as played for a cue track.
+    pub fn restrict_to(&mut self, start: Option<f64>, end: Option<f64>) {
+        let start = start.unwrap_or(0.0);
+        if let Some(end) = end.or(self.duration) {
+            self.duration = Some((end - start).max(0.0));
+        }
+    }
+}
+
+/// The result of reading one track: display metadata, embedded lyrics and the
+/// technical properties of the audio.
+#[derive(Clone, Debug)]
+pub struct TrackMetadata {
+    pub metadata: Metadata,
+    pub lyrics: Option<String>,
+    /// Further lyrics tags (`lyrics-zh`, `lyrics-eng`, ...), usually translations.
+    pub other_lyrics: Vec<String>,
+    pub properties: AudioProperties,
+    /// ReplayGain/R128 values from the tags.
+    pub loudness: Loudness,
+}
+
+/// Raw ffprobe result: format and first-audio-stream tags merged, keys lower-cased.
+#[derive(Clone, Debug, Default)]
+pub struct Probe {
+    pub tags: HashMap<String, String>,
+    pub properties: AudioProperties,
+}
+
+impl Probe {
+    /// The probed tags in the normalised model.
+    pub fn tags(&self) -> Tags {
+        // Sorted so that when two spellings of a key are both present (`album_artist` and
+        // `albumartist`), the same one wins every time.
+        let mut raw: Vec<(&str, &str)> = self
+            .tags
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        raw.sort();
+        Tags::from_raw(raw)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct FFProbeOutput {
+    #[serde(default)]
+    streams: Vec<Stream>,
+    format: Format,
+}
+
+// ffprobe prints most numbers as JSON strings ("44100"), but a few as integers.
+#[derive(Deserialize, Debug)]
+struct Stream {
+    codec_type: String,
+    codec_name: Option<String>,
+    sample_rate: Option<String>,
+    channels: Option<u32>,
+    channel_layout: Option<String>,
+    #[serde(default)]
+    bits_per_sample: u32,
+    bits_per_raw_sample: Option<String>,
+    bit_rate: Option<String>,
+    duration: Option<String>,
+    // The `tags` field might be missing if there are no tags.
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Format {
+    #[serde(default)]
+    tags: HashMap<String, String>,
+    format_name: Option<String>,
+    duration: Option<String>,
+    bit_rate: Option<String>,
+}
+
+fn number<T: std::str::FromStr>(value: Option<&String>) -> Option<T> {
+    value?.trim().parse().ok()
+}
+
+fn audio_properties(stream: Option<&Stream>, format: &Format) -> AudioProperties {
+    let codec = stream.and_then(|s| s.codec_name.clone());
+    let lossless = codec.as_deref().and_then(|c| {
+        if LOSSLESS_CODECS.contains(&c) || c.starts_with("pcm_") || c.starts_with("dsd_") {
+            Some(true)
+        } else if LOSSY_CODECS.contains(&c) || c.starts_with("adpcm_") {
+            Some(false)
+        } else {
+            None
+        }
+    });
+    let bit_depth = match (stream, lossless) {
+        (Some(s), Some(true)) => number::<u32>(s.bits_per_raw_sample.as_ref())
+            .filter(|&b| b > 0)
+            .or((s.bits_per_sample > 0).then_some(s.bits_per_sample)),
+        _ => None,
+    };
+    let channels = stream.and_then(|s| s.channels);
+
+    AudioProperties {
+        duration: number(format.duration.as_ref())
+            .or_else(|| stream.and_then(|s| number(s.duration.as_ref()))),
+        codec,
+        container: format.format_name.clone(),
+        sample_rate: stream.and_then(|s| number(s.sample_rate.as_ref())),
+        bit_depth,
+        channels,
+        channel_layout: stream.and_then(|s| s.channel_layout.clone()),
+        bitrate: stream
+            .and_then(|s| number(s.bit_rate.as_ref()))
+            .or_else(|| number(format.bit_rate.as_ref())),
+        lossless,
+        channel_layout_changed: channels.is_some_and(|c| c != PLAYBACK_CHANNELS),
+        gapless: None,
+    }
+}
+
+/// ffprobe writes its JSON in UTF-8, but on Windows tag values sometimes come through in
+/// UTF-16LE or the ANSI codepage (GBK on Chinese systems). We try them in that order.
+fn decode_output(stdout: &[u8]) -> Cow<'_, str> {
+    if let Ok(s) = std::str::from_utf8(stdout) {
+        return Cow::Borrowed(s);
+    }
+    let (decoded_utf16, _, had_errors_utf16) = UTF_16LE.decode(stdout);
+    if !had_errors_utf16 {
+        return decoded_utf16;
+    }
+    let (decoded_gbk, _, had_errors_gbk) = GBK.decode(stdout);
+    if had_errors_gbk {
+        log::warn!(
+            "Failed to decode metadata as UTF-8, UTF-16LE, or GBK. Some characters may be incorrect."
+        );
+    }
+    decoded_gbk
+}
+
+/// Reads the tags and audio properties of `path` with ffprobe. When ffprobe is missing or
+/// cannot read the file, falls back to the in-process tag reader, which yields tags but no
+/// audio properties.
+pub fn probe(path: &Path) -> Result<Probe, String> {
+    let ffprobe_error = match ffprobe(path) {
+        Ok(probe) => return Ok(probe),
+        Err(e) => e,
+    };
+    let native = tag_reader::read(path)
+        .map_err(|e| format!("{} (native tag reader: {})", ffprobe_error, e))?;
+    log::info!(
+        "{}: {}; using the native tag reader",
+        path.display(),
+        ffprobe_error
+    );
+    Ok(Probe {
+        tags: native
+            .fields
+            .into_iter()
+            .map(|(k, v)| (k.to_lowercase(), v))
+            .collect(),
+        properties: AudioProperties::default(),
+    })
+}
+
+/// Runs ffprobe on `path`.
+fn ffprobe(path: &Path) -> Result<Probe, String> {
+    let mut cmd = ffmpeg::ffprobe_command().map_err(|e| e.to_string())?;
+    cmd.arg("-v")
+        .arg("quiet")
+        .arg("-print_format")
+        .arg("json")
+        .arg("-show_format")
+        .arg("-show_streams")
+        .arg("-i")
+        .arg(path);
+
+    let output = cmd
+        .output()
+        .map_err(|e| format!("Failed to execute ffprobe: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe exited with non-zero status: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    let probe_data: FFProbeOutput = serde_json::from_str(&decode_output(&output.stdout))
+        .map_err(|e| format!("Failed to parse ffprobe JSON output: {}", e))?;
+
+    // Assume the first audio stream is the one we want.
+    let stream = probe_data
+        .streams
+        .into_iter()
+        .find(|s| s.codec_type == "audio");
+    let properties = audio_properties(stream.as_ref(), &probe_data.format);
+
+    // Combine tags from format and streams (sometimes metadata is in one or the other).
+    // Tag keys vary in case between containers ("ARTIST" in Vorbis comments), so they are
+    // normalised here once.
+    let mut tags: HashMap<String, String> = probe_data
+        .format
+        .tags
+        .into_iter()
+        .map(|(k, v)| (k.to_lowercase(), v))
+        .collect();
+    if let Some(stream) = stream {
+        tags.extend(stream.tags.into_iter().map(|(k, v)| (k.to_lowercase(), v)));
+    }
+    Ok(Probe { tags, properties })
+}
+
+/// Embedded lyrics: `lyrics`, or failing that the first `lyrics-XXX` style key (often
+/// produced by DAWs and taggers that append a language or description), and the values of
+/// the remaining such keys, in key order.
+fn find_lyrics(tags: &HashMap<String, String>) -> (Option<String>, Vec<String>) {
+    let mut keys: Vec<&String> = tags.keys().filter(|k| k.starts_with("lyrics")).collect();
+    // `lyrics` itself sorts first.
+    keys.sort();
+    let mut values = keys.into_iter().map(|k| tags[k].clone());
+    (values.next(), values.collect())
+}
+
+/// Fills a missing title from the file name, and a missing artist from an
+/// "artist - title" file name.
+fn apply_filename_fallback(metadata: &mut Metadata, path: &Path) {
+    let Some(file_stem_os) = path.file_stem() else {
+        return;
+    };
+    let file_stem_str = file_stem_os.to_string_lossy();
+
+    let tags = &mut metadata.tags;
+    if tags.title.is_none() {
+        tags.title = Some(file_stem_str.to_string());
+    }
+    if tags.artist.is_none() {
+        let parts: Vec<&str> = file_stem_str
+            .split('-')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if parts.len() >= 2 {
+            tags.artist = Some(parts[0].to_string());
+            // If the title is still the whole file name, drop the artist part from it.
+            if tags.title.as_deref() == Some(&*file_stem_str) {
+                tags.title = Some(parts[1..].join(" - "));
+            }
+        }
+    }
+}
+
+/// Reads everything the player needs to know about `path`. `start` identifies a cue
+/// track within a single-file rip, whose title/performer then replace the file's tags.
+/// Never fails: a file ffprobe cannot read still gets a title from its name.
+pub fn read(path: &Path, start: Option<f64>) -> TrackMetadata {
+    let probe = probe(path).unwrap_or_else(|e| {
+        log::warn!("{}: {}", path.display(), e);
+        Probe::default()
+    });
+
+    let mut metadata = Metadata {
+        tags: probe.tags(),
+        mime_type: None,
+    };
+    apply_filename_fallback(&mut metadata, path);
+
+    // A cue track carries its own title/performer; the file tags describe the whole rip.
+    // Fields the sheet leaves out (composer, disc, ...) keep the file's values.
+    if let Some(track) = start.and_then(|start| cue::find_track(path, start)) {
+        let sheet = track.tags();
+        let tags = &mut metadata.tags;
+        for (field, value) in [
+            (&mut tags.title, sheet.title),
+            (&mut tags.artist, sheet.artist),
+            (&mut tags.album, sheet.album),
+            (&mut tags.album_artist, sheet.album_artist),
+            (&mut tags.genre, sheet.genre),
+        ] {
+            if value.is_some() {
+                *field = value;
+            }
+        }
+        tags.track_number = sheet.track_number;
+        if sheet.date.is_some() {
+            tags.date = sheet.date;
+            tags.year = sheet.year;
+        }
+    }
+
+    let mut properties = probe.properties;
+    properties.gapless = gapless::read(path, properties.codec.as_deref(), &probe.tags);
+    if let Some(duration) = properties
+        .gapless
+        .and_then(|g| g.duration(properties.sample_rate, properties.duration))
+    {
+        properties.duration = Some(duration);
+    }
+
+    let (lyrics, other_lyrics) = find_lyrics(&probe.tags);
+    TrackMetadata {
+        metadata,
+        lyrics,
+        other_lyrics,
+        properties,
+        loudness: Loudness::from_tags(&probe.tags, start.is_none()),
+    }
+}
+
+/// Identifies the image type of cover art from its first bytes. ffmpeg copies the
+/// embedded stream as-is, so a "cover.jpg" may well hold a PNG.
+fn image_mime_type(data: &[u8]) -> &'static str {
+    if data.starts_with(b"\x89PNG") {
+        "image/png"
+    } else if data.starts_with(b"GIF8") {
+        "image/gif"
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        "image/webp"
+    } else if data.starts_with(b"BM") {
+        "image/bmp"
+    } else {
+        "image/jpeg"
+    }
+}
+
+/// Extracts the embedded cover art of `path` as base64, if it has one, and records its
+/// type in `metadata.mime_type`.
+pub fn album_art(path: &Path, metadata: &mut Metadata) -> Result<Option<String>, AppError> {
+    let temp_dir_art =
+        tempfile::tempdir().map_err(|e| AppError::io("create a temp dir for art", None, e))?;
+    let art_output_path = temp_dir_art.path().join("cover.jpg");
+
+    let extracted = ffmpeg::ffmpeg_command().is_ok_and(|mut art_cmd| {
+        art_cmd
+            .arg("-hide_banner")
+            .arg("-loglevel")
+            .arg("error")
+            .arg("-i")
+            .arg(path)
+            .arg("-an") // no audio
+            .arg("-vcodec")
+            .arg("copy")
+            .arg(&art_output_path);
+        art_cmd.output().is_ok_and(|o| o.status.success())
+    });
+    // Without ffmpeg (or for containers it cannot pull a picture from), try reading the
+    // picture from the tags directly.
+    let data = match extracted
+        .then(|| std::fs::read(&art_output_path).ok())
+        .flatten()
+    {
+        Some(data) => data,
+        None => match tag_reader::read(path).ok().and_then(|tags| tags.picture) {
+            Some(data) => data,
+            None => return Ok(None),
+        },
+    };
+    metadata.mime_type = Some(image_mime_type(&data).to_string());
+    Ok(Some(general_purpose::STANDARD.encode(&data)))
+}
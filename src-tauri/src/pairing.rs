@@ -0,0 +1,490 @@
+use crate::lyrics::LyricLine;
+
+/// Lines this close together (in seconds) are grouped as one line and its translation or
+/// romanization when they are in different scripts. Lines with exactly the same time are
+/// always grouped, as in plain bilingual LRC files.
+const GROUP_TOLERANCE: f64 = 0.25;
+/// How far (in seconds) a line from a separate translation source may be from the line
+/// it translates.
+const MERGE_TOLERANCE: f64 = 0.6;
+/// Share of words that must be valid syllables for a line to count as romanization.
+const ROMANIZATION_WORD_SHARE: f64 = 0.8;
+
+/// The writing system of a line, as far as pairing cares.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Script {
+    /// Contains kana.
+    Japanese,
+    /// Contains Hangul.
+    Korean,
+    /// Han characters without kana or Hangul.
+    Chinese,
+    Latin,
+    Other,
+}
+
+impl Script {
+    pub fn detect(text: &str) -> Script {
+        let (mut kana, mut hangul, mut han, mut latin, mut other) = (0, 0, 0, 0, 0);
+        for c in text.chars().filter(|c| c.is_alphabetic()) {
+            match c {
+                '\u{3040}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' | '\u{FF66}'..='\u{FF9F}' => {
+                    kana += 1
+                }
+                '\u{AC00}'..='\u{D7AF}' | '\u{1100}'..='\u{11FF}' | '\u{3130}'..='\u{318F}' => {
+                    hangul += 1
+                }
+                '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' | '\u{F900}'..='\u{FAFF}' => {
+                    han += 1
+                }
+                c if c.is_ascii_alphabetic() || ('\u{00C0}'..='\u{024F}').contains(&c) => {
+                    latin += 1
+                }
+                _ => other += 1,
+            }
+        }
+        if kana > 0 {
+            Script::Japanese
+        } else if hangul > 0 {
+            Script::Korean
+        } else if han > 0 {
+            Script::Chinese
+        } else if latin > 0 && latin >= other {
+            Script::Latin
+        } else if other > 0 {
+            Script::Other
+        } else {
+            Script::Latin
+        }
+    }
+
+    /// Whether a line in this script can belong to lyrics mainly in `main`: Japanese lines
+    /// without kana are detected as Chinese.
+    fn fits(self, main: Script) -> bool {
+        self == main || (self == Script::Chinese && main == Script::Japanese)
+    }
+
+    fn is_cjk(self) -> bool {
+        matches!(self, Script::Japanese | Script::Korean | Script::Chinese)
+    }
+}
+
+/// The script most lines of `lines` are written in.
+pub fn dominant_script<'a>(lines: impl Iterator<Item = &'a str>) -> Option<Script> {
+    let mut counts: Vec<(Script, usize)> = Vec::new();
+    for text in lines {
+        let script = Script::detect(text);
+        match counts.iter_mut().find(|(s, _)| *s == script) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((script, 1)),
+        }
+    }
+    counts
+        .into_iter()
+        .max_by_key(|&(_, count)| count)
+        .map(|(s, _)| s)
+}
+
+const ROMAJI_ONSETS: &[&str] = &[
+    "ky", "gy", "sh", "sy", "ch", "ty", "ts", "ny", "hy", "by", "py", "my", "ry", "dy", "zy", "j",
+    "k", "g", "s", "z", "t", "d", "n", "h", "b", "p", "m", "y", "r", "w", "f", "v",
+];
+const PINYIN_INITIALS: &[&str] = &[
+    "zh", "ch", "sh", "b", "p", "m", "f", "d", "t", "n", "l", "g", "k", "h", "j", "q", "x", "r",
+    "z", "c", "s", "y", "w",
+];
+const PINYIN_FINALS: &[&str] = &[
+    "iang", "iong", "uang", "ang", "eng", "ing", "ong", "iao", "ian", "uai", "uan", "ai", "ei",
+    "ui", "ao", "ou", "iu", "ie", "er", "an", "en", "in", "un", "ia", "ua", "uo", "ue", "a", "o",
+    "e", "i", "u",
+];
+const HANGUL_ONSETS: &[&str] = &[
+    "kk", "tt", "pp", "ss", "jj", "ch", "g", "n", "d", "r", "l", "m", "b", "s", "j", "k", "t", "p",
+    "h",
+];
+const HANGUL_VOWELS: &[&str] = &[
+    "yae", "yeo", "wae", "ae", "ya", "eo", "ye", "wa", "oe", "yo", "wo", "we", "wi", "yu", "eu",
+    "ui", "a", "e", "o", "u", "i",
+];
+const HANGUL_CODAS: &[&str] = &["ng", "k", "n", "t", "l", "m", "p", "g", "d", "b", "r"];
+
+/// Lengths of the entries of `list` that `word` starts with at `at`.
+fn prefixes<'a>(word: &'a [u8], at: usize, list: &'a [&str]) -> impl Iterator<Item = usize> + 'a {
+    list.iter()
+        .filter(move |p| word[at..].starts_with(p.as_bytes()))
+        .map(|p| p.len())
+}
+
+/// Whether `word` (lower-case ASCII) splits completely into syllables, each produced by
+/// `syllables` as the possible lengths of a syllable starting at a position.
+fn segments<I: Iterator<Item = usize>>(word: &[u8], syllables: impl Fn(usize) -> I) -> bool {
+    let mut reachable = vec![false; word.len() + 1];
+    reachable[0] = true;
+    for at in 0..word.len() {
+        if !reachable[at] {
+            continue;
+        }
+        for len in syllables(at) {
+            reachable[at + len] = true;
+        }
+    }
+    reachable[word.len()]
+}
+
+fn is_romaji(word: &[u8]) -> bool {
+    let is_vowel = |b: u8| b"aiueo".contains(&b);
+    segments(word, |at| {
+        let mut lengths = Vec::new();
+        // A doubled consonant is a glottal stop (`kitte`, `matcha`); `n` is a mora alone.
+        if word[at] == b'n'
+            || (at + 1 < word.len()
+                && !is_vowel(word[at])
+                && (word[at + 1] == word[at] || &word[at..at + 2] == b"tc"))
+        {
+            lengths.push(1);
+        }
+        if is_vowel(word[at]) {
+            lengths.push(1);
+        }
+        for onset in prefixes(word, at, ROMAJI_ONSETS) {
+            if word.get(at + onset).is_some_and(|&b| is_vowel(b)) {
+                lengths.push(onset + 1);
+            }
+        }
+        lengths.into_iter()
+    })
+}
+
+fn is_pinyin(word: &[u8]) -> bool {
+    segments(word, |at| {
+        let mut lengths: Vec<usize> = prefixes(word, at, PINYIN_FINALS).collect();
+        for initial in prefixes(word, at, PINYIN_INITIALS) {
+            lengths.extend(prefixes(word, at + initial, PINYIN_FINALS).map(|f| initial + f));
+        }
+        lengths.into_iter()
+    })
+}
+
+fn is_korean_romanization(word: &[u8]) -> bool {
+    segments(word, |at| {
+        let mut lengths = Vec::new();
+        let onsets = std::iter::once(0).chain(prefixes(word, at, HANGUL_ONSETS));
+        for onset in onsets {
+            for vowel in prefixes(word, at + onset, HANGUL_VOWELS) {
+                let syllable = onset + vowel;
+                lengths.push(syllable);
+                lengths.extend(prefixes(word, at + syllable, HANGUL_CODAS).map(|c| syllable + c));
+            }
+        }
+        lengths.into_iter()
+    })
+}
+
+/// Lower-cases `text` and maps the tone marks of pinyin and the long vowels of romaji to
+/// plain letters (`ǎ` and `ā` to `a`, `ü` to `u`), dropping tone numbers.
+fn plain_letters(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter_map(|c| {
+            Some(match c {
+                'ā' | 'á' | 'ǎ' | 'à' | 'â' => 'a',
+                'ē' | 'é' | 'ě' | 'è' | 'ê' => 'e',
+                'ī' | 'í' | 'ǐ' | 'ì' | 'î' => 'i',
+                'ō' | 'ó' | 'ǒ' | 'ò' | 'ô' => 'o',
+                'ū' | 'ú' | 'ǔ' | 'ù' | 'û' => 'u',
+                'ü' | 'ǖ' | 'ǘ' | 'ǚ' | 'ǜ' => 'u',
+                '1'..='5' => return None,
+                c => c,
+            })
+        })
+        .collect()
+}
+
+/// Whether `text` reads as a romanization of a line in `original`: romaji for Japanese,
+/// pinyin for Chinese, Revised Romanization for Korean.
+pub fn is_romanization(text: &str, original: Script) -> bool {
+    let check: fn(&[u8]) -> bool = match original {
+        Script::Japanese => is_romaji,
+        Script::Chinese => is_pinyin,
+        Script::Korean => is_korean_romanization,
+        _ => return false,
+    };
+    if Script::detect(text) != Script::Latin {
+        return false;
+    }
+    let plain = plain_letters(text);
+    let words: Vec<&str> = plain
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .filter(|w| !w.is_empty())
+        .collect();
+    if words.is_empty() {
+        return false;
+    }
+    let valid = words.iter().filter(|w| check(w.as_bytes())).count();
+    valid as f64 >= words.len() as f64 * ROMANIZATION_WORD_SHARE
+}
+
+/// Fills the empty translation or romanization slot of `line` that `text` belongs in.
+/// Returns `false` if that slot is taken.
+fn attach(line: &mut LyricLine, text: &str, original: Script) -> bool {
+    let slot = if original.is_cjk() && is_romanization(text, original) {
+        &mut line.romanization
+    } else {
+        &mut line.translation
+    };
+    if slot.is_some() {
+        return false;
+    }
+    *slot = Some(text.to_string());
+    true
+}
+
+/// Groups lines that share a time (or nearly, when in different scripts) into one line
+/// with its translation and romanization. The original is, in order of preference, the
+/// line with word timing, the one another line romanizes, the one in the lyrics' main
+/// script, or the first; a romanization is never the original. Lines that already carry a
+/// translation are left alone. `lines` must be sorted by time.
+pub fn pair_lines(lines: Vec<LyricLine>) -> Vec<LyricLine> {
+    // The main script is judged from lines with no same-time partner, which are originals
+    // in any layout; if every line has one, from all lines.
+    let single = |i: usize| {
+        let time = lines[i].time;
+        let alone_before = i == 0 || lines[i - 1].time != time;
+        let alone_after = !lines.get(i + 1).is_some_and(|l| l.time == time);
+        alone_before && alone_after
+    };
+    let main = dominant_script(
+        (0..lines.len())
+            .filter(|&i| single(i))
+            .map(|i| lines[i].text.as_str()),
+    )
+    .or_else(|| dominant_script(lines.iter().map(|l| l.text.as_str())));
+
+    let mut paired: Vec<LyricLine> = Vec::with_capacity(lines.len());
+    let mut lines = lines.into_iter().peekable();
+    while let Some(first) = lines.next() {
+        let mut group = vec![first];
+        if group[0].translation.is_none() {
+            while let Some(next) = lines.peek() {
+                let first = &group[0];
+                let exact = next.time == first.time;
+                let near = next.time - first.time <= GROUP_TOLERANCE
+                    && Script::detect(&next.text) != Script::detect(&first.text);
+                if group.len() >= 3 || next.translation.is_some() || !(exact || near) {
+                    break;
+                }
+                group.push(lines.next().expect("peeked"));
+            }
+        }
+        if group.len() == 1 {
+            paired.extend(group);
+            continue;
+        }
+
+        let scripts: Vec<Script> = group.iter().map(|l| Script::detect(&l.text)).collect();
+        // A line romanized by another line of the group is the original, and the
+        // romanization never is.
+        let romanizes = |i: usize, j: usize| i != j && is_romanization(&group[i].text, scripts[j]);
+        let candidates: Vec<usize> = (0..group.len())
+            .filter(|&i| !(0..group.len()).any(|j| romanizes(i, j)))
+            .collect();
+        let original = candidates
+            .iter()
+            .copied()
+            .find(|&i| !group[i].words.is_empty())
+            .or_else(|| {
+                let romanized = |&i: &usize| (0..group.len()).any(|j| romanizes(j, i));
+                candidates.iter().copied().find(romanized)
+            })
+            .or_else(|| {
+                candidates
+                    .iter()
+                    .copied()
+                    .find(|&i| main.is_some_and(|m| scripts[i].fits(m)))
+            })
+            .or_else(|| candidates.first().copied())
+            .unwrap_or(0);
+        let mut line = group[original].clone();
+        let mut leftover = Vec::new();
+        for (i, other) in group.into_iter().enumerate() {
+            if i != original && !attach(&mut line, &other.text, scripts[original]) {
+                leftover.push(other);
+            }
+        }
+        paired.push(line);
+        paired.extend(leftover);
+    }
+    paired
+}
+
+/// Merges the lines of a separate translation or romanization source (another lyrics
+/// tag or sidecar file) into `lines`, attaching each to the nearest line in time. A source
+/// in the same script as `lines` is another copy of the lyrics rather than a translation
+/// and is left out; returns whether `other` was merged.
+pub fn merge(lines: &mut [LyricLine], other: &[LyricLine]) -> bool {
+    let original = dominant_script(lines.iter().map(|l| l.text.as_str()));
+    let Some(original) =
+        original.filter(|&s| dominant_script(other.iter().map(|l| l.text.as_str())) != Some(s))
+    else {
+        return false;
+    };
+    for extra in other {
+        // Lines are sorted, so the nearest is next to where `extra` would be inserted.
+        let at = lines.partition_point(|l| l.time < extra.time);
+        let nearest = [at.checked_sub(1), Some(at)]
+            .into_iter()
+            .flatten()
+            .filter(|&i| i < lines.len())
+            .min_by(|&a, &b| {
+                (lines[a].time - extra.time)
+                    .abs()
+                    .total_cmp(&(lines[b].time - extra.time).abs())
+            });
+        if let Some(i) = nearest.filter(|&i| (lines[i].time - extra.time).abs() <= MERGE_TOLERANCE)
+        {
+            attach(&mut lines[i], &extra.text, original);
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyrics::LyricWord;
+
+    fn line(time: f64, text: &str) -> LyricLine {
+        LyricLine {
+            time,
+            text: text.to_string(),
+            ..LyricLine::default()
+        }
+    }
+
+    /// (text, translation, romanization) of each line.
+    fn texts(lines: &[LyricLine]) -> Vec<(&str, Option<&str>, Option<&str>)> {
+        lines
+            .iter()
+            .map(|l| {
+                (
+                    l.text.as_str(),
+                    l.translation.as_deref(),
+                    l.romanization.as_deref(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn script_detection() {
+        assert_eq!(Script::detect("君の声"), Script::Japanese);
+        assert_eq!(Script::detect("사랑해"), Script::Korean);
+        assert_eq!(Script::detect("你好"), Script::Chinese);
+        assert_eq!(Script::detect("Hello, world!"), Script::Latin);
+        assert_eq!(Script::detect("Привет"), Script::Other);
+        assert_eq!(Script::detect("♪"), Script::Latin);
+    }
+
+    #[test]
+    fn romanization_checks() {
+        assert!(is_romanization("Kimi no koe ga kikoeru", Script::Japanese));
+        assert!(is_romanization("kitte matcha shinjitsu", Script::Japanese));
+        assert!(is_romanization("Nǐ hǎo shì jiè", Script::Chinese));
+        assert!(is_romanization("ni3 hao3", Script::Chinese));
+        assert!(is_romanization("saranghae neol", Script::Korean));
+        assert!(!is_romanization("Hello world", Script::Japanese));
+        assert!(!is_romanization("kimi no koe", Script::Latin));
+        assert!(!is_romanization("君の声", Script::Japanese));
+    }
+
+    #[test]
+    fn same_time_lines_become_translation_and_romanization() {
+        let lines = vec![
+            line(1.0, "君の声が聞こえる"),
+            line(1.0, "听见你的声音"),
+            line(1.0, "kimi no koe ga kikoeru"),
+            line(5.0, "夢を見た"),
+        ];
+        assert_eq!(
+            texts(&pair_lines(lines)),
+            [
+                (
+                    "君の声が聞こえる",
+                    Some("听见你的声音"),
+                    Some("kimi no koe ga kikoeru")
+                ),
+                ("夢を見た", None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn original_chosen_by_word_timing_then_main_script() {
+        let mut timed = line(1.0, "Hello");
+        timed.words = vec![LyricWord {
+            text: "Hello".to_string(),
+            start_time: 1.0,
+            end_time: 2.0,
+        }];
+        let lines = vec![line(1.0, "你好"), timed, line(3.0, "谢谢")];
+        assert_eq!(texts(&pair_lines(lines))[0], ("Hello", Some("你好"), None));
+
+        // Without word timing, the line in the script of the unpaired lines wins.
+        let lines = vec![line(1.0, "Hello"), line(1.0, "你好"), line(3.0, "谢谢")];
+        assert_eq!(texts(&pair_lines(lines))[0], ("你好", Some("Hello"), None));
+    }
+
+    #[test]
+    fn near_lines_group_only_across_scripts() {
+        let lines = vec![
+            line(1.0, "你好"),
+            line(1.2, "Hello"),
+            line(4.0, "第一"),
+            line(4.2, "第二"),
+        ];
+        assert_eq!(
+            texts(&pair_lines(lines)),
+            [
+                ("你好", Some("Hello"), None),
+                ("第一", None, None),
+                ("第二", None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_with_a_translation_are_left_alone() {
+        let mut translated = line(1.0, "你好");
+        translated.translation = Some("Hi".to_string());
+        let lines = vec![translated, line(1.0, "Hello")];
+        assert_eq!(
+            texts(&pair_lines(lines)),
+            [("你好", Some("Hi"), None), ("Hello", None, None)]
+        );
+    }
+
+    #[test]
+    fn merge_attaches_to_the_nearest_line() {
+        let mut lines = vec![line(1.0, "君の声"), line(4.0, "夢を見た")];
+        let other = [
+            line(1.3, "Your voice"),
+            line(3.8, "yume wo mita"),
+            line(9.0, "Too late"),
+        ];
+        assert!(merge(&mut lines, &other));
+        assert_eq!(
+            texts(&lines),
+            [
+                ("君の声", Some("Your voice"), None),
+                ("夢を見た", None, Some("yume wo mita")),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_skips_a_copy_in_the_same_script() {
+        let mut lines = vec![line(1.0, "君の声")];
+        assert!(!merge(&mut lines, &[line(1.0, "君の声だ")]));
+        assert_eq!(texts(&lines), [("君の声", None, None)]);
+    }
+}
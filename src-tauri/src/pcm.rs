@@ -0,0 +1,149 @@
+use crate::error::AppError;
+use crate::ffmpeg;
+use crate::metadata::{self, AudioProperties};
+use std::io::Read;
+use std::path::Path;
+use std::process::{Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+const PIPE_BUFFER_LEN: usize = 64 * 1024;
+
+/// The single background decode of one kind that is worth finishing: starting a new one
+/// cancels its predecessor, since only the latest requested track matters.
+#[derive(Default)]
+pub struct CurrentJob(Mutex<Option<Arc<AtomicBool>>>);
+
+impl CurrentJob {
+    /// Cancels the running job, if any, and returns the cancellation flag of the new one.
+    pub fn start(&self) -> Arc<AtomicBool> {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        if let Some(previous) = self.0.lock().unwrap().replace(cancelled.clone()) {
+            previous.store(true, Ordering::Relaxed);
+        }
+        cancelled
+    }
+}
+
+/// A track (or the `start`..`end` part of it) to be decoded to interleaved 16-bit
+/// little-endian PCM for analysis rather than playback.
+pub struct Decode<'a> {
+    path: &'a Path,
+    start: Option<f64>,
+    end: Option<f64>,
+    properties: AudioProperties,
+    pub channels: u32,
+    pub sample_rate: u32,
+}
+
+impl<'a> Decode<'a> {
+    /// Reads the track's properties and picks the output format: the source channels and
+    /// sample rate, capped at `max_channels` and `max_sample_rate`.
+    pub fn new(
+        path: &'a Path,
+        start: Option<f64>,
+        end: Option<f64>,
+        max_channels: u32,
+        max_sample_rate: u32,
+    ) -> Self {
+        let mut properties = metadata::read(path, start).properties;
+        properties.restrict_to(start, end);
+        let channels = properties
+            .channels
+            .filter(|&c| c > 0)
+            .unwrap_or(2)
+            .min(max_channels);
+        let sample_rate = properties
+            .sample_rate
+            .filter(|&r| r > 0)
+            .unwrap_or(44_100)
+            .min(max_sample_rate);
+        Decode {
+            path,
+            start,
+            end,
+            properties,
+            channels,
+            sample_rate,
+        }
+    }
+
+    /// Runs ffmpeg at low priority, passing whole frames to `sink` as they arrive. The
+    /// length matches the transcode, so results line up with playback. Returns the number
+    /// of frames decoded, or `Ok(None)` if `cancelled` was set.
+    pub fn run(
+        &self,
+        cancelled: &AtomicBool,
+        mut sink: impl FnMut(&[u8]),
+    ) -> Result<Option<u64>, AppError> {
+        let mut cmd = ffmpeg::ffmpeg_command()?;
+        ffmpeg::lower_priority(&mut cmd);
+        cmd.arg("-hide_banner").arg("-loglevel").arg("error");
+        if let Some(start) = self.start {
+            cmd.arg("-ss").arg(format!("{:.3}", start));
+        }
+        cmd.arg("-i").arg(self.path);
+        let length = match self.end {
+            Some(end) => Some(end - self.start.unwrap_or(0.0)),
+            None => self.properties.gapless.and(self.properties.duration),
+        };
+        if let Some(length) = length {
+            cmd.arg("-t").arg(format!("{:.6}", length));
+        }
+        cmd.arg("-vn")
+            .arg("-ac")
+            .arg(self.channels.to_string())
+            .arg("-ar")
+            .arg(self.sample_rate.to_string())
+            .arg("-f")
+            .arg("s16le")
+            .arg("pipe:1")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| AppError::io("run ffmpeg", None, e))?;
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let stderr_reader = ffmpeg::drain_stderr(child.stderr.take().expect("stderr is piped"));
+
+        let frame_len = self.channels as usize * 2;
+        let mut buf = vec![0u8; PIPE_BUFFER_LEN];
+        // Bytes of an incomplete frame carried over to the next read.
+        let mut pending = 0;
+        let mut frames = 0u64;
+        loop {
+            if cancelled.load(Ordering::Relaxed) {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Ok(None);
+            }
+            let n = stdout
+                .read(&mut buf[pending..])
+                .map_err(|e| AppError::io("read ffmpeg output", None, e))?;
+            if n == 0 {
+                break;
+            }
+            let available = pending + n;
+            let whole = available - available % frame_len;
+            sink(&buf[..whole]);
+            frames += (whole / frame_len) as u64;
+            buf.copy_within(whole..available, 0);
+            pending = available - whole;
+        }
+
+        let status = child
+            .wait()
+            .map_err(|e| AppError::io("wait for ffmpeg", None, e))?;
+        let stderr = stderr_reader.join().unwrap_or_default();
+        if !status.success() && frames == 0 {
+            let output = Output {
+                status,
+                stdout: Vec::new(),
+                stderr,
+            };
+            return Err(AppError::from_ffmpeg(self.path, &output));
+        }
+        Ok(Some(frames))
+    }
+}
@@ -0,0 +1,672 @@
+use crate::error::AppError;
+use crate::text;
+use quick_xml::escape::{escape, resolve_predefined_entity};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// VLC's XSPF extension namespace; its `vlc:option` elements carry start/stop times.
+const VLC_EXTENSION: &str = "http://www.videolan.org/vlc/playlist/0";
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaylistFormat {
+    M3u,
+    M3u8,
+    Pls,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "m3u" => Some(PlaylistFormat::M3u),
+            "m3u8" => Some(PlaylistFormat::M3u8),
+            "pls" => Some(PlaylistFormat::Pls),
+            "xspf" => Some(PlaylistFormat::Xspf),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format of a file with an unknown extension from its contents.
+    fn sniff(text: &str) -> Self {
+        let head = text.trim_start().to_ascii_lowercase();
+        if head.starts_with("[playlist]") {
+            PlaylistFormat::Pls
+        } else if head.starts_with("<?xml") || head.starts_with("<playlist") {
+            PlaylistFormat::Xspf
+        } else {
+            PlaylistFormat::M3u8
+        }
+    }
+}
+
+/// One queue entry, shaped like the frontend's playlist items so imported entries can be
+/// appended directly. `start`/`end` select a range of the file (cue tracks).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistEntry {
+    pub path: String,
+    pub name: String,
+    /// Seconds, as stated by the playlist; never probed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<f64>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum MissingReason {
+    /// The location resolved to a local path that does not exist.
+    NotFound,
+    /// Streams and other non-`file` URLs cannot be played.
+    Remote,
+    /// The location could not be turned into a path at all.
+    Invalid,
+}
+
+/// A playlist entry that was not imported.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MissingEntry {
+    /// The location exactly as written in the playlist.
+    pub location: String,
+    /// Where we looked for it, if it was a local location.
+    pub path: Option<String>,
+    pub reason: MissingReason,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedPlaylist {
+    pub name: Option<String>,
+    pub entries: Vec<PlaylistEntry>,
+    pub missing: Vec<MissingEntry>,
+}
+
+/// An entry as read from the file, before its location is resolved.
+#[derive(Default)]
+struct RawEntry {
+    location: String,
+    title: Option<String>,
+    duration: Option<f64>,
+    start: Option<f64>,
+    end: Option<f64>,
+}
+
+#[derive(Default)]
+struct RawPlaylist {
+    name: Option<String>,
+    entries: Vec<RawEntry>,
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Durations of `-1` (or any negative value) mean "unknown" in M3U and PLS.
+fn parse_seconds(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().filter(|d| *d >= 0.0)
+}
+
+/// Applies a VLC `start-time=`/`stop-time=` option, as written by VLC to M3U and XSPF.
+fn apply_vlc_option(entry: &mut RawEntry, option: &str) {
+    match option.trim().split_once('=') {
+        Some(("start-time", value)) => entry.start = parse_seconds(value),
+        Some(("stop-time", value)) => entry.end = parse_seconds(value),
+        _ => {}
+    }
+}
+
+fn parse_m3u(text: &str) -> RawPlaylist {
+    let mut playlist = RawPlaylist::default();
+    let mut pending = RawEntry::default();
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            // `#EXTINF:<seconds> [attributes],<title>`
+            let (head, title) = info.split_once(',').unwrap_or((info, ""));
+            pending.duration = head.split_whitespace().next().and_then(parse_seconds);
+            pending.title = non_empty(title);
+        } else if let Some(name) = line.strip_prefix("#PLAYLIST:") {
+            playlist.name = non_empty(name);
+        } else if let Some(option) = line.strip_prefix("#EXTVLCOPT:") {
+            apply_vlc_option(&mut pending, option);
+        } else if !line.starts_with('#') {
+            pending.location = line.to_string();
+            playlist.entries.push(std::mem::take(&mut pending));
+        }
+    }
+    playlist
+}
+
+fn parse_pls(text: &str) -> RawPlaylist {
+    let mut entries: BTreeMap<u32, RawEntry> = BTreeMap::new();
+
+    for line in text.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        // Keys are `File1`, `Title1`, `Length1`, ...
+        let split = key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len());
+        let (field, number) = key.split_at(split);
+        let Ok(number) = number.parse::<u32>() else {
+            continue;
+        };
+        let entry = entries.entry(number).or_default();
+        match field {
+            "file" => entry.location = value.trim().to_string(),
+            "title" => entry.title = non_empty(value),
+            "length" => entry.duration = parse_seconds(value),
+            _ => {}
+        }
+    }
+
+    RawPlaylist {
+        name: None,
+        entries: entries
+            .into_values()
+            .filter(|e| !e.location.is_empty())
+            .collect(),
+    }
+}
+
+fn parse_xspf(text: &str) -> Result<RawPlaylist, String> {
+    // Text is not trimmed by the reader: entities arrive as separate events, and trimming
+    // would eat the spaces around them. Values are trimmed once complete instead.
+    let mut reader = Reader::from_str(text);
+
+    let mut playlist = RawPlaylist::default();
+    let mut stack: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut track: Option<RawEntry> = None;
+    let mut in_vlc_extension = false;
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("Invalid XSPF at byte {}: {}", reader.buffer_position(), e))?;
+        match event {
+            Event::Start(e) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                match name.as_str() {
+                    "track" => track = Some(RawEntry::default()),
+                    "extension" => {
+                        in_vlc_extension = e
+                            .try_get_attribute("application")
+                            .ok()
+                            .flatten()
+                            .and_then(|a| a.unescape_value().ok())
+                            .is_some_and(|v| v == VLC_EXTENSION);
+                    }
+                    _ => {}
+                }
+                stack.push(name);
+                text.clear();
+            }
+            Event::Text(e) => {
+                text.push_str(
+                    &e.decode()
+                        .map_err(|e| format!("Invalid XSPF text: {}", e))?,
+                );
+            }
+            Event::CData(e) => {
+                text.push_str(
+                    &e.decode()
+                        .map_err(|e| format!("Invalid XSPF text: {}", e))?,
+                );
+            }
+            Event::GeneralRef(e) => {
+                if let Ok(Some(c)) = e.resolve_char_ref() {
+                    text.push(c);
+                } else if let Ok(name) = e.decode() {
+                    text.push_str(resolve_predefined_entity(&name).unwrap_or_default());
+                }
+            }
+            Event::End(_) => {
+                let name = stack.pop().unwrap_or_default();
+                let parent = stack.last().map(String::as_str);
+                match (parent, name.as_str(), track.as_mut()) {
+                    (Some("playlist"), "title", _) => playlist.name = non_empty(&text),
+                    (_, "track", _) => {
+                        if let Some(entry) = track.take() {
+                            if !entry.location.is_empty() {
+                                playlist.entries.push(entry);
+                            }
+                        }
+                    }
+                    (Some("track"), "location", Some(entry)) if entry.location.is_empty() => {
+                        entry.location = text.trim().to_string();
+                    }
+                    (Some("track"), "title", Some(entry)) => entry.title = non_empty(&text),
+                    (Some("track"), "duration", Some(entry)) => {
+                        // XSPF durations are in milliseconds.
+                        entry.duration = parse_seconds(&text).map(|ms| ms / 1000.0);
+                    }
+                    (_, "option", Some(entry)) if in_vlc_extension => {
+                        apply_vlc_option(entry, &text);
+                    }
+                    (_, "extension", _) => in_vlc_extension = false,
+                    _ => {}
+                }
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(playlist)
+}
+
+/// Percent-decodes a URI component, keeping the original on invalid UTF-8.
+fn percent_decode(value: &str) -> Cow<'_, str> {
+    urlencoding::decode(value).unwrap_or(Cow::Borrowed(value))
+}
+
+/// Resolves a playlist location to a local path. `uri` is set for XSPF, where every
+/// location is a URI; M3U and PLS locations are plain paths unless they carry a scheme.
+fn resolve_location(location: &str, base: &Path, uri: bool) -> Result<PathBuf, MissingReason> {
+    let path = if let Some(rest) = location
+        .get(..7)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("file://"))
+        .map(|_| &location[7..])
+    {
+        // `file:///C:/x` on Windows, `file:///x` elsewhere; `file://host/x` is a UNC share.
+        let rest = percent_decode(rest);
+        let rest = rest.strip_prefix("localhost").unwrap_or(&rest);
+        match rest.strip_prefix('/') {
+            Some(local) if cfg!(windows) => local.to_string(),
+            Some(_) => rest.to_string(),
+            None if cfg!(windows) => format!("//{}", rest),
+            None => return Err(MissingReason::Invalid),
+        }
+    } else if location.contains("://") {
+        return Err(MissingReason::Remote);
+    } else if uri {
+        percent_decode(location).into_owned()
+    } else {
+        location.to_string()
+    };
+
+    if path.is_empty() {
+        return Err(MissingReason::Invalid);
+    }
+    // Playlists written on Windows use backslashes, which mean nothing elsewhere.
+    #[cfg(not(windows))]
+    let path = path.replace('\\', "/");
+
+    let path = PathBuf::from(path);
+    Ok(if path.is_absolute() {
+        path
+    } else {
+        base.join(path)
+    })
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Reads a playlist file. Relative entries are resolved against the playlist's directory;
+/// entries that cannot be played are listed in `missing` instead of `entries`.
+#[tauri::command]
+pub fn import_playlist(path: String) -> Result<ImportedPlaylist, AppError> {
+    let playlist_path = Path::new(&path);
+    let bytes = std::fs::read(playlist_path)
+        .map_err(|e| AppError::io("read playlist", Some(playlist_path), e))?;
+    let contents = text::decode(&bytes);
+    let format = PlaylistFormat::from_path(playlist_path)
+        .unwrap_or_else(|| PlaylistFormat::sniff(&contents));
+
+    let raw = match format {
+        PlaylistFormat::M3u | PlaylistFormat::M3u8 => parse_m3u(&contents),
+        PlaylistFormat::Pls => parse_pls(&contents),
+        PlaylistFormat::Xspf => {
+            parse_xspf(&contents).map_err(|message| AppError::InvalidPlaylist {
+                path: path.clone(),
+                message,
+            })?
+        }
+    };
+    let base = playlist_path.parent().unwrap_or(Path::new("."));
+    let uri = format == PlaylistFormat::Xspf;
+
+    let mut entries = Vec::new();
+    let mut missing = Vec::new();
+    for entry in raw.entries {
+        match resolve_location(&entry.location, base, uri) {
+            Ok(resolved) if resolved.is_file() => entries.push(PlaylistEntry {
+                name: entry.title.unwrap_or_else(|| file_name(&resolved)),
+                path: resolved.to_string_lossy().to_string(),
+                duration: entry.duration,
+                start: entry.start,
+                end: entry.end,
+            }),
+            Ok(resolved) => missing.push(MissingEntry {
+                location: entry.location,
+                path: Some(resolved.to_string_lossy().to_string()),
+                reason: MissingReason::NotFound,
+            }),
+            Err(reason) => missing.push(MissingEntry {
+                location: entry.location,
+                path: None,
+                reason,
+            }),
+        }
+    }
+    if !missing.is_empty() {
+        log::warn!("{}: {} entries could not be found", path, missing.len());
+    }
+
+    Ok(ImportedPlaylist {
+        name: raw.name.or_else(|| {
+            playlist_path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+        }),
+        entries,
+        missing,
+    })
+}
+
+/// `path` relative to `dir` when it lies inside it, so playlists keep working when the
+/// whole folder is moved; otherwise `path` unchanged.
+fn relative_to<'a>(path: &'a Path, dir: &Path) -> &'a Path {
+    path.strip_prefix(dir).unwrap_or(path)
+}
+
+/// Percent-encodes everything except unreserved characters and path separators.
+fn encode_uri_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                out.push(byte as char)
+            }
+            _ => {
+                let _ = write!(out, "%{:02X}", byte);
+            }
+        }
+    }
+    out
+}
+
+fn location_uri(path: &Path, dir: &Path) -> String {
+    let relative = relative_to(path, dir);
+    let slashed = relative.to_string_lossy().replace('\\', "/");
+    if relative.is_absolute() || slashed.starts_with('/') {
+        let slashed = slashed.trim_start_matches('/');
+        format!("file:///{}", encode_uri_path(slashed))
+    } else {
+        encode_uri_path(&slashed)
+    }
+}
+
+fn entry_duration(entry: &PlaylistEntry) -> Option<f64> {
+    entry.duration.or(match (entry.start, entry.end) {
+        (Some(start), Some(end)) => Some(end - start),
+        _ => None,
+    })
+}
+
+fn write_m3u(entries: &[PlaylistEntry], dir: &Path, name: Option<&str>) -> String {
+    let mut out = String::from("#EXTM3U\n");
+    if let Some(name) = name {
+        let _ = writeln!(out, "#PLAYLIST:{}", name);
+    }
+    for entry in entries {
+        let seconds = entry_duration(entry).map_or(-1, |d| d.round() as i64);
+        let _ = writeln!(out, "#EXTINF:{},{}", seconds, entry.name);
+        if let Some(start) = entry.start {
+            let _ = writeln!(out, "#EXTVLCOPT:start-time={:.3}", start);
+        }
+        if let Some(end) = entry.end {
+            let _ = writeln!(out, "#EXTVLCOPT:stop-time={:.3}", end);
+        }
+        let _ = writeln!(
+            out,
+            "{}",
+            relative_to(Path::new(&entry.path), dir).display()
+        );
+    }
+    out
+}
+
+/// PLS has no way to express a time range, so cue tracks are written as their whole file.
+fn write_pls(entries: &[PlaylistEntry], dir: &Path) -> String {
+    let mut out = String::from("[playlist]\n");
+    for (i, entry) in entries.iter().enumerate() {
+        let n = i + 1;
+        let seconds = entry_duration(entry).map_or(-1, |d| d.round() as i64);
+        let _ = writeln!(
+            out,
+            "File{}={}",
+            n,
+            relative_to(Path::new(&entry.path), dir).display()
+        );
+        let _ = writeln!(out, "Title{}={}", n, entry.name);
+        let _ = writeln!(out, "Length{}={}", n, seconds);
+    }
+    let _ = writeln!(out, "NumberOfEntries={}", entries.len());
+    let _ = writeln!(out, "Version=2");
+    out
+}
+
+fn write_xspf(entries: &[PlaylistEntry], dir: &Path, name: Option<&str>) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        out,
+        "<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\" xmlns:vlc=\"{}\">",
+        VLC_EXTENSION
+    );
+    if let Some(name) = name {
+        let _ = writeln!(out, "  <title>{}</title>", escape(name));
+    }
+    out.push_str("  <trackList>\n");
+    for entry in entries {
+        out.push_str("    <track>\n");
+        let location = location_uri(Path::new(&entry.path), dir);
+        let _ = writeln!(out, "      <location>{}</location>", escape(&location));
+        let _ = writeln!(out, "      <title>{}</title>", escape(&entry.name));
+        if let Some(duration) = entry_duration(entry) {
+            let _ = writeln!(
+                out,
+                "      <duration>{}</duration>",
+                (duration * 1000.0).round() as i64
+            );
+        }
+        if entry.start.is_some() || entry.end.is_some() {
+            let _ = writeln!(out, "      <extension application=\"{}\">", VLC_EXTENSION);
+            if let Some(start) = entry.start {
+                let _ = writeln!(
+                    out,
+                    "        <vlc:option>start-time={:.3}</vlc:option>",
+                    start
+                );
+            }
+            if let Some(end) = entry.end {
+                let _ = writeln!(out, "        <vlc:option>stop-time={:.3}</vlc:option>", end);
+            }
+            out.push_str("      </extension>\n");
+        }
+        out.push_str("    </track>\n");
+    }
+    out.push_str("  </trackList>\n</playlist>\n");
+    out
+}
+
+/// Writes `entries` to a playlist file. The format follows `format` or, if omitted, the
+/// file extension. Files inside the playlist's folder are stored as relative paths.
+#[tauri::command]
+pub fn export_playlist(
+    path: String,
+    entries: Vec<PlaylistEntry>,
+    name: Option<String>,
+    format: Option<PlaylistFormat>,
+) -> Result<(), AppError> {
+    let playlist_path = Path::new(&path);
+    let format = format
+        .or_else(|| PlaylistFormat::from_path(playlist_path))
+        .ok_or_else(|| {
+            AppError::invalid_argument(format!("Unknown playlist format for {}", path))
+        })?;
+    let dir = playlist_path.parent().unwrap_or(Path::new(""));
+    let name = name.as_deref();
+
+    // Everything is written as UTF-8; `.m3u` readers that assume the ANSI codepage are
+    // rare now, and our own importer detects the encoding anyway.
+    let contents = match format {
+        PlaylistFormat::M3u | PlaylistFormat::M3u8 => write_m3u(&entries, dir, name),
+        PlaylistFormat::Pls => write_pls(&entries, dir),
+        PlaylistFormat::Xspf => write_xspf(&entries, dir, name),
+    };
+    std::fs::write(playlist_path, contents)
+        .map_err(|e| AppError::io("write playlist", Some(playlist_path), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn m3u_reads_extinf_name_and_vlc_options() {
+        let playlist = parse_m3u(
+            "#EXTM3U\n#PLAYLIST:Road Trip\n#EXTINF:215 tvg-id=\"x\",Artist - Song\n#EXTVLCOPT:start-time=30\n#EXTVLCOPT:stop-time=90.5\nmusic/song.flac\n\n#EXTINF:-1,\nother.mp3\n",
+        );
+        assert_eq!(playlist.name.as_deref(), Some("Road Trip"));
+        assert_eq!(playlist.entries.len(), 2);
+
+        let first = &playlist.entries[0];
+        assert_eq!(first.location, "music/song.flac");
+        assert_eq!(first.title.as_deref(), Some("Artist - Song"));
+        assert_eq!(first.duration, Some(215.0));
+        assert_eq!(first.start, Some(30.0));
+        assert_eq!(first.end, Some(90.5));
+
+        // Metadata does not leak into the next entry; -1 means unknown.
+        let second = &playlist.entries[1];
+        assert_eq!(second.location, "other.mp3");
+        assert_eq!(second.title, None);
+        assert_eq!(second.duration, None);
+        assert_eq!(second.start, None);
+    }
+
+    #[test]
+    fn pls_orders_by_number_and_skips_entries_without_file() {
+        let playlist = parse_pls(
+            "[playlist]\nNumberOfEntries=3\nFile2=b.mp3\nTitle2=Second\nLength2=-1\nFile1=a.mp3\ntitle1=First\nlength1=120\nTitle3=No file\nVersion=2\n",
+        );
+        assert_eq!(playlist.entries.len(), 2);
+        assert_eq!(playlist.entries[0].location, "a.mp3");
+        assert_eq!(playlist.entries[0].title.as_deref(), Some("First"));
+        assert_eq!(playlist.entries[0].duration, Some(120.0));
+        assert_eq!(playlist.entries[1].location, "b.mp3");
+        assert_eq!(playlist.entries[1].duration, None);
+    }
+
+    #[test]
+    fn xspf_reads_tracks_entities_and_vlc_extension() {
+        let playlist = parse_xspf(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/" xmlns:vlc="http://www.videolan.org/vlc/playlist/ns/0/">
+  <title>Rock &amp; Roll</title>
+  <trackList>
+    <track>
+      <location>file:///music/Tom%20%26%20Jerry.flac</location>
+      <title>Tom &amp; Jerry</title>
+      <duration>183500</duration>
+      <extension application="http://www.videolan.org/vlc/playlist/0">
+        <vlc:option>start-time=10</vlc:option>
+        <vlc:option>stop-time=20</vlc:option>
+      </extension>
+    </track>
+    <track><title>No location</title></track>
+    <track><location><![CDATA[b.mp3]]></location></track>
+  </trackList>
+</playlist>"#,
+        )
+        .unwrap();
+        assert_eq!(playlist.name.as_deref(), Some("Rock & Roll"));
+        assert_eq!(playlist.entries.len(), 2);
+
+        let first = &playlist.entries[0];
+        assert_eq!(first.location, "file:///music/Tom%20%26%20Jerry.flac");
+        assert_eq!(first.title.as_deref(), Some("Tom & Jerry"));
+        assert_eq!(first.duration, Some(183.5));
+        assert_eq!(first.start, Some(10.0));
+        assert_eq!(first.end, Some(20.0));
+        assert_eq!(playlist.entries[1].location, "b.mp3");
+    }
+
+    #[test]
+    fn xspf_rejects_malformed_xml() {
+        assert!(parse_xspf("<playlist><trackList></playlist>").is_err());
+    }
+
+    #[test]
+    fn sniff_recognises_formats_by_content() {
+        assert_eq!(
+            PlaylistFormat::sniff("\n[Playlist]\nFile1=a.mp3"),
+            PlaylistFormat::Pls
+        );
+        assert_eq!(
+            PlaylistFormat::sniff("<?xml version=\"1.0\"?><playlist/>"),
+            PlaylistFormat::Xspf
+        );
+        assert_eq!(
+            PlaylistFormat::sniff("#EXTM3U\na.mp3"),
+            PlaylistFormat::M3u8
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn locations_resolve_against_the_playlist_directory() {
+        let base = Path::new("/music/lists");
+        assert_eq!(
+            resolve_location("../a b.mp3", base, false).unwrap(),
+            PathBuf::from("/music/lists/../a b.mp3")
+        );
+        assert_eq!(
+            resolve_location("sub\\c.mp3", base, false).unwrap(),
+            PathBuf::from("/music/lists/sub/c.mp3")
+        );
+        assert_eq!(
+            resolve_location("a%20b.mp3", base, true).unwrap(),
+            PathBuf::from("/music/lists/a b.mp3")
+        );
+        assert_eq!(
+            resolve_location("file:///music/x%23y.mp3", base, true).unwrap(),
+            PathBuf::from("/music/x#y.mp3")
+        );
+        assert_eq!(
+            resolve_location("file://localhost/music/z.mp3", base, true).unwrap(),
+            PathBuf::from("/music/z.mp3")
+        );
+        assert!(matches!(
+            resolve_location("http://radio.example/stream", base, false),
+            Err(MissingReason::Remote)
+        ));
+        assert!(matches!(
+            resolve_location("file://server/share/a.mp3", base, true),
+            Err(MissingReason::Invalid)
+        ));
+    }
+}
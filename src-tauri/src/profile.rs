@@ -0,0 +1,234 @@
+use crate::loudness::{GainMode, Loudness};
+use crate::metadata::AudioProperties;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+/// Sample rates above this (DSD decodes to several MHz) are resampled even in passthrough.
+const MAX_SAMPLE_RATE: u32 = 192_000;
+/// Used when the source sample rate is unknown.
+const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+/// Opus only runs at 48 kHz.
+const OPUS_SAMPLE_RATE: u32 = 48_000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Container {
+    #[default]
+    Wav,
+    Flac,
+    Opus,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum BitDepth {
+    #[default]
+    #[serde(rename = "16")]
+    Bits16,
+    #[serde(rename = "24")]
+    Bits24,
+    /// 16 bits for 16-bit (or lower) sources, 24 bits for anything deeper.
+    Source,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ChannelMode {
+    /// Downmix surround and duplicate mono to two channels.
+    #[default]
+    Stereo,
+    /// Keep the source's channels.
+    Passthrough,
+}
+
+/// How tracks are transcoded for playback, as chosen in the settings. The defaults
+/// reproduce the original behaviour: 16-bit stereo WAV at the source sample rate.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TranscodeProfile {
+    pub container: Container,
+    /// Target sample rate in Hz; `None` keeps the source rate.
+    pub sample_rate: Option<u32>,
+    /// Ignored for Opus, which has no bit depth.
+    pub bit_depth: BitDepth,
+    /// Apply triangular high-pass dither when reducing to 16 bits.
+    pub dither: bool,
+    pub channels: ChannelMode,
+    /// Opus bitrate in kbit/s.
+    pub opus_bitrate: u32,
+    /// Loudness normalisation; applied by the player unless `bake_replay_gain` is set.
+    pub replay_gain: GainMode,
+    /// Apply the gain in the transcode itself, so the output is already normalised.
+    pub bake_replay_gain: bool,
+}
+
+impl Default for TranscodeProfile {
+    fn default() -> Self {
+        TranscodeProfile {
+            container: Container::Wav,
+            sample_rate: None,
+            bit_depth: BitDepth::Bits16,
+            dither: false,
+            channels: ChannelMode::Stereo,
+            opus_bitrate: 160,
+            replay_gain: GainMode::Off,
+            bake_replay_gain: false,
+        }
+    }
+}
+
+/// A `TranscodeProfile` resolved against one source file: the concrete output format.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedProfile {
+    pub container: Container,
+    pub sample_rate: u32,
+    /// `None` for Opus.
+    pub bit_depth: Option<u32>,
+    pub channels: u32,
+    pub dither: bool,
+    /// kbit/s; only for Opus.
+    pub bitrate: Option<u32>,
+    /// dB of loudness normalisation baked into the output.
+    pub gain: Option<f64>,
+}
+
+/// Output sample rate for a source rate when passing it through: unchanged for PCM-like
+/// rates, a rate of the same family for DSD and other very high rates.
+fn passthrough_sample_rate(source: Option<u32>) -> u32 {
+    match source {
+        None | Some(0) => DEFAULT_SAMPLE_RATE,
+        Some(rate) if rate <= MAX_SAMPLE_RATE => rate,
+        Some(rate) if rate % 44_100 == 0 => 176_400,
+        Some(_) => MAX_SAMPLE_RATE,
+    }
+}
+
+impl TranscodeProfile {
+    /// The gain the player should apply itself: `None` when normalisation is off, baked
+    /// into the transcode, or the loudness is not known.
+    pub fn playback_gain(&self, loudness: &Loudness) -> Option<f64> {
+        if self.bake_replay_gain {
+            return None;
+        }
+        loudness.gain(self.replay_gain).map(|g| g.clipless())
+    }
+
+    pub fn apply(&self, source: &AudioProperties, loudness: &Loudness) -> AppliedProfile {
+        let gain = if self.bake_replay_gain {
+            loudness.gain(self.replay_gain).map(|g| g.clipless())
+        } else {
+            None
+        };
+        let channels = match self.channels {
+            ChannelMode::Stereo => 2,
+            ChannelMode::Passthrough => source.channels.filter(|&c| c > 0).unwrap_or(2),
+        };
+        if self.container == Container::Opus {
+            return AppliedProfile {
+                container: Container::Opus,
+                sample_rate: OPUS_SAMPLE_RATE,
+                bit_depth: None,
+                channels,
+                dither: false,
+                bitrate: Some(self.opus_bitrate),
+                gain,
+            };
+        }
+        let bit_depth = match self.bit_depth {
+            BitDepth::Bits16 => 16,
+            BitDepth::Bits24 => 24,
+            BitDepth::Source if source.bit_depth.is_some_and(|b| b > 16) => 24,
+            BitDepth::Source => 16,
+        };
+        AppliedProfile {
+            container: self.container,
+            sample_rate: self
+                .sample_rate
+                .unwrap_or_else(|| passthrough_sample_rate(source.sample_rate)),
+            bit_depth: Some(bit_depth),
+            channels,
+            dither: self.dither && bit_depth == 16,
+            bitrate: None,
+            gain,
+        }
+    }
+}
+
+impl AppliedProfile {
+    pub fn extension(&self) -> &'static str {
+        match self.container {
+            Container::Wav => "wav",
+            Container::Flac => "flac",
+            Container::Opus => "opus",
+        }
+    }
+
+    /// Identifies the output in transcode cache keys.
+    pub fn cache_variant(&self) -> String {
+        format!(
+            "{}-{}ch-{}hz-{}bit-{}-{}k-{:+.2}db",
+            self.extension(),
+            self.channels,
+            self.sample_rate,
+            self.bit_depth.unwrap_or(0),
+            if self.dither { "dither" } else { "nodither" },
+            self.bitrate.unwrap_or(0),
+            self.gain.unwrap_or(0.0)
+        )
+    }
+
+    /// Adds the resampling, channel and sample-format options shared by every container.
+    pub fn add_audio_args(&self, cmd: &mut Command) {
+        cmd.arg("-vn").arg("-ac").arg(self.channels.to_string());
+        let mut filters = Vec::new();
+        if let Some(gain) = self.gain {
+            filters.push(format!("volume={:.2}dB", gain));
+        }
+        if self.dither {
+            // Downmix first, then resample and requantise in one step so the dither is
+            // applied to the final 16-bit samples rather than undone by a later conversion.
+            if self.channels == 2 {
+                filters.push("aformat=channel_layouts=stereo".to_string());
+            }
+            filters.push(format!(
+                "aresample=osr={}:osf=s16:dither_method=triangular_hp",
+                self.sample_rate
+            ));
+        } else {
+            cmd.arg("-ar").arg(self.sample_rate.to_string());
+        }
+        if !filters.is_empty() {
+            cmd.arg("-af").arg(filters.join(","));
+        }
+    }
+
+    /// Adds the encoder options for writing a complete file in the profile's container.
+    pub fn add_encoder_args(&self, cmd: &mut Command) {
+        match (self.container, self.bit_depth) {
+            (Container::Opus, _) => {
+                cmd.arg("-c:a")
+                    .arg("libopus")
+                    .arg("-b:a")
+                    .arg(format!("{}k", self.bitrate.unwrap_or(160)));
+            }
+            (Container::Flac, Some(24)) => {
+                cmd.arg("-c:a")
+                    .arg("flac")
+                    .arg("-sample_fmt")
+                    .arg("s32")
+                    .arg("-bits_per_raw_sample")
+                    .arg("24");
+            }
+            (Container::Flac, _) => {
+                cmd.arg("-c:a").arg("flac").arg("-sample_fmt").arg("s16");
+            }
+            (Container::Wav, Some(24)) => {
+                cmd.arg("-c:a").arg("pcm_s24le");
+            }
+            (Container::Wav, _) => {
+                cmd.arg("-c:a").arg("pcm_s16le");
+            }
+        }
+    }
+}
@@ -0,0 +1,506 @@
+use crate::lyrics::Lyrics;
+use crate::pairing::{self, Script};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Bundled reading dictionaries; see the header of each file for its format and source.
+const PINYIN: &str = include_str!("../dictionaries/pinyin.txt");
+const PINYIN_WORDS: &str = include_str!("../dictionaries/pinyin_words.txt");
+const KANJI: &str = include_str!("../dictionaries/kanji.txt");
+
+/// Hepburn romanization of the basic hiragana, a row of the kana chart at a time.
+/// Katakana are mapped onto these first.
+const KANA: &[(&str, &str)] = &[
+    ("あいうえお", "a i u e o"),
+    ("かきくけこ", "ka ki ku ke ko"),
+    ("がぎぐげご", "ga gi gu ge go"),
+    ("さしすせそ", "sa shi su se so"),
+    ("ざじずぜぞ", "za ji zu ze zo"),
+    ("たちつてと", "ta chi tsu te to"),
+    ("だぢづでど", "da ji zu de do"),
+    ("なにぬねの", "na ni nu ne no"),
+    ("はひふへほ", "ha hi fu he ho"),
+    ("ばびぶべぼ", "ba bi bu be bo"),
+    ("ぱぴぷぺぽ", "pa pi pu pe po"),
+    ("まみむめも", "ma mi mu me mo"),
+    ("やゆよ", "ya yu yo"),
+    ("らりるれろ", "ra ri ru re ro"),
+    ("わゐゑをんゔ", "wa i e wo n vu"),
+    ("ぁぃぅぇぉゃゅょゎ", "a i u e o ya yu yo wa"),
+];
+
+/// Particles that start the hiragana after a word rather than being part of its ending.
+/// `は`, `へ` and `を` are read differently as particles.
+const PARTICLES: &[(char, &str)] = &[
+    ('は', "wa"),
+    ('へ', "e"),
+    ('を', "wo"),
+    ('が', "ga"),
+    ('の', "no"),
+    ('も', "mo"),
+];
+/// Kana that are particles when they are all the hiragana after a word (`夜空に`), but
+/// may also end it (`死に`).
+const SHORT_PARTICLES: &[(char, &str)] = &[('に', "ni"), ('と', "to"), ('で', "de"), ('や', "ya")];
+
+/// Revised Romanization of the parts of a Hangul syllable.
+const HANGUL_INITIALS: [&str; 19] = [
+    "g", "kk", "n", "d", "tt", "r", "m", "b", "pp", "s", "ss", "", "j", "jj", "ch", "k", "t", "p",
+    "h",
+];
+const HANGUL_VOWELS: [&str; 21] = [
+    "a", "ae", "ya", "yae", "eo", "e", "yeo", "ye", "o", "wa", "wae", "oe", "yo", "u", "wo", "we",
+    "wi", "yu", "eu", "ui", "i",
+];
+/// Final consonants at the end of a word or before another consonant.
+const HANGUL_FINALS: [&str; 28] = [
+    "", "k", "k", "k", "n", "n", "n", "t", "l", "k", "m", "l", "l", "l", "p", "l", "m", "p", "p",
+    "t", "t", "ng", "t", "t", "k", "t", "p", "t",
+];
+/// Final consonants carried over to a following syllable that starts with a vowel.
+const HANGUL_LINKED_FINALS: [&str; 28] = [
+    "", "g", "kk", "gs", "n", "nj", "n", "d", "r", "lg", "lm", "lb", "ls", "lt", "lp", "r", "m",
+    "b", "bs", "s", "ss", "ng", "j", "ch", "k", "t", "p", "",
+];
+/// Indices of the silent initial `ㅇ`, the initial `ㄹ` and the final `ㄹ` in the tables.
+const HANGUL_SILENT_INITIAL: u32 = 11;
+const HANGUL_INITIAL_R: u32 = 5;
+const HANGUL_FINAL_L: u32 = 8;
+
+/// Readings looked up by longest match.
+#[derive(Default)]
+struct Dictionary {
+    entries: HashMap<String, String>,
+    /// Length of the longest entry, in characters.
+    longest: usize,
+}
+
+impl Dictionary {
+    fn insert(&mut self, key: String, reading: &str) {
+        self.longest = self.longest.max(key.chars().count());
+        self.entries.insert(key, reading.to_string());
+    }
+
+    /// The longest entry `chars` starts with: its length in characters and its reading.
+    fn longest_match(&self, chars: &[char]) -> Option<(usize, &str)> {
+        (1..=self.longest.min(chars.len())).rev().find_map(|len| {
+            let key: String = chars[..len].iter().collect();
+            self.entries.get(&key).map(|r| (len, r.as_str()))
+        })
+    }
+}
+
+/// `key<TAB>value` lines of a dictionary file, skipping comments.
+fn entries(file: &str) -> impl Iterator<Item = (&str, &str)> {
+    file.lines()
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|l| l.split_once('\t'))
+}
+
+fn pinyin() -> &'static Dictionary {
+    static PINYIN_DICTIONARY: OnceLock<Dictionary> = OnceLock::new();
+    PINYIN_DICTIONARY.get_or_init(|| {
+        let mut dictionary = Dictionary::default();
+        for (reading, characters) in entries(PINYIN) {
+            for c in characters.chars() {
+                dictionary.insert(c.to_string(), reading);
+            }
+        }
+        for (word, reading) in entries(PINYIN_WORDS) {
+            dictionary.insert(word.to_string(), reading);
+        }
+        dictionary
+    })
+}
+
+fn kanji() -> &'static Dictionary {
+    static KANJI_DICTIONARY: OnceLock<Dictionary> = OnceLock::new();
+    KANJI_DICTIONARY.get_or_init(|| {
+        let mut dictionary = Dictionary::default();
+        for (word, reading) in entries(KANJI) {
+            dictionary.insert(word.to_string(), reading);
+        }
+        dictionary
+    })
+}
+
+fn is_han(c: char) -> bool {
+    matches!(c, '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' | '\u{F900}'..='\u{FAFF}' | '々')
+}
+
+/// Full-width and CJK punctuation as its ASCII counterpart; `None` for other characters.
+fn ascii_punctuation(c: char) -> Option<char> {
+    Some(match c {
+        '，' | '、' | '､' => ',',
+        '。' | '｡' | '．' => '.',
+        '！' => '!',
+        '？' => '?',
+        '：' => ':',
+        '；' => ';',
+        '「' | '」' | '『' | '』' | '“' | '”' | '＂' => '"',
+        '（' => '(',
+        '）' => ')',
+        '～' | '〜' => '~',
+        '…' | '‥' => '…',
+        c if c.is_ascii_punctuation() => c,
+        _ => return None,
+    })
+}
+
+/// Words of a romanized line. Punctuation attaches to the word before it.
+#[derive(Default)]
+struct Words {
+    words: Vec<String>,
+    /// The next text continues the last word rather than starting a new one.
+    joined: bool,
+}
+
+impl Words {
+    /// Starts a new word with `text`.
+    fn word(&mut self, text: &str) {
+        self.words.push(text.to_string());
+        self.joined = true;
+    }
+
+    /// Appends `text` to the current word, starting one if there is none.
+    fn push(&mut self, text: &str) {
+        match self.words.last_mut() {
+            Some(last) if self.joined => last.push_str(text),
+            _ => self.word(text),
+        }
+    }
+
+    /// Ends the current word.
+    fn end(&mut self) {
+        self.joined = false;
+    }
+
+    /// Handles a character no script rule applies to: whitespace ends the word,
+    /// punctuation ends it too, and anything else (Latin, digits) is kept as written.
+    fn other(&mut self, c: char) {
+        match ascii_punctuation(c) {
+            _ if c.is_whitespace() => self.end(),
+            Some('(') => {
+                self.end();
+                self.word("(");
+            }
+            Some(p) => {
+                match self.words.last_mut() {
+                    Some(last) => last.push(p),
+                    None => self.word(&p.to_string()),
+                }
+                self.end();
+            }
+            None => self.push(c.encode_utf8(&mut [0; 4])),
+        }
+    }
+
+    fn finish(self) -> String {
+        self.words.join(" ")
+    }
+}
+
+/// Pinyin with tone marks, one word per syllable as is usual for lyrics. `None` if a Han
+/// character is missing from the bundled dictionary.
+fn chinese(text: &str) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut words = Words::default();
+    let mut i = 0;
+    while i < chars.len() {
+        if !is_han(chars[i]) {
+            words.other(chars[i]);
+            i += 1;
+            continue;
+        }
+        let (len, reading) = pinyin().longest_match(&chars[i..])?;
+        for syllable in reading.split(' ') {
+            words.word(syllable);
+            words.end();
+        }
+        i += len;
+    }
+    Some(words.finish())
+}
+
+/// Hiragana for a katakana character, or the character itself.
+fn hiragana(c: char) -> char {
+    match c {
+        'ァ'..='ヶ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+        c => c,
+    }
+}
+
+fn is_kana(c: char) -> bool {
+    matches!(c, 'ぁ'..='ゖ' | 'ァ'..='ヺ' | 'ー')
+}
+
+fn kana_romaji(c: char) -> Option<&'static str> {
+    let c = hiragana(c);
+    KANA.iter().find_map(|(kana, romaji)| {
+        let column = kana.chars().position(|k| k == c)?;
+        romaji.split(' ').nth(column)
+    })
+}
+
+/// Hepburn romaji for a run of kana: small `ゃ`/`ゅ`/`ょ` and small vowels combine with
+/// the kana before them, `っ` doubles the next consonant and `ー` lengthens a vowel.
+fn romaji(kana: &[char]) -> String {
+    let mut out = String::new();
+    let mut double = false;
+    let mut i = 0;
+    while i < kana.len() {
+        let c = hiragana(kana[i]);
+        if c == 'っ' {
+            double = true;
+            i += 1;
+            continue;
+        }
+        if c == 'ー' {
+            if let Some(vowel) = out.chars().last().filter(|v| "aiueo".contains(*v)) {
+                out.push(vowel);
+            }
+            i += 1;
+            continue;
+        }
+        let Some(base) = kana_romaji(c) else {
+            out.push(kana[i]);
+            i += 1;
+            continue;
+        };
+        let mut syllable = base.to_string();
+        let next = kana.get(i + 1).map(|&n| hiragana(n));
+        let consonant = base.trim_end_matches(['a', 'i', 'u', 'e', 'o']);
+        match next {
+            // きゃ kya, しゃ sha, ちゃ cha, じゃ ja.
+            Some(small @ ('ゃ' | 'ゅ' | 'ょ')) if base.ends_with('i') && base.len() > 1 => {
+                let vowel = &kana_romaji(small).unwrap_or_default()[1..];
+                syllable = match consonant {
+                    "sh" | "ch" | "j" => format!("{}{}", consonant, vowel),
+                    _ => format!("{}y{}", consonant, vowel),
+                };
+                i += 1;
+            }
+            // ファ fa, ティ ti, ウィ wi, シェ she.
+            Some(small @ ('ぁ' | 'ぃ' | 'ぅ' | 'ぇ' | 'ぉ')) if c != small => {
+                let consonant = if consonant.is_empty() { "w" } else { consonant };
+                syllable = format!("{}{}", consonant, kana_romaji(small).unwrap_or_default());
+                i += 1;
+            }
+            _ => {}
+        }
+        if double {
+            // っち is tchi in Hepburn; otherwise the consonant is written twice.
+            match syllable.strip_prefix("ch") {
+                Some(_) => out.push('t'),
+                None => out.extend(syllable.chars().next().filter(|c| !"aiueon".contains(*c))),
+            }
+            double = false;
+        }
+        out.push_str(&syllable);
+        i += 1;
+    }
+    out
+}
+
+/// Romaji for a Japanese line. Kanji are read from the bundled dictionary, and the line
+/// is `None` if one is missing from it; a word is a dictionary match or katakana run with
+/// the hiragana after it, except for a particle, so `名前を` gives `namae wo`.
+fn japanese(text: &str) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut words = Words::default();
+    // The last piece was a kanji or katakana word, so hiragana continue it as okurigana.
+    let mut after_word = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if is_han(c) {
+            words.end();
+            let (len, reading) = kanji().longest_match(&chars[i..])?;
+            let reading: Vec<char> = reading.chars().collect();
+            words.push(&romaji(&reading));
+            i += len;
+            after_word = true;
+            continue;
+        }
+        if is_kana(c) {
+            let katakana = c != 'ー' && hiragana(c) != c;
+            let start = i;
+            while i < chars.len()
+                && is_kana(chars[i])
+                && (chars[i] == 'ー' || (hiragana(chars[i]) != chars[i]) == katakana)
+            {
+                i += 1;
+            }
+            let mut run = &chars[start..i];
+            if katakana {
+                words.end();
+                words.push(&romaji(run));
+                after_word = true;
+                continue;
+            }
+            if after_word {
+                let short = if run.len() == 1 { SHORT_PARTICLES } else { &[] };
+                let particle = PARTICLES.iter().chain(short).find(|&&(p, _)| p == run[0]);
+                match particle {
+                    Some(&(_, reading)) => {
+                        words.end();
+                        words.word(reading);
+                        words.end();
+                        run = &run[1..];
+                    }
+                    None => {
+                        // Okurigana: the ending of the word.
+                        words.push(&romaji(run));
+                        run = &[];
+                    }
+                }
+            }
+            if !run.is_empty() {
+                words.end();
+                words.push(&romaji(run));
+            }
+            after_word = false;
+            continue;
+        }
+        words.other(c);
+        after_word = false;
+        i += 1;
+    }
+    Some(words.finish())
+}
+
+/// Revised Romanization for Korean, syllable by syllable. A final consonant moves to the
+/// next syllable when that starts with a vowel, and `ㄹㄹ` is `ll`.
+fn korean(text: &str) -> String {
+    let syllable = |c: char| {
+        let index = (c as u32).checked_sub(0xAC00).filter(|&i| i < 11172)?;
+        Some((index / 588, index % 588 / 28, index % 28))
+    };
+    let chars: Vec<char> = text.chars().collect();
+    let mut words = Words::default();
+    for (i, &c) in chars.iter().enumerate() {
+        let Some((initial, vowel, last)) = syllable(c) else {
+            words.other(c);
+            continue;
+        };
+        let previous = i.checked_sub(1).and_then(|p| syllable(chars[p]));
+        let next = chars.get(i + 1).and_then(|&n| syllable(n));
+        let mut out = String::new();
+        let lateral = initial == HANGUL_INITIAL_R
+            && previous.is_some_and(|(_, _, last)| last == HANGUL_FINAL_L);
+        out.push_str(if lateral {
+            "l"
+        } else {
+            HANGUL_INITIALS[initial as usize]
+        });
+        out.push_str(HANGUL_VOWELS[vowel as usize]);
+        let next_initial = next.map(|(initial, _, _)| initial);
+        out.push_str(if next_initial == Some(HANGUL_SILENT_INITIAL) {
+            HANGUL_LINKED_FINALS[last as usize]
+        } else {
+            HANGUL_FINALS[last as usize]
+        });
+        words.push(&out);
+    }
+    words.finish()
+}
+
+/// Romanization of `text` written in `script`: romaji, pinyin or Revised Romanization.
+/// `None` for other scripts, when nothing was romanized, or when a kanji or hanzi has no
+/// known reading, rather than a line that mixes scripts.
+pub fn romanize(text: &str, script: Script) -> Option<String> {
+    let romanized = match script {
+        Script::Japanese => japanese(text)?,
+        Script::Chinese => chinese(text)?,
+        Script::Korean => korean(text),
+        _ => return None,
+    };
+    (!romanized.is_empty() && romanized != text).then_some(romanized)
+}
+
+/// Generates a romanization for the Japanese, Chinese and Korean lines of `lyrics` that
+/// have none from their source. Lines with only kanji are read as Japanese in lyrics that
+/// are mostly Japanese.
+pub fn fill(lyrics: &mut Lyrics) {
+    let main = pairing::dominant_script(lyrics.lines.iter().map(|l| l.text.as_str()));
+    for line in lyrics.lines.iter_mut().filter(|l| l.romanization.is_none()) {
+        let script = match Script::detect(&line.text) {
+            Script::Chinese if main == Some(Script::Japanese) => Script::Japanese,
+            script => script,
+        };
+        line.romanization = romanize(&line.text, script);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyrics::LyricLine;
+
+    #[test]
+    fn japanese_words_and_particles() {
+        assert_eq!(
+            romanize("君の名前を", Script::Japanese).as_deref(),
+            Some("kimi no namae wo")
+        );
+        assert_eq!(
+            romanize("夢、カメラ", Script::Japanese).as_deref(),
+            Some("yume, kamera")
+        );
+    }
+
+    #[test]
+    fn unread_kanji_leave_the_line_unromanized() {
+        assert_eq!(romanize("君の桛", Script::Japanese), None);
+        assert_eq!(romanize("你好桛", Script::Chinese), None);
+    }
+
+    #[test]
+    fn chinese_and_korean() {
+        assert_eq!(
+            romanize("你好，世界", Script::Chinese).as_deref(),
+            Some("nǐ hǎo, shì jiè")
+        );
+        assert_eq!(
+            romanize("사랑해", Script::Korean).as_deref(),
+            Some("saranghae")
+        );
+        assert_eq!(romanize("Hello", Script::Latin), None);
+    }
+
+    #[test]
+    fn fill_reads_kanji_lines_as_japanese_in_japanese_lyrics() {
+        let line = |text: &str| LyricLine {
+            text: text.to_string(),
+            ..LyricLine::default()
+        };
+        let mut lyrics = Lyrics {
+            lines: vec![
+                line("君の声"),
+                line("カメラ"),
+                line("名前を"),
+                line("夢"),
+                line("桛"),
+                line("Hello"),
+            ],
+            ..Lyrics::default()
+        };
+        fill(&mut lyrics);
+        let romanizations: Vec<Option<&str>> = lyrics
+            .lines
+            .iter()
+            .map(|l| l.romanization.as_deref())
+            .collect();
+        assert_eq!(
+            romanizations,
+            [
+                Some("kimi no koe"),
+                Some("kamera"),
+                Some("namae wo"),
+                Some("yume"),
+                None,
+                None
+            ]
+        );
+    }
+}
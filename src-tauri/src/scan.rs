@@ -0,0 +1,198 @@
+use crate::error::AppError;
+use crate::library::{AudioFile, LibraryState, ScanObserver, ScanSummary, TrackRecord};
+use crate::watcher::LibraryWatcher;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+pub const SCAN_BATCH_EVENT: &str = "scan-batch";
+pub const SCAN_PROGRESS_EVENT: &str = "scan-progress";
+pub const SCAN_FINISHED_EVENT: &str = "scan-finished";
+
+/// Files are flushed to the frontend once this many have accumulated...
+const BATCH_SIZE: usize = 200;
+/// ...or when this much time has passed since the last flush.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ScanBatch {
+    job_id: u64,
+    files: Vec<AudioFile>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ScanError {
+    path: Option<String>,
+    message: String,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ScanProgress {
+    job_id: u64,
+    directories_visited: usize,
+    files_matched: usize,
+    /// Errors encountered since the previous progress event.
+    errors: Vec<ScanError>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ScanFinished {
+    job_id: u64,
+    cancelled: bool,
+    error: Option<AppError>,
+    summary: Option<ScanSummary>,
+}
+
+/// Running scan jobs, keyed by id, each with its cancellation flag.
+#[derive(Default)]
+pub struct ScanJobs {
+    next_id: AtomicU64,
+    running: Mutex<HashMap<u64, Arc<AtomicBool>>>,
+}
+
+/// Streams a scan to the frontend as batched events.
+struct EventObserver {
+    app: AppHandle,
+    job_id: u64,
+    cancelled: Arc<AtomicBool>,
+    directories_visited: usize,
+    files_matched: usize,
+    pending_files: Vec<AudioFile>,
+    pending_errors: Vec<ScanError>,
+    last_flush: Instant,
+}
+
+impl EventObserver {
+    fn maybe_flush(&mut self) {
+        if self.pending_files.len() >= BATCH_SIZE || self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.pending_files.is_empty() {
+            let _ = self.app.emit(
+                SCAN_BATCH_EVENT,
+                ScanBatch {
+                    job_id: self.job_id,
+                    files: std::mem::take(&mut self.pending_files),
+                },
+            );
+        }
+        let _ = self.app.emit(
+            SCAN_PROGRESS_EVENT,
+            ScanProgress {
+                job_id: self.job_id,
+                directories_visited: self.directories_visited,
+                files_matched: self.files_matched,
+                errors: std::mem::take(&mut self.pending_errors),
+            },
+        );
+        self.last_flush = Instant::now();
+    }
+}
+
+impl ScanObserver for EventObserver {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn directory(&mut self, _path: &Path) {
+        self.directories_visited += 1;
+        self.maybe_flush();
+    }
+
+    fn file(&mut self, record: &TrackRecord) {
+        self.files_matched += 1;
+        self.pending_files.push(record.audio_file());
+        self.maybe_flush();
+    }
+
+    fn error(&mut self, path: Option<&Path>, message: String) {
+        self.pending_errors.push(ScanError {
+            path: path.map(|p| p.to_string_lossy().to_string()),
+            message,
+        });
+        self.maybe_flush();
+    }
+}
+
+/// Starts scanning `path` in the background and returns the job id. Found files arrive
+/// as `scan-batch` events, counters and errors as `scan-progress`, and the job always
+/// ends with one `scan-finished`.
+#[tauri::command]
+pub fn start_library_scan(path: String, app: AppHandle, jobs: State<'_, ScanJobs>) -> u64 {
+    let job_id = jobs.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+    let cancelled = Arc::new(AtomicBool::new(false));
+    jobs.running
+        .lock()
+        .unwrap()
+        .insert(job_id, cancelled.clone());
+
+    std::thread::spawn(move || {
+        let mut observer = EventObserver {
+            app: app.clone(),
+            job_id,
+            cancelled,
+            directories_visited: 0,
+            files_matched: 0,
+            pending_files: Vec::new(),
+            pending_errors: Vec::new(),
+            last_flush: Instant::now(),
+        };
+        let result = app.state::<LibraryState>().index_root(&path, &mut observer);
+        observer.flush();
+
+        let finished = match result {
+            Ok(Some((_, summary))) => {
+                app.state::<LibraryWatcher>().watch(&path);
+                ScanFinished {
+                    job_id,
+                    cancelled: false,
+                    error: None,
+                    summary: Some(summary),
+                }
+            }
+            Ok(None) => ScanFinished {
+                job_id,
+                cancelled: true,
+                error: None,
+                summary: None,
+            },
+            Err(e) => ScanFinished {
+                job_id,
+                cancelled: false,
+                error: Some(e),
+                summary: None,
+            },
+        };
+        app.state::<ScanJobs>()
+            .running
+            .lock()
+            .unwrap()
+            .remove(&job_id);
+        let _ = app.emit(SCAN_FINISHED_EVENT, finished);
+    });
+
+    job_id
+}
+
+/// Requests cancellation of a running scan. Returns `false` if the job already finished.
+#[tauri::command]
+pub fn cancel_library_scan(job_id: u64, jobs: State<'_, ScanJobs>) -> bool {
+    match jobs.running.lock().unwrap().get(&job_id) {
+        Some(flag) => {
+            flag.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
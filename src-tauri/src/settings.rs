@@ -0,0 +1,97 @@
+use crate::cache::{TranscodeCache, DEFAULT_CACHE_LIMIT};
+use crate::error::AppError;
+use crate::ffmpeg;
+use crate::profile::TranscodeProfile;
+use crate::sidecar::LyricsSearch;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::State;
+
+/// File name of the persisted settings inside the app data directory.
+const SETTINGS_FILE: &str = "settings.json";
+
+/// Backend settings. Fields missing from an older file take their defaults.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    /// ffmpeg executable chosen by the user; tried before the bundled and system copies.
+    pub ffmpeg_path: Option<String>,
+    /// ffprobe executable; defaults to the one next to `ffmpeg_path`.
+    pub ffprobe_path: Option<String>,
+    /// Byte budget of the transcode cache; `DEFAULT_CACHE_LIMIT` when unset.
+    pub transcode_cache_limit: Option<u64>,
+    /// Output format of transcodes; applies to tracks prepared after it changes.
+    pub transcode_profile: TranscodeProfile,
+    /// Where lyrics files next to the audio (or in shared folders) are looked for.
+    pub lyrics_search: LyricsSearch,
+}
+
+impl Settings {
+    pub fn transcode_cache_limit(&self) -> u64 {
+        self.transcode_cache_limit.unwrap_or(DEFAULT_CACHE_LIMIT)
+    }
+}
+
+/// Tauri-managed handle to the on-disk settings.
+pub struct SettingsState {
+    settings: Mutex<Settings>,
+    file: PathBuf,
+}
+
+impl SettingsState {
+    /// Loads `settings.json` from `data_dir`, using defaults if it is missing or unreadable.
+    pub fn load(data_dir: &Path) -> Self {
+        let file = data_dir.join(SETTINGS_FILE);
+        let settings = match std::fs::read(&file) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                log::warn!("Failed to parse settings {}: {}", file.display(), e);
+                Settings::default()
+            }),
+            Err(_) => Settings::default(),
+        };
+        SettingsState {
+            settings: Mutex::new(settings),
+            file,
+        }
+    }
+
+    pub fn get(&self) -> Settings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    fn save(&self, settings: &Settings) -> Result<(), AppError> {
+        if let Some(dir) = self.file.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| AppError::io("create settings directory", Some(dir), e))?;
+        }
+        let data = serde_json::to_vec_pretty(settings).map_err(|e| AppError::Io {
+            action: "serialize settings".to_string(),
+            path: None,
+            message: e.to_string(),
+        })?;
+        let tmp = self.file.with_extension("json.tmp");
+        std::fs::write(&tmp, data).map_err(|e| AppError::io("write settings", Some(&tmp), e))?;
+        std::fs::rename(&tmp, &self.file)
+            .map_err(|e| AppError::io("replace settings", Some(&self.file), e))
+    }
+}
+
+#[tauri::command]
+pub fn get_settings(state: State<'_, SettingsState>) -> Settings {
+    state.get()
+}
+
+/// Replaces and persists the settings, applying them to the running session.
+#[tauri::command]
+pub fn set_settings(
+    settings: Settings,
+    state: State<'_, SettingsState>,
+    cache: State<'_, TranscodeCache>,
+) -> Result<(), AppError> {
+    state.save(&settings)?;
+    ffmpeg::configure(&settings);
+    cache.set_limit(settings.transcode_cache_limit());
+    *state.settings.lock().unwrap() = settings;
+    Ok(())
+}
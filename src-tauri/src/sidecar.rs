@@ -0,0 +1,417 @@
+use crate::lyrics::{self, Lyrics, LyricsSource, LyricsSourceKind};
+use crate::tags::Tags;
+use crate::text;
+use crate::{pairing, romanize};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// How a lyrics file name is matched to a track. Names are compared case-insensitively,
+/// ignoring characters that cannot appear in file names.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum NameRule {
+    /// `Song.lrc` for `Song.flac`.
+    FileName,
+    /// `Song.zh.lrc` or `Song.en-US.lrc`: the audio file name plus a language tag.
+    FileNameLanguage,
+    /// `Artist - Title.lrc` from the track's tags.
+    ArtistTitle,
+    /// `Title.lrc` from the track's tags.
+    Title,
+}
+
+/// Where lyrics files are looked for and which names count, as chosen in the settings.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LyricsSearch {
+    /// Folders searched in order. Relative paths are resolved against the audio file's
+    /// folder, so `.` is the folder itself and `Lyrics` a subfolder of it; absolute paths
+    /// name a shared lyrics folder.
+    pub folders: Vec<String>,
+    /// Name rules, most preferred first.
+    pub rules: Vec<NameRule>,
+    /// Lyrics file extensions, most preferred first. `.txt` files may be LRC or plain
+    /// text; the content decides.
+    pub extensions: Vec<String>,
+    /// Language tags `FileNameLanguage` prefers, e.g. `["zh", "ja"]`; files in other
+    /// languages are still used after these.
+    pub languages: Vec<String>,
+    /// Use lyrics embedded in the tags even when a lyrics file exists.
+    pub prefer_embedded: bool,
+}
+
+impl Default for LyricsSearch {
+    fn default() -> Self {
+        LyricsSearch {
+            folders: vec![".".to_string(), "Lyrics".to_string()],
+            rules: vec![
+                NameRule::FileName,
+                NameRule::FileNameLanguage,
+                NameRule::ArtistTitle,
+                NameRule::Title,
+            ],
+            extensions: ["lrc", "ttml", "srt", "vtt", "ass", "txt"]
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+            languages: Vec::new(),
+            prefer_embedded: false,
+        }
+    }
+}
+
+/// Lower-cases `name` and drops characters that are invalid in file names (taggers and
+/// download tools strip or replace them differently), collapsing the whitespace left.
+fn normalise(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .filter(|c| !matches!(c, '\\' | '/' | ':' | '*' | '?' | '"' | '<' | '>' | '|'))
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether `tag` looks like a language tag: `zh`, `jpn`, `zh-Hans`, `en_US`.
+fn is_language_tag(tag: &str) -> bool {
+    let mut parts = tag.split(['-', '_']);
+    let primary = parts.next().unwrap_or_default();
+    (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && parts
+            .all(|p| !p.is_empty() && p.len() <= 8 && p.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// A file in one of the searched folders.
+struct Candidate {
+    path: PathBuf,
+    /// Normalised file name without the extension.
+    stem: String,
+    /// Lower-cased extension.
+    extension: String,
+}
+
+fn candidates_in(dir: &Path, extensions: &[String]) -> Vec<Candidate> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut candidates: Vec<Candidate> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .filter_map(|path| {
+            let extension = path.extension()?.to_string_lossy().to_lowercase();
+            if !extensions.contains(&extension) {
+                return None;
+            }
+            let stem = normalise(&path.file_stem()?.to_string_lossy());
+            Some(Candidate {
+                path,
+                stem,
+                extension,
+            })
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.path.cmp(&b.path));
+    candidates
+}
+
+/// The names a lyrics file for one track may have, normalised.
+struct TrackNames {
+    /// `None` for tracks cut from a single-file rip.
+    file_stem: Option<String>,
+    title: Option<String>,
+    artist_title: Option<String>,
+    /// Preferred language tags, lower-cased.
+    languages: Vec<String>,
+}
+
+impl TrackNames {
+    /// Whether `candidate` matches under `rule`, and if so its rank among the matches of
+    /// that rule (lower is better).
+    fn rank(&self, rule: NameRule, candidate: &Candidate) -> Option<usize> {
+        let stem = Some(&candidate.stem);
+        match rule {
+            NameRule::FileName => (stem == self.file_stem.as_ref()).then_some(0),
+            NameRule::FileNameLanguage => {
+                let (stem, tag) = candidate.stem.rsplit_once('.')?;
+                if Some(stem) != self.file_stem.as_deref() || !is_language_tag(tag) {
+                    return None;
+                }
+                // Preferred languages first, in order, then any other.
+                let tag = tag.replace('_', "-");
+                let primary = tag.split('-').next().unwrap_or_default();
+                let position = self
+                    .languages
+                    .iter()
+                    .position(|l| *l == tag || l == primary);
+                Some(position.unwrap_or(self.languages.len()))
+            }
+            NameRule::ArtistTitle => (stem == self.artist_title.as_ref()).then_some(0),
+            NameRule::Title => (stem == self.title.as_ref()).then_some(0),
+        }
+    }
+}
+
+/// Lyrics files for the track at `audio_path`, best match first: by folder, then rule,
+/// then extension. Tracks cut from a single-file rip (`start` is set) are only matched by
+/// their tags, since a file named after the rip would time the whole album.
+pub fn find(
+    audio_path: &Path,
+    start: Option<f64>,
+    tags: &Tags,
+    options: &LyricsSearch,
+) -> Vec<PathBuf> {
+    let Some(audio_dir) = audio_path.parent() else {
+        return Vec::new();
+    };
+    let title = tags
+        .title
+        .as_deref()
+        .map(normalise)
+        .filter(|t| !t.is_empty());
+    let names = TrackNames {
+        file_stem: match start {
+            Some(_) => None,
+            None => audio_path
+                .file_stem()
+                .map(|s| normalise(&s.to_string_lossy())),
+        },
+        artist_title: match (&tags.artist, &title) {
+            (Some(artist), Some(title)) => Some(format!("{} - {}", normalise(artist), title)),
+            _ => None,
+        },
+        title,
+        languages: options.languages.iter().map(|l| l.to_lowercase()).collect(),
+    };
+    let extensions: Vec<String> = options
+        .extensions
+        .iter()
+        .map(|e| e.trim_start_matches('.').to_lowercase())
+        .collect();
+
+    let mut found: Vec<PathBuf> = Vec::new();
+    for folder in &options.folders {
+        // `join` keeps absolute folders as they are.
+        let candidates = candidates_in(&audio_dir.join(folder), &extensions);
+        for &rule in &options.rules {
+            for extension in &extensions {
+                let mut matches: Vec<(usize, &Candidate)> = candidates
+                    .iter()
+                    .filter(|c| &c.extension == extension)
+                    .filter_map(|c| Some((names.rank(rule, c)?, c)))
+                    .collect();
+                matches.sort_by_key(|&(rank, _)| rank);
+                for (_, candidate) in matches {
+                    if !found.contains(&candidate.path) {
+                        found.push(candidate.path.clone());
+                    }
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Reads and parses a lyrics file, decoding its text with charset detection.
+fn load(path: &Path) -> Option<Lyrics> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            log::warn!("Failed to read lyrics {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    Some(lyrics::parse(&text::decode(&bytes)))
+}
+
+/// The lyrics to show for a track: the first lyrics file found by `find`, otherwise the
+/// embedded lyrics (tried first with `prefer_embedded`); timed lyrics from any of them win
+/// over plain text. The other timed files and lyrics tags (`other_embedded`) that are in
+/// a different script, such as `Song.zh.lrc` or a `lyrics-eng` tag, are merged in as
+/// translations or romanizations.
+/// Japanese, Chinese and Korean lines still without a romanization get a generated one.
+/// The result records which source its lines came from.
+pub fn resolve(
+    audio_path: &Path,
+    start: Option<f64>,
+    tags: &Tags,
+    embedded: Option<&str>,
+    other_embedded: &[String],
+    options: &LyricsSearch,
+) -> Option<Lyrics> {
+    let embedded_source = || LyricsSource {
+        kind: LyricsSourceKind::Embedded,
+        path: None,
+    };
+    let mut embedded: Vec<Lyrics> = embedded
+        .into_iter()
+        .chain(other_embedded.iter().map(String::as_str))
+        .map(|text| Lyrics {
+            source: Some(embedded_source()),
+            ..lyrics::parse(text)
+        })
+        .filter(|l| !l.lines.is_empty())
+        .collect();
+    let sidecars = find(audio_path, start, tags, options)
+        .into_iter()
+        .filter_map(|path| {
+            let parsed = load(&path)?;
+            Some(Lyrics {
+                source: Some(LyricsSource {
+                    kind: LyricsSourceKind::Sidecar,
+                    path: Some(path.to_string_lossy().into_owned()),
+                }),
+                ..parsed
+            })
+        })
+        .filter(|l| !l.lines.is_empty());
+
+    let mut sources: Vec<Lyrics> = if options.prefer_embedded {
+        embedded.extend(sidecars);
+        embedded
+    } else {
+        sidecars.chain(embedded).collect()
+    };
+    // Plain text only when nothing timed was found. Stable, so the order above holds.
+    sources.sort_by_key(|l| l.unsynced);
+    let mut sources = sources.into_iter();
+    let mut lyrics = sources.next()?;
+    // Untimed lines have nothing to line up a translation by.
+    let timed = !lyrics.unsynced;
+    for other in sources.filter(|other| timed && !other.unsynced) {
+        if pairing::merge(&mut lyrics.lines, &other.lines) {
+            let from = other.source.as_ref().and_then(|s| s.path.as_deref());
+            log::info!("Merged translated lyrics from {}", from.unwrap_or("tags"));
+        }
+    }
+    romanize::fill(&mut lyrics);
+    Some(lyrics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh folder holding `files`, removed when dropped.
+    struct Folder(PathBuf);
+
+    impl Folder {
+        fn new(name: &str, files: &[&str]) -> Self {
+            let dir = std::env::temp_dir().join(format!("sidecar_{}_{}", std::process::id(), name));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            for file in files {
+                std::fs::write(dir.join(file), "[00:01.00]x\n").unwrap();
+            }
+            Folder(dir)
+        }
+
+        fn names(&self, found: &[PathBuf]) -> Vec<String> {
+            found
+                .iter()
+                .map(|p| {
+                    p.strip_prefix(&self.0)
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect()
+        }
+    }
+
+    impl Drop for Folder {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn tags(artist: Option<&str>, title: &str) -> Tags {
+        Tags {
+            artist: artist.map(str::to_string),
+            title: Some(title.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rules_folders_and_extensions_in_order() {
+        let folder = Folder::new(
+            "order",
+            &[
+                "Song.srt",
+                "Song.lrc",
+                "Song.zh.lrc",
+                "Artist - Title.lrc",
+                "Other.lrc",
+            ],
+        );
+        let found = find(
+            &folder.0.join("Song.flac"),
+            None,
+            &tags(Some("Artist"), "Title"),
+            &LyricsSearch::default(),
+        );
+        assert_eq!(
+            folder.names(&found),
+            ["Song.lrc", "Song.srt", "Song.zh.lrc", "Artist - Title.lrc"]
+        );
+    }
+
+    #[test]
+    fn title_rule_with_and_without_artist() {
+        let folder = Folder::new("title", &["Title.lrc", "Artist - Title.lrc"]);
+        let audio = folder.0.join("01.flac");
+        let options = LyricsSearch::default();
+        assert_eq!(
+            folder.names(&find(
+                &audio,
+                None,
+                &tags(Some("Artist"), "Title"),
+                &options
+            )),
+            ["Artist - Title.lrc", "Title.lrc"]
+        );
+        assert_eq!(
+            folder.names(&find(&audio, None, &tags(None, "Title"), &options)),
+            ["Title.lrc"]
+        );
+    }
+
+    #[test]
+    fn plain_text_is_shown_unsynced() {
+        let folder = Folder::new("txt", &[]);
+        std::fs::write(
+            folder.0.join("Song.txt"),
+            "[ti:Song]\nFirst line\n\nSecond line\n",
+        )
+        .unwrap();
+        let audio = folder.0.join("Song.flac");
+        let options = LyricsSearch::default();
+        let lyrics = resolve(&audio, None, &Tags::default(), None, &[], &options).unwrap();
+        assert!(lyrics.unsynced);
+        assert_eq!(lyrics.title.as_deref(), Some("Song"));
+        let texts: Vec<&str> = lyrics.lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, ["First line", "Second line"]);
+        assert_eq!(lyrics.source.unwrap().kind, LyricsSourceKind::Sidecar);
+    }
+
+    #[test]
+    fn timed_lyrics_win_over_plain_text() {
+        let folder = Folder::new("timed", &[]);
+        std::fs::write(folder.0.join("Song.txt"), "Plain line\n").unwrap();
+        let audio = folder.0.join("Song.flac");
+        let options = LyricsSearch::default();
+        let embedded = Some("[00:01.00]Timed line");
+        let lyrics = resolve(&audio, None, &Tags::default(), embedded, &[], &options).unwrap();
+        assert!(!lyrics.unsynced);
+        assert_eq!(lyrics.lines[0].text, "Timed line");
+        assert_eq!(lyrics.source.unwrap().kind, LyricsSourceKind::Embedded);
+
+        // A `.txt` file holding LRC is timed.
+        std::fs::write(folder.0.join("Song.txt"), "[00:02.00]LRC in a text file\n").unwrap();
+        let lyrics = resolve(&audio, None, &Tags::default(), embedded, &[], &options).unwrap();
+        assert_eq!(lyrics.lines[0].text, "LRC in a text file");
+        assert_eq!(lyrics.source.unwrap().kind, LyricsSourceKind::Sidecar);
+    }
+}
@@ -0,0 +1,716 @@
+use crate::cache::{CacheKey, TranscodeCache};
+use crate::error::AppError;
+use crate::ffmpeg;
+use crate::metadata::AudioProperties;
+use crate::profile::AppliedProfile;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStderr, ChildStdout, Output, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::{AppHandle, Manager, Runtime, State, UriSchemeContext, UriSchemeResponder};
+
+/// URI scheme the webview loads streams from; the frontend builds URLs with
+/// `convertFileSrc(id, 'stream')`.
+pub const STREAM_SCHEME: &str = "stream";
+
+/// `KSDATAFORMAT_SUBTYPE_PCM`, the sub-format of a `WAVE_FORMAT_EXTENSIBLE` header.
+const PCM_SUBFORMAT: [u8; 16] = [
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+/// Largest body served per range request; the webview simply asks for the next range.
+const MAX_RESPONSE_LEN: u64 = 1024 * 1024;
+/// A request this far (in seconds of audio) past the decoder's position restarts decoding
+/// at the requested offset instead of waiting for the decoder to get there.
+const SEEK_AHEAD_SECONDS: u64 = 3;
+/// How long a request waits for the decoder before failing.
+const READ_TIMEOUT: Duration = Duration::from_secs(20);
+const PIPE_BUFFER_LEN: usize = 64 * 1024;
+
+#[derive(Clone, Copy)]
+struct PcmFormat {
+    sample_rate: u32,
+    channels: u32,
+    /// 16 or 24.
+    bits: u32,
+}
+
+impl PcmFormat {
+    fn frame_len(&self) -> u64 {
+        u64::from(self.channels) * u64::from(self.bits / 8)
+    }
+
+    /// Like ffmpeg, use `WAVE_FORMAT_EXTENSIBLE` for anything beyond 16-bit stereo.
+    fn extensible(&self) -> bool {
+        self.channels > 2 || self.bits > 16
+    }
+
+    fn header_len(&self) -> u64 {
+        if self.extensible() {
+            68
+        } else {
+            44
+        }
+    }
+
+    /// ffmpeg's raw muxer for this sample format.
+    fn raw_format(&self) -> &'static str {
+        if self.bits == 24 {
+            "s24le"
+        } else {
+            "s16le"
+        }
+    }
+
+    fn byte_rate(&self) -> u64 {
+        u64::from(self.sample_rate) * self.frame_len()
+    }
+
+    fn wav_header(&self, data_len: u32) -> Vec<u8> {
+        let header_len = self.header_len() as u32;
+        let mut header = Vec::with_capacity(header_len as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(data_len + header_len - 8).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        let fmt_len: u32 = if self.extensible() { 40 } else { 16 };
+        header.extend_from_slice(&fmt_len.to_le_bytes());
+        let format_tag: u16 = if self.extensible() { 0xfffe } else { 1 }; // PCM
+        header.extend_from_slice(&format_tag.to_le_bytes());
+        header.extend_from_slice(&(self.channels as u16).to_le_bytes());
+        header.extend_from_slice(&self.sample_rate.to_le_bytes());
+        header.extend_from_slice(&(self.byte_rate() as u32).to_le_bytes());
+        header.extend_from_slice(&(self.frame_len() as u16).to_le_bytes());
+        header.extend_from_slice(&(self.bits as u16).to_le_bytes());
+        if self.extensible() {
+            header.extend_from_slice(&22u16.to_le_bytes()); // size of the extension
+            header.extend_from_slice(&(self.bits as u16).to_le_bytes()); // valid bits
+
+            // No speaker positions: the source layout is kept but not necessarily known.
+            header.extend_from_slice(&0u32.to_le_bytes());
+            header.extend_from_slice(&PCM_SUBFORMAT);
+        }
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&data_len.to_le_bytes());
+        header
+    }
+}
+
+struct Decoder {
+    child: Child,
+    /// Next data offset the decoder will write.
+    position: u64,
+}
+
+struct SessionState {
+    /// The staging file while the stream is incomplete, the cache file afterwards.
+    file: PathBuf,
+    /// Data ranges already written, sorted and non-overlapping.
+    filled: Vec<(u64, u64)>,
+    decoder: Option<Decoder>,
+    /// Incremented on every decoder restart; output of older decoders is discarded.
+    generation: u64,
+    complete: bool,
+    closed: bool,
+    error: Option<AppError>,
+}
+
+impl SessionState {
+    fn filled_range_at(&self, offset: u64) -> Option<(u64, u64)> {
+        self.filled
+            .iter()
+            .copied()
+            .find(|&(start, end)| start <= offset && offset < end)
+    }
+
+    /// Start of the next filled range after `offset`, i.e. where a decoder writing from
+    /// `offset` has to stop.
+    fn next_filled_start(&self, offset: u64, data_len: u64) -> u64 {
+        self.filled
+            .iter()
+            .map(|&(start, _)| start)
+            .find(|&start| start > offset)
+            .unwrap_or(data_len)
+    }
+
+    fn first_hole(&self, data_len: u64) -> Option<u64> {
+        let mut expected = 0;
+        for &(start, end) in &self.filled {
+            if start > expected {
+                return Some(expected);
+            }
+            expected = end;
+        }
+        (expected < data_len).then_some(expected)
+    }
+
+    fn mark_filled(&mut self, start: u64, end: u64) {
+        if start >= end {
+            return;
+        }
+        self.filled.push((start, end));
+        self.filled.sort_unstable();
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(self.filled.len());
+        for &(start, end) in &self.filled {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        self.filled = merged;
+    }
+
+    fn stop_decoder(&mut self) {
+        if let Some(mut decoder) = self.decoder.take() {
+            let _ = decoder.child.kill();
+            let _ = decoder.child.wait();
+        }
+    }
+}
+
+/// One track being transcoded to WAV while it is played. The file is preallocated with a
+/// header for its final size, so the webview sees a complete, seekable WAV whose data
+/// arrives progressively.
+pub struct StreamSession {
+    app: AppHandle,
+    source: PathBuf,
+    /// Where the track starts in `source`, for tracks cut from a cue sheet.
+    start: f64,
+    profile: AppliedProfile,
+    format: PcmFormat,
+    header: Vec<u8>,
+    data_len: u64,
+    key: CacheKey,
+    state: Mutex<SessionState>,
+    changed: Condvar,
+}
+
+impl StreamSession {
+    fn header_len(&self) -> u64 {
+        self.header.len() as u64
+    }
+
+    fn total_len(&self) -> u64 {
+        self.header_len() + self.data_len
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SessionState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Starts (or restarts) decoding at data offset `from`, replacing any running decoder.
+    fn spawn_decoder(
+        self: &Arc<Self>,
+        state: &mut SessionState,
+        from: u64,
+    ) -> Result<(), AppError> {
+        state.stop_decoder();
+        state.generation += 1;
+        let generation = state.generation;
+        let from = from - from % self.format.frame_len();
+
+        let mut cmd = ffmpeg::ffmpeg_command()?;
+        cmd.arg("-hide_banner").arg("-loglevel").arg("error");
+        let seek = self.start + from as f64 / self.format.byte_rate() as f64;
+        if seek > 0.0 {
+            cmd.arg("-ss").arg(format!("{:.3}", seek));
+        }
+        cmd.arg("-i").arg(&self.source);
+        self.profile.add_audio_args(&mut cmd);
+        cmd.arg("-f")
+            .arg(self.format.raw_format())
+            .arg("pipe:1")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| AppError::io("run ffmpeg", None, e))?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+        state.decoder = Some(Decoder {
+            child,
+            position: from,
+        });
+
+        let session = self.clone();
+        std::thread::spawn(move || session.run_decoder(generation, from, stdout, stderr));
+        Ok(())
+    }
+
+    /// Copies ffmpeg's output into the file until the decoder reaches data that is already
+    /// there, the end of the track, or is replaced by a newer one.
+    fn run_decoder(
+        self: Arc<Self>,
+        generation: u64,
+        from: u64,
+        mut stdout: ChildStdout,
+        mut stderr: ChildStderr,
+    ) {
+        let file_path = self.lock().file.clone();
+        let mut file = match OpenOptions::new().write(true).open(&file_path) {
+            Ok(file) => file,
+            Err(e) => {
+                self.fail(generation, AppError::io("open", Some(&file_path), e));
+                return;
+            }
+        };
+        let mut buf = vec![0u8; PIPE_BUFFER_LEN];
+        let mut position = from;
+        loop {
+            let n = match stdout.read(&mut buf) {
+                Ok(n) => n,
+                Err(e) => {
+                    self.fail(generation, AppError::io("read ffmpeg output", None, e));
+                    return;
+                }
+            };
+            let mut state = self.lock();
+            if state.generation != generation || state.closed {
+                return;
+            }
+            let limit = state.next_filled_start(position, self.data_len);
+            if n == 0 {
+                // ffmpeg is done; whatever it did not produce up to `limit` stays silent.
+                let mut decoder = state.decoder.take();
+                drop(state);
+                let mut stderr_text = Vec::new();
+                let _ = stderr.read_to_end(&mut stderr_text);
+                let status = decoder.as_mut().and_then(|d| d.child.wait().ok());
+                if let Some(status) = status.filter(|s| !s.success() && position == from) {
+                    let output = Output {
+                        status,
+                        stdout: Vec::new(),
+                        stderr: stderr_text,
+                    };
+                    self.fail(generation, AppError::from_ffmpeg(&self.source, &output));
+                    return;
+                }
+                let mut state = self.lock();
+                if state.generation != generation || state.closed {
+                    return;
+                }
+                state.mark_filled(position, limit);
+                self.decoder_finished(&mut state);
+                return;
+            }
+
+            let end = (position + n as u64).min(limit);
+            let write = file
+                .seek(SeekFrom::Start(self.header_len() + position))
+                .and_then(|_| file.write_all(&buf[..(end - position) as usize]));
+            if let Err(e) = write {
+                drop(state);
+                self.fail(generation, AppError::io("write", Some(&file_path), e));
+                return;
+            }
+            state.mark_filled(position, end);
+            position = end;
+            if let Some(decoder) = state.decoder.as_mut() {
+                decoder.position = position;
+            }
+            if position >= limit {
+                state.stop_decoder();
+                self.decoder_finished(&mut state);
+                return;
+            }
+            self.changed.notify_all();
+        }
+    }
+
+    /// Called when a decoder stopped on its own: fills the next hole left by seeking, or
+    /// moves the finished file into the transcode cache.
+    fn decoder_finished(self: &Arc<Self>, state: &mut SessionState) {
+        state.decoder = None;
+        match state.first_hole(self.data_len) {
+            Some(hole) => {
+                if let Err(e) = self.spawn_decoder(state, hole) {
+                    state.error = Some(e);
+                }
+            }
+            None => {
+                state.complete = true;
+                let cache = self.app.state::<TranscodeCache>();
+                match cache.insert(&self.key, &state.file) {
+                    Ok(path) => state.file = path,
+                    Err(e) => log::warn!("Failed to cache finished stream: {}", e),
+                }
+            }
+        }
+        self.changed.notify_all();
+    }
+
+    fn fail(&self, generation: u64, error: AppError) {
+        let mut state = self.lock();
+        if state.generation == generation && !state.closed {
+            log::warn!("Stream decoder failed: {}", error);
+            state.decoder = None;
+            state.error = Some(error);
+            self.changed.notify_all();
+        }
+    }
+
+    /// Reads up to `max_len` bytes at `offset` of the WAV file, waiting for the decoder if
+    /// they have not been written yet and restarting it at `offset` if it is far behind.
+    fn read(self: &Arc<Self>, offset: u64, max_len: u64) -> Result<Vec<u8>, AppError> {
+        let deadline = Instant::now() + READ_TIMEOUT;
+        let seek_ahead = SEEK_AHEAD_SECONDS * self.format.byte_rate();
+        let mut state = self.lock();
+        let mut restarted = false;
+        loop {
+            if let Some(error) = &state.error {
+                return Err(error.clone());
+            }
+            if state.closed {
+                return Err(AppError::invalid_argument("Stream was closed"));
+            }
+
+            let header_len = self.header_len();
+            if offset < header_len {
+                // The header is always available; add whatever data follows it.
+                let mut bytes = self.header[offset as usize..].to_vec();
+                bytes.truncate(max_len as usize);
+                if let Some((_, end)) = state.filled_range_at(0) {
+                    let len = end.min(max_len - bytes.len() as u64);
+                    bytes.extend(read_at(&state.file, header_len, len)?);
+                }
+                return Ok(bytes);
+            }
+
+            let data_offset = offset - header_len;
+            if let Some((_, end)) = state.filled_range_at(data_offset) {
+                let len = (end - data_offset).min(max_len);
+                return read_at(&state.file, offset, len);
+            }
+
+            let decoder_near = state.decoder.as_ref().is_some_and(|d| {
+                d.position <= data_offset && data_offset - d.position <= seek_ahead
+            });
+            if !decoder_near && !restarted {
+                self.spawn_decoder(&mut state, data_offset)?;
+                restarted = true;
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(AppError::TranscodeFailed {
+                    path: self.source.display().to_string(),
+                    exit_status: None,
+                    stderr: "Timed out waiting for decoded audio".to_string(),
+                });
+            }
+            state = self.changed.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+
+    fn close(&self) {
+        let mut state = self.lock();
+        if state.closed {
+            return;
+        }
+        state.closed = true;
+        state.stop_decoder();
+        if !state.complete {
+            let _ = std::fs::remove_file(&state.file);
+        }
+        self.app.state::<TranscodeCache>().unpin(&self.key);
+        self.changed.notify_all();
+    }
+}
+
+fn read_at(path: &Path, offset: u64, len: u64) -> Result<Vec<u8>, AppError> {
+    let mut file = File::open(path).map_err(|e| AppError::io("open", Some(path), e))?;
+    let mut bytes = vec![0u8; len as usize];
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.read_exact(&mut bytes))
+        .map_err(|e| AppError::io("read", Some(path), e))?;
+    Ok(bytes)
+}
+
+/// Tauri-managed table of open streams.
+#[derive(Default)]
+pub struct StreamRegistry {
+    sessions: Mutex<HashMap<u64, Arc<StreamSession>>>,
+    next_id: AtomicU64,
+}
+
+impl StreamRegistry {
+    /// Starts streaming `path` (from `start` seconds, for `properties.duration` seconds)
+    /// as a WAV in the format of `profile` and returns the stream id. Returns `Ok(None)`
+    /// when the length is unknown or too long for a WAV file, in which case the caller
+    /// transcodes the whole file instead.
+    pub fn open(
+        &self,
+        app: &AppHandle,
+        path: &Path,
+        start: Option<f64>,
+        properties: &AudioProperties,
+        profile: &AppliedProfile,
+        key: CacheKey,
+    ) -> Result<Option<u64>, AppError> {
+        let Some(duration) = properties.duration.filter(|d| *d > 0.0) else {
+            return Ok(None);
+        };
+        let format = PcmFormat {
+            sample_rate: profile.sample_rate,
+            channels: profile.channels,
+            bits: profile.bit_depth.unwrap_or(16),
+        };
+        let frames = (duration * f64::from(format.sample_rate)).round() as u64;
+        let data_len = frames * format.frame_len();
+        let riff_len = format.header_len() - 8 + data_len;
+        let Ok(header_data_len) = u32::try_from(riff_len).map(|_| data_len as u32) else {
+            return Ok(None);
+        };
+
+        let cache = app.state::<TranscodeCache>();
+        let file = cache.staging_path(&key, "wav");
+        let create = File::create(&file).and_then(|mut f| {
+            f.set_len(format.header_len() + data_len)?;
+            f.write_all(&format.wav_header(header_data_len))
+        });
+        create.map_err(|e| AppError::io("create", Some(&file), e))?;
+        // Once finished the stream is served from its cache entry, which must not be
+        // evicted under it; `close` releases the pin.
+        cache.pin(&key);
+
+        let session = Arc::new(StreamSession {
+            app: app.clone(),
+            source: path.to_path_buf(),
+            start: start.unwrap_or(0.0),
+            profile: profile.clone(),
+            format,
+            header: format.wav_header(header_data_len),
+            data_len,
+            key,
+            state: Mutex::new(SessionState {
+                file,
+                filled: Vec::new(),
+                decoder: None,
+                generation: 0,
+                complete: false,
+                closed: false,
+                error: None,
+            }),
+            changed: Condvar::new(),
+        });
+        {
+            let mut state = session.lock();
+            if let Err(e) = session.spawn_decoder(&mut state, 0) {
+                drop(state);
+                session.close();
+                return Err(e);
+            }
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.sessions.lock().unwrap().insert(id, session);
+        Ok(Some(id))
+    }
+
+    fn get(&self, id: u64) -> Option<Arc<StreamSession>> {
+        self.sessions.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Stops decoding stream `id` and releases it. Finished streams stay in the transcode
+    /// cache; unfinished ones are deleted.
+    pub fn close(&self, id: u64) {
+        if let Some(session) = self.sessions.lock().unwrap().remove(&id) {
+            session.close();
+        }
+    }
+}
+
+/// Releases stream `id`; see `StreamRegistry::close`.
+#[tauri::command]
+pub fn close_audio_stream(id: u64, streams: State<'_, StreamRegistry>) {
+    streams.close(id);
+}
+
+/// Resolves a single `bytes=` range against a resource of `total` bytes to the inclusive
+/// byte range to serve, or `None` when it is malformed or cannot be satisfied. Without a
+/// `Range` header the whole resource is served.
+fn parse_range(value: Option<&str>, total: u64) -> Option<(u64, u64)> {
+    if total == 0 {
+        return None;
+    }
+    let Some(value) = value else {
+        return Some((0, total - 1));
+    };
+    let spec = value
+        .trim()
+        .strip_prefix("bytes=")?
+        .split(',')
+        .next()?
+        .trim();
+    let (start, end) = spec.split_once('-')?;
+    if start.is_empty() {
+        let suffix: u64 = end.parse().ok()?;
+        if suffix == 0 {
+            return None;
+        }
+        return Some((total.saturating_sub(suffix), total - 1));
+    }
+    let start: u64 = start.parse().ok()?;
+    let end = match end {
+        "" => total - 1,
+        end => end.parse::<u64>().ok()?.min(total - 1),
+    };
+    // Ranges starting past the end, or ending before they start, cannot be served.
+    if start >= total || end < start {
+        return None;
+    }
+    Some((start, end))
+}
+
+fn error_response(status: StatusCode, body: String) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(body.into_bytes())
+        .unwrap()
+}
+
+fn respond(session: &Arc<StreamSession>, range: Option<&str>) -> Response<Vec<u8>> {
+    let total = session.total_len();
+    let Some((start, end)) = parse_range(range, total) else {
+        return Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .header(header::CONTENT_RANGE, format!("bytes */{}", total))
+            .body(Vec::new())
+            .unwrap();
+    };
+    let builder = Response::builder()
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(header::CONTENT_TYPE, "audio/wav")
+        .header(header::ACCEPT_RANGES, "bytes");
+
+    if range.is_none() {
+        // Media elements always send a range; anything else gets the whole file, which
+        // means waiting for the decoder to reach the end.
+        return match read_all(session, total) {
+            Ok(bytes) => builder
+                .status(StatusCode::OK)
+                .header(header::CONTENT_LENGTH, bytes.len())
+                .body(bytes)
+                .unwrap(),
+            Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        };
+    }
+
+    let max_len = (end + 1 - start).min(MAX_RESPONSE_LEN);
+    match session.read(start, max_len) {
+        Ok(bytes) => builder
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(
+                header::CONTENT_RANGE,
+                format!(
+                    "bytes {}-{}/{}",
+                    start,
+                    start + bytes.len() as u64 - 1,
+                    total
+                ),
+            )
+            .header(header::CONTENT_LENGTH, bytes.len())
+            .body(bytes)
+            .unwrap(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+/// Reads the whole stream, chunk by chunk as the decoder produces it.
+fn read_all(session: &Arc<StreamSession>, total: u64) -> Result<Vec<u8>, AppError> {
+    let mut bytes = Vec::with_capacity(total as usize);
+    while (bytes.len() as u64) < total {
+        let offset = bytes.len() as u64;
+        let chunk = session.read(offset, (total - offset).min(MAX_RESPONSE_LEN))?;
+        if chunk.is_empty() {
+            break;
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Handler for the `stream://` scheme: `stream://localhost/<id>` serves stream `id`,
+/// honouring `Range` requests. Reads may wait for the decoder, so they run off the
+/// webview's thread.
+pub fn handle_request<R: Runtime>(
+    ctx: UriSchemeContext<'_, R>,
+    request: Request<Vec<u8>>,
+    responder: UriSchemeResponder,
+) {
+    let id = request.uri().path().trim_start_matches('/').parse::<u64>();
+    let session = id
+        .ok()
+        .and_then(|id| ctx.app_handle().state::<StreamRegistry>().get(id));
+    let Some(session) = session else {
+        responder.respond(error_response(
+            StatusCode::NOT_FOUND,
+            format!("Unknown stream {}", request.uri().path()),
+        ));
+        return;
+    };
+    let range = request
+        .headers()
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    std::thread::spawn(move || responder.respond(respond(&session, range.as_deref())));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_byte_ranges() {
+        let cases = [
+            ("bytes=0-99", (0, 99)),
+            ("bytes=500-", (500, 999)),
+            ("bytes=-100", (900, 999)),
+            ("bytes=-5000", (0, 999)),
+            ("bytes=900-5000", (900, 999)),
+            ("bytes=10-20, 30-40", (10, 20)),
+        ];
+        for (header, expected) in cases {
+            assert_eq!(
+                parse_range(Some(header), 1000),
+                Some(expected),
+                "{}",
+                header
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unsatisfiable_ranges() {
+        let cases = [
+            "bytes=99-0",
+            "bytes=1000-",
+            "bytes=1000-1100",
+            "bytes=-0",
+            "bytes=a-b",
+            "items=0-99",
+        ];
+        for header in cases {
+            assert_eq!(parse_range(Some(header), 1000), None, "{}", header);
+        }
+    }
+
+    #[test]
+    fn missing_header_selects_everything() {
+        assert_eq!(parse_range(None, 1000), Some((0, 999)));
+    }
+
+    #[test]
+    fn empty_resource_is_never_satisfiable() {
+        assert_eq!(parse_range(None, 0), None);
+        assert_eq!(parse_range(Some("bytes=0-"), 0), None);
+    }
+}
@@ -0,0 +1,387 @@
+use crate::lyrics::{LyricLine, LyricWord, Lyrics};
+
+/// ASS karaoke durations (`\k`) are in centiseconds.
+const KARAOKE_UNIT: f64 = 0.01;
+
+/// Parses a subtitle timestamp: `01:02:03,456` (SRT), `01:02:03.456` or `02:03.456`
+/// (WebVTT, hours optional), `1:02:03.45` (ASS).
+fn parse_clock(value: &str) -> Option<f64> {
+    let value = value.trim().replace(',', ".");
+    let parts: Vec<&str> = value.split(':').collect();
+    if !(2..=3).contains(&parts.len()) {
+        return None;
+    }
+    parts.iter().try_fold(0.0, |total, part| {
+        let number = part.parse::<f64>().ok().filter(|n| *n >= 0.0)?;
+        Some(total * 60.0 + number)
+    })
+}
+
+/// Parses a cue timing line such as `00:00:01,000 --> 00:00:04,000 position:10%`.
+fn parse_timing(line: &str) -> Option<(f64, f64)> {
+    let (start, rest) = line.split_once("-->")?;
+    let end = rest.split_whitespace().next()?;
+    Some((parse_clock(start)?, parse_clock(end)?))
+}
+
+/// Removes markup from a cue line: HTML-style tags (`<i>`, `<c.yellow>`, `<v Singer>`)
+/// and the `{\an8}` style overrides some SRT files borrow from ASS.
+fn strip_markup(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut closing = None;
+    for c in text.chars() {
+        match (closing, c) {
+            (None, '<') => closing = Some('>'),
+            (None, '{') => closing = Some('}'),
+            (Some(close), c) if c == close => closing = None,
+            (Some(_), _) => {}
+            (None, c) => plain.push(c),
+        }
+    }
+    plain
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+}
+
+/// Words of a WebVTT karaoke cue line, which marks when each word starts with an inline
+/// `<00:00:01.500>` timestamp. `None` if the line has none.
+fn vtt_words(line: &str, start: f64, end: f64) -> Option<Vec<LyricWord>> {
+    // (start time, text with markup) pieces, split at timestamp tags.
+    let mut pieces: Vec<(f64, String)> = vec![(start, String::new())];
+    let mut rest = line;
+    while let Some(open) = rest.find('<') {
+        let Some(close) = rest[open..].find('>').map(|i| open + i) else {
+            break;
+        };
+        let piece = &mut pieces.last_mut().expect("starts with a piece").1;
+        piece.push_str(&rest[..open]);
+        match parse_clock(&rest[open + 1..close]) {
+            Some(time) => pieces.push((time, String::new())),
+            // Other tags are left for `strip_markup`.
+            None => piece.push_str(&rest[open..=close]),
+        }
+        rest = &rest[close + 1..];
+    }
+    pieces
+        .last_mut()
+        .expect("starts with a piece")
+        .1
+        .push_str(rest);
+    if pieces.len() == 1 {
+        return None;
+    }
+
+    let mut words: Vec<LyricWord> = Vec::new();
+    for (i, (time, piece)) in pieces.iter().enumerate() {
+        let text = strip_markup(piece);
+        if text.trim().is_empty() {
+            if let Some(previous) = words.last_mut().filter(|_| !text.is_empty()) {
+                previous.text.push(' ');
+            }
+            continue;
+        }
+        words.push(LyricWord {
+            text,
+            start_time: *time,
+            end_time: pieces.get(i + 1).map_or(end, |p| p.0),
+        });
+    }
+    if let Some(first) = words.first_mut() {
+        first.text = first.text.trim_start().to_string();
+    }
+    if let Some(last) = words.last_mut() {
+        last.text = last.text.trim_end().to_string();
+    }
+    (!words.is_empty()).then_some(words)
+}
+
+/// Lines of one cue. Cues with several lines (usually an original and its translation in
+/// bilingual subtitles) become lines sharing the cue's start time.
+fn cue_lines(start: f64, end: f64, text: &[&str], lines: &mut Vec<LyricLine>) {
+    for line in text {
+        let words = vtt_words(line, start, end).unwrap_or_default();
+        let text = if words.is_empty() {
+            strip_markup(line).trim().to_string()
+        } else {
+            words.iter().map(|w| w.text.as_str()).collect()
+        };
+        if text.is_empty() {
+            continue;
+        }
+        lines.push(LyricLine {
+            time: start,
+            text,
+            words,
+            ..LyricLine::default()
+        });
+    }
+}
+
+/// Parses SRT or WebVTT subtitles: blocks separated by blank lines, each with an optional
+/// identifier, a `start --> end` timing line and the text. WebVTT `NOTE`, `STYLE` and
+/// `REGION` blocks have no timing line and are skipped with the header.
+pub fn parse_srt(text: &str) -> Lyrics {
+    let mut lines = Vec::new();
+    let normalised = text.replace("\r\n", "\n").replace('\r', "\n");
+    for block in normalised.split("\n\n") {
+        let block_lines: Vec<&str> = block.lines().collect();
+        let Some(timing) = block_lines.iter().position(|l| l.contains("-->")) else {
+            continue;
+        };
+        let Some((start, end)) = parse_timing(block_lines[timing]) else {
+            continue;
+        };
+        let text: Vec<&str> = block_lines[timing + 1..]
+            .iter()
+            .copied()
+            .filter(|l| !l.trim().is_empty())
+            .collect();
+        cue_lines(start, end, &text, &mut lines);
+    }
+    lines.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Lyrics {
+        lines,
+        ..Lyrics::default()
+    }
+}
+
+/// Splits the text of an ASS `Dialogue` line at `\k` karaoke tags into timed syllables,
+/// and strips all other override tags. Returns the words (empty without karaoke) and the
+/// plain text.
+fn ass_text(text: &str, start: f64, end: f64) -> (Vec<LyricWord>, String) {
+    let mut words: Vec<LyricWord> = Vec::new();
+    let mut plain = String::new();
+    let mut time = start;
+    // Duration of the syllable that follows, from the last `\k` tag.
+    let mut duration: Option<f64> = None;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (segment, after) = match rest.find('{') {
+            Some(open) => (&rest[..open], &rest[open..]),
+            None => (rest, ""),
+        };
+        let segment = segment
+            .replace("\\N", " ")
+            .replace("\\n", " ")
+            .replace("\\h", " ");
+        if !segment.is_empty() {
+            plain.push_str(&segment);
+            if let Some(length) = duration.take() {
+                words.push(LyricWord {
+                    text: segment.clone(),
+                    start_time: time,
+                    end_time: (time + length).min(end),
+                });
+                time += length;
+            }
+        }
+        let Some(close) = after.find('}') else {
+            break;
+        };
+        for tag in after[1..close].split('\\').skip(1) {
+            // `\k`, `\K`, `\kf` and `\ko` all start a syllable of the given length.
+            let digits = tag
+                .strip_prefix("kf")
+                .or_else(|| tag.strip_prefix("ko"))
+                .or_else(|| tag.strip_prefix('k'))
+                .or_else(|| tag.strip_prefix('K'));
+            if let Some(length) = digits.and_then(|d| d.trim().parse::<f64>().ok()) {
+                if let Some(pending) = duration.take() {
+                    // A `\k` with no text is a pause.
+                    time += pending;
+                }
+                duration = Some(length * KARAOKE_UNIT);
+            }
+        }
+        rest = &after[close + 1..];
+    }
+    let plain = plain.split_whitespace().collect::<Vec<_>>().join(" ");
+    // Syllables keep their own spaces, but the line should not start or end with one.
+    if let Some(first) = words.first_mut() {
+        first.text = first.text.trim_start().to_string();
+    }
+    if let Some(last) = words.last_mut() {
+        last.text = last.text.trim_end().to_string();
+    }
+    words.retain(|w| !w.text.is_empty());
+    (words, plain)
+}
+
+/// Parses ASS/SSA subtitles: the `Dialogue` lines of the `[Events]` section, laid out by
+/// its `Format` line. `\k` karaoke tags give syllable timing; a `\N` line break splits an
+/// original from its translation, which then share the line's start time.
+pub fn parse_ass(text: &str) -> Lyrics {
+    let mut lines = Vec::new();
+    let mut in_events = false;
+    // Field positions from the `Format:` line; the ASS default order until one is seen.
+    let mut fields: Vec<String> = [
+        "layer", "start", "end", "style", "name", "marginl", "marginr", "marginv", "effect", "text",
+    ]
+    .iter()
+    .map(|f| f.to_string())
+    .collect();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_events = line.eq_ignore_ascii_case("[events]");
+            continue;
+        }
+        if !in_events {
+            continue;
+        }
+        let Some((kind, value)) = line.split_once(':') else {
+            continue;
+        };
+        match kind.trim().to_ascii_lowercase().as_str() {
+            "format" => {
+                fields = value
+                    .split(',')
+                    .map(|f| f.trim().to_ascii_lowercase())
+                    .collect();
+            }
+            "dialogue" => {
+                // The text is last and may itself contain commas.
+                let values: Vec<&str> = value.splitn(fields.len(), ',').collect();
+                let field = |name: &str| {
+                    fields
+                        .iter()
+                        .position(|f| f == name)
+                        .and_then(|i| values.get(i))
+                        .copied()
+                };
+                let (Some(start), Some(end), Some(text)) = (
+                    field("start").and_then(parse_clock),
+                    field("end").and_then(parse_clock),
+                    field("text"),
+                ) else {
+                    continue;
+                };
+                for (i, part) in text.split("\\N").enumerate() {
+                    let (words, plain) = ass_text(part, start, end);
+                    if plain.is_empty() {
+                        continue;
+                    }
+                    lines.push(LyricLine {
+                        time: start,
+                        text: if words.is_empty() {
+                            plain
+                        } else {
+                            words.iter().map(|w| w.text.as_str()).collect()
+                        },
+                        // Karaoke timing is for the sung line, not the translation under it.
+                        words: if i == 0 { words } else { Vec::new() },
+                        ..LyricLine::default()
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    lines.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Lyrics {
+        lines,
+        ..Lyrics::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str, start_time: f64, end_time: f64) -> LyricWord {
+        LyricWord {
+            text: text.to_string(),
+            start_time,
+            end_time,
+        }
+    }
+
+    #[test]
+    fn clock_formats() {
+        assert_eq!(parse_clock("01:02:03,456"), Some(3723.456));
+        assert_eq!(parse_clock("02:03.5"), Some(123.5));
+        assert_eq!(parse_clock("1:02:03.45"), Some(3723.45));
+        assert_eq!(parse_clock("3.5"), None);
+        assert_eq!(parse_clock("00:-01"), None);
+    }
+
+    #[test]
+    fn srt_cues_and_bilingual_lines() {
+        let lyrics = parse_srt(
+            "2\r\n00:00:05,000 --> 00:00:07,000\r\n<i>Second</i> line\r\n\r\n\
+             1\r\n00:00:01,000 --> 00:00:04,000\r\n{\\an8}First &amp; line\r\n第一行\r\n\r\n\
+             3\r\nbroken --> timing\r\nSkipped\r\n",
+        );
+        let lines: Vec<(f64, &str)> = lyrics
+            .lines
+            .iter()
+            .map(|l| (l.time, l.text.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [(1.0, "First & line"), (1.0, "第一行"), (5.0, "Second line")]
+        );
+        assert!(lyrics.lines.iter().all(|l| l.words.is_empty()));
+    }
+
+    #[test]
+    fn vtt_header_notes_and_karaoke() {
+        let lyrics = parse_srt(
+            "WEBVTT\n\nNOTE a comment\n\nintro\n00:01.000 --> 00:03.000 align:start\n\
+             <v Singer>Hel<00:01.500>lo <00:02.000><c>world</c></v>\n",
+        );
+        assert_eq!(lyrics.lines.len(), 1);
+        let line = &lyrics.lines[0];
+        assert_eq!(line.text, "Hello world");
+        assert_eq!(
+            line.words,
+            [
+                word("Hel", 1.0, 1.5),
+                word("lo ", 1.5, 2.0),
+                word("world", 2.0, 3.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn ass_karaoke_and_translation() {
+        let lyrics = parse_ass(
+            "[Script Info]\nTitle: Song\n\n[V4+ Styles]\nFormat: Name, Fontname\n\n[Events]\n\
+             Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+             Dialogue: 0,0:00:05.00,0:00:06.00,Default,,0,0,0,,{\\k20}{\\k30}Hi, there\n\
+             Comment: 0,0:00:00.00,0:00:09.00,Default,,0,0,0,,Not shown\n\
+             Dialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,{\\k50}Hel{\\k50}lo {\\k100}world\\N你好世界\n",
+        );
+        assert_eq!(lyrics.lines.len(), 3);
+        let line = &lyrics.lines[0];
+        assert_eq!((line.time, line.text.as_str()), (1.0, "Hello world"));
+        assert_eq!(
+            line.words,
+            [
+                word("Hel", 1.0, 1.5),
+                word("lo ", 1.5, 2.0),
+                word("world", 2.0, 3.0)
+            ]
+        );
+        let line = &lyrics.lines[1];
+        assert_eq!((line.time, line.text.as_str()), (1.0, "你好世界"));
+        assert!(line.words.is_empty());
+        // A `\k` with no text is a pause; commas in the text are kept.
+        let line = &lyrics.lines[2];
+        assert_eq!(line.text, "Hi, there");
+        assert_eq!(line.words, [word("Hi, there", 5.2, 5.5)]);
+    }
+
+    #[test]
+    fn ass_custom_field_order() {
+        let lyrics = parse_ass(
+            "[Events]\nFormat: Start, End, Text\nDialogue: 0:00:02.00,0:00:04.00,{\\i1}Plain{\\i0} text\n",
+        );
+        assert_eq!(lyrics.lines.len(), 1);
+        assert_eq!(lyrics.lines[0].time, 2.0);
+        assert_eq!(lyrics.lines[0].text, "Plain text");
+        assert!(lyrics.lines[0].words.is_empty());
+    }
+}
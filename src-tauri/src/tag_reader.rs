@@ -0,0 +1,840 @@
+use base64::{engine::general_purpose, Engine as _};
+use encoding_rs::{UTF_16BE, UTF_16LE, WINDOWS_1252};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Upper bound for any single tag structure we load into memory (ID3v2 tag, FLAC block,
+/// MP4 `moov`, Ogg comment packet). Cover art is the only thing that gets near this.
+const MAX_TAG_SIZE: u64 = 32 * 1024 * 1024;
+
+/// ID3v2 / FLAC picture type of the front cover.
+const FRONT_COVER: u32 = 3;
+
+/// Tags read in-process, for when ffprobe is not available. Keys are close enough to what
+/// ffprobe reports for `Tags::from_raw` to map them; lyrics come through as a `lyrics` field.
+#[derive(Default, Debug)]
+pub struct NativeTags {
+    /// Raw fields in file order; repeated keys have already been joined with "; ".
+    pub fields: Vec<(String, String)>,
+    /// Embedded cover art, preferring the front cover when there are several pictures.
+    pub picture: Option<Vec<u8>>,
+    picture_type: Option<u32>,
+}
+
+impl NativeTags {
+    fn push(&mut self, key: &str, value: String) {
+        let value = value.trim_end_matches('\0').trim().to_string();
+        if value.is_empty() {
+            return;
+        }
+        match self
+            .fields
+            .iter_mut()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        {
+            Some((_, existing)) if *existing != value => {
+                existing.push_str("; ");
+                existing.push_str(&value);
+            }
+            Some(_) => {}
+            None => self.fields.push((key.to_string(), value)),
+        }
+    }
+
+    fn set_picture(&mut self, data: Vec<u8>, picture_type: u32) {
+        let better = match self.picture_type {
+            None => true,
+            Some(current) => current != FRONT_COVER && picture_type == FRONT_COVER,
+        };
+        if better && !data.is_empty() {
+            self.picture = Some(data);
+            self.picture_type = Some(picture_type);
+        }
+    }
+}
+
+/// Reads the tags of `path`. Supports ID3v2/ID3v1 (MP3 and anything else with an ID3v2
+/// header), FLAC, Ogg Vorbis/Opus and MP4/M4A.
+pub fn read(path: &Path) -> Result<NativeTags, String> {
+    let mut file =
+        File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut tags = NativeTags::default();
+
+    let mut header = [0u8; 12];
+    let len = read_up_to(&mut file, &mut header)?;
+    let header = &header[..len];
+
+    let mut offset = 0u64;
+    if header.starts_with(b"ID3") {
+        offset = read_id3v2(&mut file, &mut tags)?;
+    }
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|e| e.to_string())?;
+    let mut magic = [0u8; 8];
+    let len = read_up_to(&mut file, &mut magic)?;
+    let magic = &magic[..len];
+
+    if magic.starts_with(b"fLaC") {
+        read_flac(&mut file, offset + 4, &mut tags)?;
+    } else if magic.starts_with(b"OggS") {
+        read_ogg(&mut file, offset, &mut tags)?;
+    } else if magic.get(4..8) == Some(b"ftyp") {
+        read_mp4(&mut file, &mut tags)?;
+    } else if offset == 0 {
+        // Plain MP3 without an ID3v2 tag may still have the old fixed-size tag at the end.
+        if !read_id3v1(&mut file, &mut tags)? {
+            return Err(format!("No supported tags found in {}", path.display()));
+        }
+    }
+    Ok(tags)
+}
+
+fn read_up_to(file: &mut File, buf: &mut [u8]) -> Result<usize, String> {
+    let mut len = 0;
+    while len < buf.len() {
+        match file.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(len)
+}
+
+fn read_exact_at(file: &mut File, offset: u64, len: u64) -> Result<Vec<u8>, String> {
+    if len > MAX_TAG_SIZE {
+        return Err(format!("Tag block of {} bytes is too large", len));
+    }
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|e| e.to_string())?;
+    let mut buf = vec![0u8; len as usize];
+    file.read_exact(&mut buf).map_err(|e| e.to_string())?;
+    Ok(buf)
+}
+
+fn be_u32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn le_u32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn syncsafe(b: &[u8]) -> u32 {
+    b.iter()
+        .take(4)
+        .fold(0, |acc, &x| (acc << 7) | (x & 0x7F) as u32)
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    WINDOWS_1252
+        .decode_without_bom_handling(bytes)
+        .0
+        .into_owned()
+}
+
+/// A small cursor over a byte slice that never panics on truncated input.
+struct Bytes<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Bytes<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Bytes { data, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(n)?;
+        let slice = self.data.get(self.pos..end)?;
+        self.pos = end;
+        Some(slice)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = self.data.get(self.pos..).unwrap_or_default();
+        self.pos = self.data.len();
+        rest
+    }
+
+    fn u32_le(&mut self) -> Option<u32> {
+        self.take(4).map(le_u32)
+    }
+
+    fn u32_be(&mut self) -> Option<u32> {
+        self.take(4).map(be_u32)
+    }
+}
+
+/// ID3v2.2 uses three-letter frame ids; map the ones we read to their v2.3 names.
+const ID3V22_FRAMES: &[(&str, &str)] = &[
+    ("TT2", "TIT2"),
+    ("TP1", "TPE1"),
+    ("TP2", "TPE2"),
+    ("TAL", "TALB"),
+    ("TRK", "TRCK"),
+    ("TPA", "TPOS"),
+    ("TYE", "TYER"),
+    ("TCO", "TCON"),
+    ("TCM", "TCOM"),
+    ("TXX", "TXXX"),
+    ("COM", "COMM"),
+    ("ULT", "USLT"),
+    ("PIC", "APIC"),
+];
+
+/// Removes ID3 unsynchronisation (every `FF 00` becomes `FF`).
+fn unsynchronise(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut prev = 0u8;
+    for &b in data {
+        if !(prev == 0xFF && b == 0x00) {
+            out.push(b);
+        }
+        prev = b;
+    }
+    out
+}
+
+/// Splits `data` at the first string terminator of text encoding `encoding`.
+fn split_terminated(data: &[u8], encoding: u8) -> (&[u8], &[u8]) {
+    if matches!(encoding, 1 | 2) {
+        let mut i = 0;
+        while i + 1 < data.len() {
+            if data[i] == 0 && data[i + 1] == 0 {
+                return (&data[..i], &data[i + 2..]);
+            }
+            i += 2;
+        }
+        (data, &[])
+    } else {
+        match data.iter().position(|&b| b == 0) {
+            Some(i) => (&data[..i], &data[i + 1..]),
+            None => (data, &[]),
+        }
+    }
+}
+
+fn decode_id3_text(data: &[u8], encoding: u8) -> String {
+    match encoding {
+        1 => {
+            if data.starts_with(&[0xFE, 0xFF]) {
+                UTF_16BE.decode_with_bom_removal(data).0.into_owned()
+            } else {
+                UTF_16LE.decode_with_bom_removal(data).0.into_owned()
+            }
+        }
+        2 => UTF_16BE.decode_without_bom_handling(data).0.into_owned(),
+        3 => String::from_utf8_lossy(data).into_owned(),
+        _ => latin1(data),
+    }
+}
+
+/// Text frame values; ID3v2.4 separates multiple values with terminators.
+fn decode_id3_values(data: &[u8], encoding: u8) -> String {
+    let mut values = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (value, tail) = split_terminated(rest, encoding);
+        let value = decode_id3_text(value, encoding);
+        if !value.is_empty() {
+            values.push(value);
+        }
+        rest = tail;
+    }
+    values.join("; ")
+}
+
+/// Parses the ID3v2 tag at the start of the file. Returns the offset just past it.
+fn read_id3v2(file: &mut File, tags: &mut NativeTags) -> Result<u64, String> {
+    let header = read_exact_at(file, 0, 10)?;
+    let major = header[3];
+    let flags = header[5];
+    let size = syncsafe(&header[6..10]) as u64;
+    // A footer (v2.4 only) repeats the header after the frames.
+    let end = 10
+        + size
+        + if major == 4 && flags & 0x10 != 0 {
+            10
+        } else {
+            0
+        };
+    if !(2..=4).contains(&major) {
+        return Ok(end);
+    }
+
+    let mut body = read_exact_at(file, 10, size)?;
+    if major < 4 && flags & 0x80 != 0 {
+        body = unsynchronise(&body);
+    }
+    let mut frames = Bytes::new(&body);
+    if major >= 3 && flags & 0x40 != 0 {
+        // Extended header: v2.3 gives its size excluding the size field, v2.4 including it.
+        let size = frames.take(4).map_or(0, |b| match major {
+            3 => be_u32(b) as usize,
+            _ => (syncsafe(b) as usize).saturating_sub(4),
+        });
+        frames.take(size);
+    }
+
+    loop {
+        let (id, size, format_flags) = if major == 2 {
+            let Some(h) = frames.take(6) else { break };
+            let id = String::from_utf8_lossy(&h[..3]).to_string();
+            let id = ID3V22_FRAMES
+                .iter()
+                .find(|(old, _)| *old == id)
+                .map_or(id, |(_, new)| new.to_string());
+            let size = (h[3] as usize) << 16 | (h[4] as usize) << 8 | h[5] as usize;
+            (id, size, 0u8)
+        } else {
+            let Some(h) = frames.take(10) else { break };
+            let size = if major == 4 {
+                syncsafe(&h[4..8])
+            } else {
+                be_u32(&h[4..8])
+            };
+            (
+                String::from_utf8_lossy(&h[..4]).to_string(),
+                size as usize,
+                h[9],
+            )
+        };
+        if id.starts_with('\0') || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            break; // padding
+        }
+        let Some(data) = frames.take(size) else { break };
+
+        let (compressed_or_encrypted, frame_unsync, length_indicator) = match major {
+            3 => (format_flags & 0xC0 != 0, false, false),
+            4 => (
+                format_flags & 0x0C != 0,
+                format_flags & 0x02 != 0 || flags & 0x80 != 0,
+                format_flags & 0x01 != 0,
+            ),
+            _ => (false, false, false),
+        };
+        if compressed_or_encrypted {
+            continue;
+        }
+        let mut data = data.to_vec();
+        if length_indicator && data.len() >= 4 {
+            data.drain(..4);
+        }
+        if frame_unsync {
+            data = unsynchronise(&data);
+        }
+        read_id3_frame(&id, &data, major, tags);
+    }
+    Ok(end)
+}
+
+fn read_id3_frame(id: &str, data: &[u8], major: u8, tags: &mut NativeTags) {
+    let Some((&encoding, body)) = data.split_first() else {
+        return;
+    };
+    match id {
+        "TXXX" => {
+            let (description, value) = split_terminated(body, encoding);
+            let description = decode_id3_text(description, encoding);
+            tags.push(
+                &format!("TXXX:{}", description),
+                decode_id3_values(value, encoding),
+            );
+        }
+        "COMM" | "USLT" => {
+            let Some(text) = body.get(3..) else { return };
+            let (description, value) = split_terminated(text, encoding);
+            let description = decode_id3_text(description, encoding);
+            let value = decode_id3_text(value, encoding);
+            if id == "USLT" {
+                tags.push("lyrics", value);
+            } else if !description.starts_with("iTun") {
+                // iTunes stores normalisation and gapless data in described comments.
+                tags.push("comment", value);
+            }
+        }
+        "APIC" => {
+            let mut rest = Bytes::new(body);
+            if major == 2 {
+                // Three-letter image format ("JPG", "PNG") instead of a MIME type.
+                rest.take(3);
+            } else {
+                let (_mime, tail) = split_terminated(rest.rest(), 0);
+                rest = Bytes::new(tail);
+            }
+            let Some(&[picture_type]) = rest.take(1) else {
+                return;
+            };
+            let (_description, image) = split_terminated(rest.rest(), encoding);
+            tags.set_picture(image.to_vec(), picture_type as u32);
+        }
+        _ if id.starts_with('T') => tags.push(id, decode_id3_values(body, encoding)),
+        _ => {}
+    }
+}
+
+/// Reads the 128-byte ID3v1 tag at the end of the file. Returns whether there was one.
+fn read_id3v1(file: &mut File, tags: &mut NativeTags) -> Result<bool, String> {
+    let len = file.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+    if len < 128 {
+        return Ok(false);
+    }
+    let tag = read_exact_at(file, len - 128, 128)?;
+    if !tag.starts_with(b"TAG") {
+        return Ok(false);
+    }
+    let field = |range: std::ops::Range<usize>| {
+        let bytes = &tag[range];
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        latin1(&bytes[..end])
+    };
+    tags.push("title", field(3..33));
+    tags.push("artist", field(33..63));
+    tags.push("album", field(63..93));
+    tags.push("date", field(93..97));
+    // ID3v1.1 puts the track number in the last byte of the comment.
+    if tag[125] == 0 && tag[126] != 0 {
+        tags.push("comment", field(97..125));
+        tags.push("track", tag[126].to_string());
+    } else {
+        tags.push("comment", field(97..127));
+    }
+    Ok(true)
+}
+
+/// Reads the metadata blocks following the `fLaC` marker at `offset`.
+fn read_flac(file: &mut File, mut offset: u64, tags: &mut NativeTags) -> Result<(), String> {
+    loop {
+        let header = read_exact_at(file, offset, 4)?;
+        let last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7F;
+        let len = be_u32(&[0, header[1], header[2], header[3]]) as u64;
+        offset += 4;
+        match block_type {
+            4 => read_vorbis_comments(&read_exact_at(file, offset, len)?, tags),
+            6 => read_flac_picture(&read_exact_at(file, offset, len)?, tags),
+            _ => {}
+        }
+        offset += len;
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+/// A Vorbis comment block: little-endian lengths, `KEY=value` entries.
+fn read_vorbis_comments(data: &[u8], tags: &mut NativeTags) {
+    let mut bytes = Bytes::new(data);
+    let Some(vendor_len) = bytes.u32_le() else {
+        return;
+    };
+    bytes.take(vendor_len as usize);
+    let Some(count) = bytes.u32_le() else { return };
+
+    let mut cover_art: Option<String> = None;
+    for _ in 0..count {
+        let Some(len) = bytes.u32_le() else { break };
+        let Some(entry) = bytes.take(len as usize) else {
+            break;
+        };
+        let entry = String::from_utf8_lossy(entry);
+        let Some((key, value)) = entry.split_once('=') else {
+            continue;
+        };
+        match key.to_ascii_uppercase().as_str() {
+            "METADATA_BLOCK_PICTURE" => {
+                if let Ok(block) = general_purpose::STANDARD.decode(value.trim()) {
+                    read_flac_picture(&block, tags);
+                }
+            }
+            // Pre-standard cover art: the bare image, base64-encoded.
+            "COVERART" => cover_art = Some(value.to_string()),
+            "LYRICS" | "UNSYNCEDLYRICS" => tags.push("lyrics", value.to_string()),
+            _ => tags.push(key, value.to_string()),
+        }
+    }
+    if tags.picture.is_none() {
+        if let Some(image) = cover_art.and_then(|v| general_purpose::STANDARD.decode(v.trim()).ok())
+        {
+            tags.set_picture(image, FRONT_COVER);
+        }
+    }
+}
+
+/// A FLAC `PICTURE` block (also the payload of `METADATA_BLOCK_PICTURE`).
+fn read_flac_picture(data: &[u8], tags: &mut NativeTags) {
+    let mut bytes = Bytes::new(data);
+    let Some(picture_type) = bytes.u32_be() else {
+        return;
+    };
+    let Some(mime_len) = bytes.u32_be() else {
+        return;
+    };
+    bytes.take(mime_len as usize);
+    let Some(description_len) = bytes.u32_be() else {
+        return;
+    };
+    bytes.take(description_len as usize);
+    // Width, height, colour depth, palette size.
+    bytes.take(16);
+    let Some(len) = bytes.u32_be() else { return };
+    if let Some(image) = bytes.take(len as usize) {
+        tags.set_picture(image.to_vec(), picture_type);
+    }
+}
+
+/// Reads the comment header, which is always the second packet of the first logical stream.
+fn read_ogg(file: &mut File, offset: u64, tags: &mut NativeTags) -> Result<(), String> {
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|e| e.to_string())?;
+    let mut serial = None;
+    let mut packet_index = 0;
+    let mut packet = Vec::new();
+
+    loop {
+        let mut header = [0u8; 27];
+        if read_up_to(file, &mut header)? < header.len() || &header[..4] != b"OggS" {
+            return Err("Truncated Ogg stream".to_string());
+        }
+        let page_serial = le_u32(&header[14..18]);
+        let mut lacing = vec![0u8; header[26] as usize];
+        file.read_exact(&mut lacing).map_err(|e| e.to_string())?;
+        let mut body = vec![0u8; lacing.iter().map(|&l| l as usize).sum()];
+        file.read_exact(&mut body).map_err(|e| e.to_string())?;
+        if *serial.get_or_insert(page_serial) != page_serial {
+            continue;
+        }
+
+        let mut pos = 0;
+        for &len in &lacing {
+            if packet_index == 1 {
+                packet.extend_from_slice(&body[pos..pos + len as usize]);
+                if packet.len() as u64 > MAX_TAG_SIZE {
+                    return Err("Ogg comment packet is too large".to_string());
+                }
+            }
+            pos += len as usize;
+            // A lacing value below 255 ends the packet.
+            if len < 255 {
+                if packet_index == 1 {
+                    if let Some(comments) = packet.strip_prefix(b"\x03vorbis") {
+                        read_vorbis_comments(comments, tags);
+                    } else if let Some(comments) = packet.strip_prefix(b"OpusTags") {
+                        read_vorbis_comments(comments, tags);
+                    }
+                    return Ok(());
+                }
+                packet_index += 1;
+            }
+        }
+    }
+}
+
+/// Iterates the child atoms of `data` as (type, payload).
+fn atoms(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut bytes = Bytes::new(data);
+    std::iter::from_fn(move || {
+        let size = bytes.u32_be()? as usize;
+        let kind: [u8; 4] = bytes.take(4)?.try_into().ok()?;
+        let payload = match size {
+            // 64-bit size follows the type.
+            1 => {
+                let large = bytes.take(8)?;
+                let size = u64::from_be_bytes(large.try_into().ok()?) as usize;
+                bytes.take(size.checked_sub(16)?)?
+            }
+            0 => bytes.rest(),
+            _ => bytes.take(size.checked_sub(8)?)?,
+        };
+        Some((kind, payload))
+    })
+}
+
+fn find_atom<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    atoms(data).find(|(k, _)| k == kind).map(|(_, p)| p)
+}
+
+/// Finds `moov` among the top-level atoms; it may come after the media data.
+fn read_mp4(file: &mut File, tags: &mut NativeTags) -> Result<(), String> {
+    let file_len = file.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+    let mut offset = 0u64;
+    while file_len.saturating_sub(offset) >= 8 {
+        let header = read_exact_at(file, offset, 8)?;
+        let mut size = be_u32(&header[..4]) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            size = u64::from_be_bytes(read_exact_at(file, offset + 8, 8)?.try_into().unwrap());
+            header_len = 16;
+        } else if size == 0 {
+            size = file_len - offset;
+        }
+        if size < header_len {
+            break;
+        }
+        if &header[4..8] == b"moov" {
+            let moov = read_exact_at(file, offset + header_len, size - header_len)?;
+            read_ilst(&moov, tags);
+            return Ok(());
+        }
+        // A corrupt 64-bit size must not wrap the offset back into the file.
+        match offset.checked_add(size) {
+            Some(next) => offset = next,
+            None => break,
+        }
+    }
+    Err("No moov atom found".to_string())
+}
+
+fn read_ilst(moov: &[u8], tags: &mut NativeTags) {
+    let Some(meta) = find_atom(moov, b"udta").and_then(|udta| find_atom(udta, b"meta")) else {
+        return;
+    };
+    // `meta` is a full box (4 bytes of version/flags) in MP4, but not in QuickTime files.
+    let meta = if meta.get(4..8) == Some(b"hdlr") {
+        meta
+    } else {
+        meta.get(4..).unwrap_or_default()
+    };
+    let Some(ilst) = find_atom(meta, b"ilst") else {
+        return;
+    };
+
+    for (kind, item) in atoms(ilst) {
+        let key = latin1(&kind);
+        if &kind == b"----" {
+            // Freeform item: `mean` (reverse-DNS owner), `name`, `data`.
+            let field = |k: &[u8; 4]| {
+                find_atom(item, k)
+                    .and_then(|p| p.get(4..))
+                    .map(|p| String::from_utf8_lossy(p).to_string())
+            };
+            if let (Some(mean), Some(name), Some((_, value))) =
+                (field(b"mean"), field(b"name"), mp4_data(item).next())
+            {
+                tags.push(
+                    &format!("----:{}:{}", mean, name),
+                    String::from_utf8_lossy(value).to_string(),
+                );
+            }
+            continue;
+        }
+        for (data_type, value) in mp4_data(item) {
+            match &kind {
+                b"covr" => tags.set_picture(value.to_vec(), FRONT_COVER),
+                b"trkn" | b"disk" => {
+                    // Binary: 2 bytes padding, number, total (big-endian u16 each).
+                    if let (Some(n), Some(total)) = (value.get(2..4), value.get(4..6)) {
+                        let n = u16::from_be_bytes([n[0], n[1]]);
+                        let total = u16::from_be_bytes([total[0], total[1]]);
+                        let key = if &kind == b"trkn" { "track" } else { "disc" };
+                        let value = if total > 0 {
+                            format!("{}/{}", n, total)
+                        } else {
+                            n.to_string()
+                        };
+                        tags.push(key, value);
+                    }
+                }
+                b"\xA9lyr" => tags.push("lyrics", String::from_utf8_lossy(value).to_string()),
+                // Well-known types 1 (UTF-8) and 2 (UTF-16BE) are text.
+                _ if data_type == 1 => tags.push(&key, String::from_utf8_lossy(value).to_string()),
+                _ if data_type == 2 => tags.push(
+                    &key,
+                    UTF_16BE.decode_without_bom_handling(value).0.into_owned(),
+                ),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The `data` children of an `ilst` item as (type indicator, payload).
+fn mp4_data(item: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    atoms(item)
+        .filter(|(k, _)| k == b"data")
+        .filter_map(|(_, data)| Some((be_u32(data.get(..4)?) & 0x00FF_FFFF, data.get(8..)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `bytes` to a temporary file and reads its tags.
+    fn read_bytes(name: &str, bytes: &[u8]) -> Result<NativeTags, String> {
+        let path = std::env::temp_dir().join(format!("tag_reader_{}_{}", std::process::id(), name));
+        std::fs::write(&path, bytes).unwrap();
+        let result = read(&path);
+        let _ = std::fs::remove_file(&path);
+        result
+    }
+
+    fn field<'a>(tags: &'a NativeTags, key: &str) -> Option<&'a str> {
+        tags.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn id3v23_frame(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut frame = id.to_vec();
+        frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    fn id3v2(major: u8, frames: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = frames.concat();
+        let size = body.len() as u32;
+        let mut tag = vec![b'I', b'D', b'3', major, 0, 0];
+        tag.extend((0..4).rev().map(|i| ((size >> (7 * i)) & 0x7F) as u8));
+        tag.extend_from_slice(&body);
+        tag
+    }
+
+    fn atom(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut atom = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        atom.extend_from_slice(kind);
+        atom.extend_from_slice(payload);
+        atom
+    }
+
+    fn mp4_item(kind: &[u8; 4], data_type: u32, value: &[u8]) -> Vec<u8> {
+        let mut data = data_type.to_be_bytes().to_vec();
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(value);
+        atom(kind, &atom(b"data", &data))
+    }
+
+    #[test]
+    fn id3v23_text_comment_lyrics_and_front_cover() {
+        let mut apic_back = vec![0];
+        apic_back.extend_from_slice(b"image/png\0");
+        apic_back.push(4);
+        apic_back.extend_from_slice(b"\0BACK");
+        let mut apic_front = vec![0];
+        apic_front.extend_from_slice(b"image/jpeg\0");
+        apic_front.push(3);
+        apic_front.extend_from_slice(b"\0FRONT");
+        let mut title = vec![1, 0xFF, 0xFE];
+        title.extend("Café".encode_utf16().flat_map(|u| u.to_le_bytes()));
+
+        let tag = id3v2(
+            3,
+            &[
+                id3v23_frame(b"TIT2", &title),
+                id3v23_frame(b"TPE1", b"\0Artist"),
+                id3v23_frame(b"TXXX", b"\0REPLAYGAIN_TRACK_GAIN\0-6.5 dB"),
+                id3v23_frame(b"COMM", b"\0engiTunNORM\0 0000"),
+                id3v23_frame(b"COMM", b"\0eng\0A comment"),
+                id3v23_frame(b"USLT", b"\0eng\0First line\nSecond line"),
+                id3v23_frame(b"APIC", &apic_back),
+                id3v23_frame(b"APIC", &apic_front),
+            ],
+        );
+        let tags = read_bytes("id3v23", &tag).unwrap();
+        assert_eq!(field(&tags, "TIT2"), Some("Café"));
+        assert_eq!(field(&tags, "TPE1"), Some("Artist"));
+        assert_eq!(field(&tags, "TXXX:REPLAYGAIN_TRACK_GAIN"), Some("-6.5 dB"));
+        assert_eq!(field(&tags, "comment"), Some("A comment"));
+        assert_eq!(field(&tags, "lyrics"), Some("First line\nSecond line"));
+        assert_eq!(tags.picture.as_deref(), Some(b"FRONT".as_slice()));
+    }
+
+    #[test]
+    fn id3v24_multiple_values_are_joined() {
+        let mut frame = b"TPE1".to_vec();
+        let data = b"\x03One\0Two";
+        frame.extend_from_slice(&[0, 0, 0, data.len() as u8, 0, 0]);
+        frame.extend_from_slice(data);
+        let tags = read_bytes("id3v24", &id3v2(4, &[frame])).unwrap();
+        assert_eq!(field(&tags, "TPE1"), Some("One; Two"));
+    }
+
+    #[test]
+    fn id3v1_with_track_number() {
+        let mut file = vec![0xFFu8; 64];
+        let mut tag = [0u8; 128];
+        tag[..3].copy_from_slice(b"TAG");
+        tag[3..8].copy_from_slice(b"Title");
+        tag[33..39].copy_from_slice(b"Artist");
+        tag[93..97].copy_from_slice(b"1999");
+        tag[97..104].copy_from_slice(b"Comment");
+        tag[126] = 7;
+        file.extend_from_slice(&tag);
+
+        let tags = read_bytes("id3v1", &file).unwrap();
+        assert_eq!(field(&tags, "title"), Some("Title"));
+        assert_eq!(field(&tags, "artist"), Some("Artist"));
+        assert_eq!(field(&tags, "album"), None);
+        assert_eq!(field(&tags, "date"), Some("1999"));
+        assert_eq!(field(&tags, "comment"), Some("Comment"));
+        assert_eq!(field(&tags, "track"), Some("7"));
+    }
+
+    #[test]
+    fn flac_vorbis_comments() {
+        let mut comments = Vec::new();
+        let vendor = b"reference libFLAC";
+        comments.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        comments.extend_from_slice(vendor);
+        let entries: [&[u8]; 4] = [
+            b"TITLE=Song",
+            b"ARTIST=A",
+            b"ARTIST=B",
+            b"UNSYNCEDLYRICS=Words",
+        ];
+        comments.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        for entry in entries {
+            comments.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+            comments.extend_from_slice(entry);
+        }
+        let mut file = b"fLaC".to_vec();
+        let len = comments.len() as u32;
+        file.extend_from_slice(&[0x84, (len >> 16) as u8, (len >> 8) as u8, len as u8]);
+        file.extend_from_slice(&comments);
+
+        let tags = read_bytes("flac", &file).unwrap();
+        assert_eq!(field(&tags, "TITLE"), Some("Song"));
+        assert_eq!(field(&tags, "ARTIST"), Some("A; B"));
+        assert_eq!(field(&tags, "lyrics"), Some("Words"));
+    }
+
+    #[test]
+    fn mp4_ilst_after_media_data() {
+        let ilst = atom(
+            b"ilst",
+            &[
+                mp4_item(b"\xA9nam", 1, b"Title"),
+                mp4_item(b"trkn", 0, &[0, 0, 0, 3, 0, 12, 0, 0]),
+                mp4_item(b"covr", 13, b"JPEG"),
+            ]
+            .concat(),
+        );
+        let mut meta = vec![0, 0, 0, 0];
+        meta.extend_from_slice(&ilst);
+        let moov = atom(b"moov", &atom(b"udta", &atom(b"meta", &meta)));
+        let file = [
+            atom(b"ftyp", b"M4A \0\0\0\0"),
+            atom(b"mdat", &[0; 32]),
+            moov,
+        ]
+        .concat();
+
+        let tags = read_bytes("mp4", &file).unwrap();
+        assert_eq!(field(&tags, "\u{A9}nam"), Some("Title"));
+        assert_eq!(field(&tags, "track"), Some("3/12"));
+        assert_eq!(tags.picture.as_deref(), Some(b"JPEG".as_slice()));
+    }
+
+    #[test]
+    fn mp4_with_overflowing_atom_size_fails_cleanly() {
+        let mut file = atom(b"ftyp", b"M4A \0\0\0\0");
+        file.extend_from_slice(&1u32.to_be_bytes());
+        file.extend_from_slice(b"mdat");
+        file.extend_from_slice(&(u64::MAX - 4).to_be_bytes());
+        assert!(read_bytes("mp4_overflow", &file).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// MusicBrainz identifiers as written by Picard and compatible taggers.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MusicBrainzIds {
+    pub recording_id: Option<String>,
+    pub release_track_id: Option<String>,
+    pub release_id: Option<String>,
+    pub release_group_id: Option<String>,
+    pub artist_id: Option<String>,
+    pub album_artist_id: Option<String>,
+}
+
+impl MusicBrainzIds {
+    pub fn is_empty(&self) -> bool {
+        *self == MusicBrainzIds::default()
+    }
+}
+
+/// Normalised track tags, independent of the container they were read from.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    pub track_total: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub disc_total: Option<u32>,
+    /// Release date exactly as tagged ("2003", "2003-05-12", ...).
+    pub date: Option<String>,
+    /// Year part of `date`, for sorting.
+    pub year: Option<u32>,
+    pub genre: Option<String>,
+    pub composer: Option<String>,
+    pub comment: Option<String>,
+    #[serde(default, skip_serializing_if = "MusicBrainzIds::is_empty")]
+    pub musicbrainz: MusicBrainzIds,
+}
+
+// Keys below are normalised with `normalise_key`, so `ALBUMARTIST`, `album_artist` and
+// `Album Artist` are all `albumartist`. Aliases are listed in order of preference and cover
+// the names ffprobe reports for ID3v2 frames, Vorbis comments, MP4 atoms and APE/ASF tags.
+const TITLE: &[&str] = &["title", "tit2", "nam"];
+const ARTIST: &[&str] = &["artist", "tpe1", "art", "author"];
+const ALBUM: &[&str] = &["album", "talb", "alb"];
+const ALBUM_ARTIST: &[&str] = &["albumartist", "tpe2", "aart", "band", "wmalbumartist"];
+const TRACK: &[&str] = &["track", "tracknumber", "trck", "trkn", "wmtracknumber"];
+const TRACK_TOTAL: &[&str] = &["tracktotal", "totaltracks", "trackc"];
+const DISC: &[&str] = &["disc", "discnumber", "disk", "tpos", "wmpartofset"];
+const DISC_TOTAL: &[&str] = &["disctotal", "totaldiscs", "discc"];
+const DATE: &[&str] = &[
+    "date",
+    "tdrc",
+    "day",
+    "year",
+    "tyer",
+    "originaldate",
+    "tdor",
+    "wmyear",
+];
+const GENRE: &[&str] = &["genre", "tcon", "gen", "gnre"];
+const COMPOSER: &[&str] = &["composer", "tcom", "wrt", "writer"];
+const COMMENT: &[&str] = &["comment", "comm", "cmt", "description"];
+const MB_RECORDING: &[&str] = &["musicbrainztrackid", "musicbrainzrecordingid"];
+const MB_RELEASE_TRACK: &[&str] = &["musicbrainzreleasetrackid"];
+const MB_RELEASE: &[&str] = &["musicbrainzalbumid", "musicbrainzreleaseid"];
+const MB_RELEASE_GROUP: &[&str] = &["musicbrainzreleasegroupid"];
+const MB_ARTIST: &[&str] = &["musicbrainzartistid"];
+const MB_ALBUM_ARTIST: &[&str] = &["musicbrainzalbumartistid"];
+
+/// Lower-cases `key`, drops the freeform prefixes ID3 (`TXXX:`) and MP4
+/// (`----:com.apple.iTunes:`) put in front of custom names, and strips everything that is
+/// not a letter or digit (including the `©` of MP4 atoms).
+pub fn normalise_key(key: &str) -> String {
+    let key = key.to_lowercase();
+    let key = key
+        .strip_prefix("----:com.apple.itunes:")
+        .or_else(|| key.strip_prefix("txxx:"))
+        .unwrap_or(&key);
+    key.chars().filter(|c| c.is_alphanumeric()).collect()
+}
+
+/// Value with empty, whitespace-only and mis-decoded (U+FFFD) values treated as missing.
+pub fn clean(value: Option<&str>) -> Option<String> {
+    value
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty() && !v.contains('\u{FFFD}'))
+        .map(|v| v.to_string())
+}
+
+/// Splits "3" and "3/12" style numbers into (number, total).
+fn parse_index(value: Option<&str>) -> (Option<u32>, Option<u32>) {
+    let Some(value) = value else {
+        return (None, None);
+    };
+    let (number, total) = value.split_once('/').unwrap_or((value, ""));
+    (number.trim().parse().ok(), total.trim().parse().ok())
+}
+
+/// First run of four digits, so "2003-05-12", "12/05/2003" and "℗ 2003" all give 2003.
+pub fn parse_year(date: &str) -> Option<u32> {
+    let bytes = date.as_bytes();
+    (0..bytes.len().saturating_sub(3))
+        .find(|&i| {
+            bytes[i..i + 4].iter().all(u8::is_ascii_digit)
+                && (i == 0 || !bytes[i - 1].is_ascii_digit())
+                && !bytes.get(i + 4).is_some_and(u8::is_ascii_digit)
+        })
+        .and_then(|i| date[i..i + 4].parse().ok())
+}
+
+impl Tags {
+    /// Builds the normalised model from raw key/value pairs with keys in any case or
+    /// naming scheme.
+    pub fn from_raw<'a>(raw: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let mut map: HashMap<String, &str> = HashMap::new();
+        for (key, value) in raw {
+            // Keep the first non-empty value of a key, e.g. the format tag over a duplicate
+            // stream tag.
+            if clean(Some(value)).is_some() {
+                map.entry(normalise_key(key)).or_insert(value);
+            }
+        }
+        let get = |aliases: &[&str]| aliases.iter().find_map(|a| map.get(*a).copied());
+        let text = |aliases: &[&str]| clean(get(aliases));
+
+        let (track_number, track_total) = parse_index(get(TRACK));
+        let (disc_number, disc_total) = parse_index(get(DISC));
+        let date = text(DATE);
+
+        Tags {
+            title: text(TITLE),
+            artist: text(ARTIST),
+            album: text(ALBUM),
+            album_artist: text(ALBUM_ARTIST),
+            track_number,
+            track_total: track_total.or_else(|| parse_index(get(TRACK_TOTAL)).0),
+            disc_number,
+            disc_total: disc_total.or_else(|| parse_index(get(DISC_TOTAL)).0),
+            year: date.as_deref().and_then(parse_year),
+            date,
+            genre: text(GENRE),
+            composer: text(COMPOSER),
+            comment: text(COMMENT),
+            musicbrainz: MusicBrainzIds {
+                recording_id: text(MB_RECORDING),
+                release_track_id: text(MB_RELEASE_TRACK),
+                release_id: text(MB_RELEASE),
+                release_group_id: text(MB_RELEASE_GROUP),
+                artist_id: text(MB_ARTIST),
+                album_artist_id: text(MB_ALBUM_ARTIST),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalises_keys() {
+        let cases = [
+            ("TITLE", "title"),
+            ("TIT2", "tit2"),
+            ("©nam", "nam"),
+            ("ALBUMARTIST", "albumartist"),
+            ("album_artist", "albumartist"),
+            ("Album Artist", "albumartist"),
+            ("TXXX:MusicBrainz Album Id", "musicbrainzalbumid"),
+            (
+                "----:com.apple.iTunes:MusicBrainz Track Id",
+                "musicbrainztrackid",
+            ),
+            ("WM/AlbumArtist", "wmalbumartist"),
+        ];
+        for (key, expected) in cases {
+            assert_eq!(normalise_key(key), expected, "{}", key);
+        }
+    }
+
+    #[test]
+    fn maps_aliases_of_each_container() {
+        // The same track as ffprobe reports it for ID3v2, Vorbis comments and MP4.
+        let containers: [&[(&str, &str)]; 3] = [
+            &[
+                ("TIT2", "Song"),
+                ("TPE1", "Artist"),
+                ("TALB", "Album"),
+                ("TPE2", "Album Artist"),
+                ("TRCK", "3/12"),
+                ("TPOS", "1/2"),
+                ("TDRC", "2001-05-03"),
+                ("TCON", "Rock"),
+            ],
+            &[
+                ("TITLE", "Song"),
+                ("ARTIST", "Artist"),
+                ("ALBUM", "Album"),
+                ("ALBUMARTIST", "Album Artist"),
+                ("TRACKNUMBER", "3"),
+                ("TRACKTOTAL", "12"),
+                ("DISCNUMBER", "1"),
+                ("DISCTOTAL", "2"),
+                ("DATE", "2001-05-03"),
+                ("GENRE", "Rock"),
+            ],
+            &[
+                ("©nam", "Song"),
+                ("©ART", "Artist"),
+                ("©alb", "Album"),
+                ("aART", "Album Artist"),
+                ("trkn", "3/12"),
+                ("disk", "1/2"),
+                ("©day", "2001-05-03"),
+                ("©gen", "Rock"),
+            ],
+        ];
+        for raw in containers {
+            let tags = Tags::from_raw(raw.iter().copied());
+            assert_eq!(tags.title.as_deref(), Some("Song"), "{:?}", raw);
+            assert_eq!(tags.artist.as_deref(), Some("Artist"));
+            assert_eq!(tags.album.as_deref(), Some("Album"));
+            assert_eq!(tags.album_artist.as_deref(), Some("Album Artist"));
+            assert_eq!((tags.track_number, tags.track_total), (Some(3), Some(12)));
+            assert_eq!((tags.disc_number, tags.disc_total), (Some(1), Some(2)));
+            assert_eq!(tags.date.as_deref(), Some("2001-05-03"));
+            assert_eq!(tags.year, Some(2001));
+            assert_eq!(tags.genre.as_deref(), Some("Rock"));
+        }
+    }
+
+    #[test]
+    fn prefers_the_first_non_empty_value() {
+        let tags = Tags::from_raw([
+            ("title", "  "),
+            ("TITLE", "Format title"),
+            ("title", "Stream title"),
+            ("artist", "Bad \u{FFFD} decode"),
+            ("date", "2001"),
+            ("originaldate", "1999"),
+        ]);
+        assert_eq!(tags.title.as_deref(), Some("Format title"));
+        assert_eq!(tags.artist, None);
+        assert_eq!(tags.date.as_deref(), Some("2001"));
+        assert_eq!(tags.year, Some(2001));
+    }
+
+    #[test]
+    fn reads_musicbrainz_ids() {
+        let tags = Tags::from_raw([
+            ("MUSICBRAINZ_TRACKID", "rec"),
+            ("TXXX:MusicBrainz Release Track Id", "reltrack"),
+            ("----:com.apple.iTunes:MusicBrainz Album Id", "rel"),
+        ]);
+        assert_eq!(tags.musicbrainz.recording_id.as_deref(), Some("rec"));
+        assert_eq!(
+            tags.musicbrainz.release_track_id.as_deref(),
+            Some("reltrack")
+        );
+        assert_eq!(tags.musicbrainz.release_id.as_deref(), Some("rel"));
+        assert!(!tags.musicbrainz.is_empty());
+        assert!(Tags::from_raw([]).musicbrainz.is_empty());
+    }
+
+    #[test]
+    fn parses_track_and_disc_numbers() {
+        let cases = [
+            (Some("3"), (Some(3), None)),
+            (Some("3/12"), (Some(3), Some(12))),
+            (Some(" 03 / 12 "), (Some(3), Some(12))),
+            (Some("3/"), (Some(3), None)),
+            (Some("/12"), (None, Some(12))),
+            (Some("A1"), (None, None)),
+            (None, (None, None)),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_index(value), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn parses_years() {
+        let cases = [
+            ("2001", Some(2001)),
+            ("2001-05-03", Some(2001)),
+            ("2001-05-03T12:00:00Z", Some(2001)),
+            ("03/05/2001", Some(2001)),
+            ("℗ 2001 Label", Some(2001)),
+            ("20010503", None),
+            ("01", None),
+            ("", None),
+        ];
+        for (date, expected) in cases {
+            assert_eq!(parse_year(date), expected, "{}", date);
+        }
+    }
+}
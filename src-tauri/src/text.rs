@@ -0,0 +1,49 @@
+use encoding_rs::{GBK, SHIFT_JIS, UTF_16BE, UTF_16LE};
+use std::borrow::Cow;
+
+/// Decodes a text file of unknown encoding (cue sheets, playlists, lyrics). Files written on
+/// Chinese or Japanese Windows are often in the ANSI codepage rather than UTF-8, so after the
+/// BOM/UTF-8 checks we try Shift-JIS and GBK and keep whichever decodes cleanly and looks
+/// like real text.
+pub fn decode(bytes: &[u8]) -> Cow<'_, str> {
+    if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return String::from_utf8_lossy(rest);
+    }
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        return UTF_16LE.decode_with_bom_removal(bytes).0;
+    }
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        return UTF_16BE.decode_with_bom_removal(bytes).0;
+    }
+    if let Ok(s) = std::str::from_utf8(bytes) {
+        return Cow::Borrowed(s);
+    }
+
+    let (sjis, _, sjis_errors) = SHIFT_JIS.decode(bytes);
+    let (gbk, _, gbk_errors) = GBK.decode(bytes);
+    match (sjis_errors, gbk_errors) {
+        (false, true) => sjis,
+        (true, false) => gbk,
+        (false, false) => {
+            // GBK text read as Shift-JIS tends to turn into half-width katakana, while
+            // genuine Japanese text almost always contains hiragana.
+            let half_width = |s: &str| {
+                s.chars()
+                    .filter(|c| ('\u{FF61}'..='\u{FF9F}').contains(c))
+                    .count()
+            };
+            let hiragana = sjis.chars().any(|c| ('\u{3041}'..='\u{309F}').contains(&c));
+            if hiragana && half_width(&sjis) <= half_width(&gbk) {
+                sjis
+            } else {
+                gbk
+            }
+        }
+        (true, true) => {
+            log::warn!(
+                "Text is neither UTF-8, Shift-JIS nor GBK; some characters may be incorrect."
+            );
+            gbk
+        }
+    }
+}
@@ -0,0 +1,719 @@
+use crate::cache::{CacheKey, TranscodeCache};
+use crate::error::AppError;
+use crate::ffmpeg;
+use crate::loudness::{self, GainMode, Loudness, LoudnessStore, Measurement, TrackKey};
+use crate::lyrics::Lyrics;
+use crate::metadata::{self, AudioProperties, Metadata, TrackMetadata};
+use crate::profile::{AppliedProfile, Container, TranscodeProfile};
+use crate::settings::SettingsState;
+use crate::sidecar;
+use crate::stream::StreamRegistry;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Output, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+pub const TRANSCODE_PROGRESS_EVENT: &str = "transcode-progress";
+pub const TRANSCODE_FINISHED_EVENT: &str = "transcode-finished";
+
+/// How often a prefetch waiting for foreground jobs to finish checks again.
+const PREFETCH_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What is read from a track without ffmpeg: its tags, cover art and lyrics.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackInfo {
+    metadata: Metadata,
+    properties: AudioProperties,
+    album_art_base64: Option<String>,
+    /// Parsed lyrics from a sidecar file or the tags; `source` says which.
+    lyrics: Option<Lyrics>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PreparedFile {
+    #[serde(flatten)]
+    info: TrackInfo,
+    /// Set when the whole transcode is already on disk; played through the asset protocol.
+    cache_path: Option<String>,
+    /// Otherwise the id of a `stream://` stream that is transcoded while it plays.
+    stream_id: Option<u64>,
+    /// The output format the transcode profile resolved to for this file.
+    profile: AppliedProfile,
+    /// ReplayGain/R128 from the tags, completed with stored measurements.
+    loudness: Loudness,
+    /// dB the player should apply itself; `None` when off, baked in or not yet known.
+    playback_gain: Option<f64>,
+}
+
+/// A track to prepare: the URL-encoded path from the frontend and, for tracks cut from a
+/// single-file rip by a cue sheet, the time range in seconds.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackRequest {
+    path: String,
+    start: Option<f64>,
+    end: Option<f64>,
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+enum Stage {
+    /// Reading tags, lyrics and cover art.
+    Metadata,
+    /// Measuring loudness (only when the gain is baked into the transcode).
+    Loudness,
+    /// Transcoding the whole file (only when it cannot be streamed).
+    Transcoding,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct TranscodeProgress {
+    job_id: u64,
+    stage: Stage,
+    /// Fraction of the track transcoded, when the duration is known.
+    progress: Option<f64>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct LoudnessMeasured {
+    /// The track as passed to `start_transcode_job`.
+    path: String,
+    start: Option<f64>,
+    end: Option<f64>,
+    loudness: Loudness,
+    playback_gain: Option<f64>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct TranscodeFinished {
+    job_id: u64,
+    cancelled: bool,
+    error: Option<AppError>,
+    result: Option<PreparedFile>,
+    /// With an `FfmpegMissing` error: the track's tags, cover art and lyrics, which were
+    /// read without ffmpeg, so the player can still show them.
+    track: Option<TrackInfo>,
+}
+
+/// One running prepare job: its cancellation flag and the ffmpeg process it is waiting
+/// on, if any, so cancelling does not have to wait for ffmpeg to finish.
+#[derive(Default)]
+struct Job {
+    cancelled: AtomicBool,
+    child: Mutex<Option<Child>>,
+}
+
+impl Job {
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        if let Some(child) = self.child.lock().unwrap().as_mut() {
+            let _ = child.kill();
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Running prepare jobs, keyed by id.
+#[derive(Default)]
+pub struct TranscodeJobs {
+    next_id: AtomicU64,
+    running: Mutex<HashMap<u64, Arc<Job>>>,
+    /// Loudness measurement of the track playing, cancelled when the next one starts.
+    measuring: Mutex<Option<Arc<Job>>>,
+}
+
+/// Tracks prepared ahead of time by `prefetch_tracks`, handed out by the next
+/// `start_transcode_job` for the same track.
+#[derive(Default)]
+pub struct Prefetcher {
+    /// The running prefetch, cancelled when the queue changes.
+    job: Mutex<Option<Arc<Job>>>,
+    ready: Mutex<Vec<(TrackRequest, PreparedFile)>>,
+}
+
+impl Prefetcher {
+    fn is_ready(&self, request: &TrackRequest) -> bool {
+        self.ready.lock().unwrap().iter().any(|(r, _)| r == request)
+    }
+
+    /// Removes and returns the prefetched result for `request`, unless its cache file has
+    /// been evicted or it was made with a different profile than `profile`.
+    fn take(&self, request: &TrackRequest, profile: &TranscodeProfile) -> Option<PreparedFile> {
+        let mut ready = self.ready.lock().unwrap();
+        let index = ready.iter().position(|(r, _)| r == request)?;
+        let (_, prepared) = ready.remove(index);
+        let cached = prepared
+            .cache_path
+            .as_deref()
+            .is_some_and(|p| Path::new(p).is_file());
+        let current = profile.apply(&prepared.info.properties, &prepared.loudness);
+        (cached && current == prepared.profile).then_some(prepared)
+    }
+}
+
+struct JobContext<'a> {
+    app: &'a AppHandle,
+    /// `None` for prefetches, which run in the background without progress events.
+    job_id: Option<u64>,
+    job: &'a Job,
+}
+
+impl JobContext<'_> {
+    fn is_background(&self) -> bool {
+        self.job_id.is_none()
+    }
+
+    fn progress(&self, stage: Stage, progress: Option<f64>) {
+        let Some(job_id) = self.job_id else {
+            return;
+        };
+        let _ = self.app.emit(
+            TRANSCODE_PROGRESS_EVENT,
+            TranscodeProgress {
+                job_id,
+                stage,
+                progress,
+            },
+        );
+    }
+
+    /// Runs `cmd`, which must have been given `-progress pipe:1`, reporting progress in
+    /// `stage` against `duration` seconds. Returns `Ok(None)` if the job was cancelled
+    /// meanwhile.
+    fn run_ffmpeg(
+        &self,
+        mut cmd: std::process::Command,
+        stage: Stage,
+        duration: Option<f64>,
+    ) -> Result<Option<Output>, AppError> {
+        if self.is_background() {
+            ffmpeg::lower_priority(&mut cmd);
+        }
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| AppError::io("run ffmpeg", None, e))?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = ffmpeg::drain_stderr(child.stderr.take().expect("stderr is piped"));
+        {
+            let mut slot = self.job.child.lock().unwrap();
+            if self.job.is_cancelled() {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Ok(None);
+            }
+            *slot = Some(child);
+        }
+
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let (Some(seconds), Some(duration)) = (parse_out_time(&line), duration) {
+                self.progress(stage, Some((seconds / duration).clamp(0.0, 1.0)));
+            }
+        }
+        let stderr_text = stderr.join().unwrap_or_default();
+
+        let child = self.job.child.lock().unwrap().take();
+        let status = child
+            .map(|mut c| c.wait())
+            .transpose()
+            .map_err(|e| AppError::io("wait for ffmpeg", None, e))?;
+        if self.job.is_cancelled() {
+            return Ok(None);
+        }
+        Ok(status.map(|status| Output {
+            status,
+            stdout: Vec::new(),
+            stderr: stderr_text,
+        }))
+    }
+}
+
+/// Seconds from an `out_time_us=` line of ffmpeg's `-progress` output.
+fn parse_out_time(line: &str) -> Option<f64> {
+    let micros: i64 = line.strip_prefix("out_time_us=")?.trim().parse().ok()?;
+    (micros >= 0).then(|| micros as f64 / 1_000_000.0)
+}
+
+fn decode_path(path: &str) -> Result<PathBuf, AppError> {
+    urlencoding::decode(path)
+        .map(|p| PathBuf::from(p.into_owned()))
+        .map_err(|e| AppError::invalid_argument(format!("Failed to decode path: {}", e)))
+}
+
+/// Measures the loudness of `path` (`start..end`) and stores it under `key`. `Ok(None)`
+/// means the job was cancelled.
+fn measure_loudness(
+    ctx: &JobContext,
+    path: &Path,
+    start: Option<f64>,
+    end: Option<f64>,
+    duration: Option<f64>,
+    key: &TrackKey,
+) -> Result<Option<Measurement>, AppError> {
+    ctx.progress(Stage::Loudness, Some(0.0));
+    let cmd = loudness::measure_command(path, start, end)?;
+    let Some(output) = ctx.run_ffmpeg(cmd, Stage::Loudness, duration)? else {
+        return Ok(None);
+    };
+    if !output.status.success() {
+        return Err(AppError::from_ffmpeg(path, &output));
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let measurement =
+        loudness::parse_summary(&stderr, duration).ok_or_else(|| AppError::TranscodeFailed {
+            path: path.display().to_string(),
+            exit_status: output.status.code(),
+            stderr: "No ebur128 summary in ffmpeg output".to_string(),
+        })?;
+    ctx.app.state::<LoudnessStore>().insert(key, measurement);
+    Ok(Some(measurement))
+}
+
+/// The loudness from the tags, completed with whatever the store has for `key`.
+fn stored_loudness(app: &AppHandle, tagged: Loudness, key: &TrackKey) -> Loudness {
+    let (track, album) = app.state::<LoudnessStore>().get(key);
+    tagged.or_measured(track.as_ref(), album.as_ref())
+}
+
+/// A track whose tags, cover art and lyrics have been read by `read_track`.
+struct ReadTrack {
+    path: PathBuf,
+    start: Option<f64>,
+    end: Option<f64>,
+    info: TrackInfo,
+    /// Loudness from the tags.
+    tagged: Loudness,
+}
+
+/// Reads the tags, cover art and lyrics of the track, none of which needs ffmpeg.
+/// `Ok(None)` means the job was cancelled.
+fn read_track(ctx: &JobContext, request: &TrackRequest) -> Result<Option<ReadTrack>, AppError> {
+    let (start, end) = (request.start, request.end);
+    // Decode the URL-encoded path received from the frontend.
+    let path = decode_path(&request.path)?;
+    if let Some(end) = end {
+        if end - start.unwrap_or(0.0) <= 0.0 {
+            return Err(AppError::invalid_argument(format!(
+                "Invalid time range: {:?}..{}",
+                start, end
+            )));
+        }
+    }
+
+    ctx.progress(Stage::Metadata, None);
+    let TrackMetadata {
+        mut metadata,
+        lyrics,
+        other_lyrics,
+        mut properties,
+        loudness: tagged,
+    } = metadata::read(&path, start);
+    properties.restrict_to(start, end);
+    let album_art_base64 = metadata::album_art(&path, &mut metadata)?;
+    if ctx.job.is_cancelled() {
+        return Ok(None);
+    }
+
+    let settings = ctx.app.state::<SettingsState>().get();
+    let lyrics = sidecar::resolve(
+        &path,
+        start,
+        &metadata.tags,
+        lyrics.as_deref(),
+        &other_lyrics,
+        &settings.lyrics_search,
+    );
+    Ok(Some(ReadTrack {
+        path,
+        start,
+        end,
+        info: TrackInfo {
+            metadata,
+            properties,
+            album_art_base64,
+            lyrics,
+        },
+        tagged,
+    }))
+}
+
+/// Reads metadata for the track and makes it playable: from the transcode cache, as a
+/// stream, or by transcoding the whole file. Background jobs never stream, so their result
+/// stays valid until it is played. `Ok(None)` means the job was cancelled.
+fn prepare(ctx: &JobContext, request: &TrackRequest) -> Result<Option<PreparedFile>, AppError> {
+    let Some(track) = read_track(ctx, request)? else {
+        return Ok(None);
+    };
+    ffmpeg::resolve()?;
+    make_playable(ctx, track)
+}
+
+/// Makes a track read by `read_track` playable; see `prepare`. Needs ffmpeg.
+fn make_playable(ctx: &JobContext, track: ReadTrack) -> Result<Option<PreparedFile>, AppError> {
+    let ReadTrack {
+        path,
+        start,
+        end,
+        mut info,
+        tagged,
+    } = track;
+    let path = path.as_path();
+    let wanted = ctx.app.state::<SettingsState>().get().transcode_profile;
+    let loudness_key = TrackKey::new(path, start, end, &info.metadata.tags)?;
+    let mut loudness = stored_loudness(ctx.app, tagged, &loudness_key);
+    // A baked gain is needed before transcoding. Prefetches measure anyway, since nobody
+    // is waiting for them; otherwise the track is measured once it plays.
+    let measure_now = wanted.bake_replay_gain || ctx.is_background();
+    if wanted.replay_gain != GainMode::Off
+        && loudness.gain(wanted.replay_gain).is_none()
+        && measure_now
+    {
+        let duration = info.properties.duration;
+        let measured = measure_loudness(ctx, path, start, end, duration, &loudness_key);
+        match measured {
+            Ok(Some(_)) => loudness = stored_loudness(ctx.app, tagged, &loudness_key),
+            Ok(None) => return Ok(None),
+            Err(_) if ctx.job.is_cancelled() => return Ok(None),
+            Err(e) => log::warn!("Failed to measure loudness of {}: {}", path.display(), e),
+        }
+    }
+
+    // Streams and full transcodes of the same profile produce the same file and share
+    // cache entries.
+    let profile = wanted.apply(&info.properties, &loudness);
+    info.properties.channel_layout_changed = info
+        .properties
+        .channels
+        .is_some_and(|c| c != profile.channels);
+    let key = CacheKey::new(path, start, end, &profile.cache_variant())?;
+    let mut prepared = PreparedFile {
+        info,
+        cache_path: None,
+        stream_id: None,
+        profile,
+        loudness,
+        playback_gain: wanted.playback_gain(&loudness),
+    };
+    let cache = ctx.app.state::<TranscodeCache>();
+    if let Some(cached) = cache.get(&key) {
+        prepared.cache_path = Some(path_string(&cached)?);
+        return Ok(Some(prepared));
+    }
+
+    // Stream WAV output when the length is known; otherwise (and for compressed
+    // containers) transcode the whole file first.
+    if prepared.profile.container == Container::Wav && !ctx.is_background() {
+        let streams = ctx.app.state::<StreamRegistry>();
+        let opened = streams.open(
+            ctx.app,
+            path,
+            start,
+            &prepared.info.properties,
+            &prepared.profile,
+            key.clone(),
+        )?;
+        if let Some(id) = opened {
+            if ctx.job.is_cancelled() {
+                streams.close(id);
+                return Ok(None);
+            }
+            prepared.stream_id = Some(id);
+            return Ok(Some(prepared));
+        }
+    }
+
+    ctx.progress(Stage::Transcoding, Some(0.0));
+    let staged = cache.staging_path(&key, prepared.profile.extension());
+    let mut cmd = ffmpeg::ffmpeg_command()?;
+    cmd.arg("-hide_banner")
+        .arg("-loglevel")
+        .arg("error")
+        .arg("-nostats")
+        .arg("-progress")
+        .arg("pipe:1");
+    if ctx.is_background() {
+        cmd.arg("-threads").arg("1");
+    }
+    // Seek on the input side so ffmpeg does not decode everything before `start`.
+    if let Some(start) = start {
+        cmd.arg("-ss").arg(format!("{:.3}", start));
+    }
+    cmd.arg("-i").arg(path);
+    // An exact length from the gapless info also cuts the encoder padding.
+    let length = match end {
+        Some(end) => Some(end - start.unwrap_or(0.0)),
+        None => prepared
+            .info
+            .properties
+            .gapless
+            .and(prepared.info.properties.duration),
+    };
+    if let Some(length) = length {
+        cmd.arg("-t").arg(format!("{:.6}", length));
+    }
+    prepared.profile.add_audio_args(&mut cmd);
+    prepared.profile.add_encoder_args(&mut cmd);
+    cmd.arg("-y").arg(&staged);
+
+    match ctx.run_ffmpeg(cmd, Stage::Transcoding, prepared.info.properties.duration)? {
+        Some(output) if output.status.success() => {}
+        Some(output) => {
+            let _ = std::fs::remove_file(&staged);
+            return Err(AppError::from_ffmpeg(path, &output));
+        }
+        None => {
+            let _ = std::fs::remove_file(&staged);
+            return Ok(None);
+        }
+    }
+    let cached = cache.insert(&key, &staged)?;
+    prepared.cache_path = Some(path_string(&cached)?);
+    Ok(Some(prepared))
+}
+
+fn path_string(path: &Path) -> Result<String, AppError> {
+    path.to_str()
+        .map(str::to_owned)
+        .ok_or_else(|| AppError::Io {
+            action: "convert cache path to string".to_string(),
+            path: Some(path.display().to_string()),
+            message: "path is not valid UTF-8".to_string(),
+        })
+}
+
+/// Prepares `path` for playback in the background and returns the job id. `start`/`end`
+/// (seconds) select a time range of the file, as used by tracks cut from a single-file rip
+/// by a cue sheet. Any earlier job still running is cancelled, since only the latest
+/// request matters when skipping through tracks. Progress arrives as `transcode-progress`
+/// events and the job always ends with one `transcode-finished`.
+///
+/// A track already prefetched by `prefetch_tracks` finishes immediately.
+#[tauri::command]
+pub fn start_transcode_job(
+    path: String,
+    start: Option<f64>,
+    end: Option<f64>,
+    app: AppHandle,
+    jobs: State<'_, TranscodeJobs>,
+    prefetcher: State<'_, Prefetcher>,
+    settings: State<'_, SettingsState>,
+) -> u64 {
+    let request = TrackRequest { path, start, end };
+    let job_id = jobs.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+    let job = Arc::new(Job::default());
+    {
+        let mut running = jobs.running.lock().unwrap();
+        for superseded in running.values() {
+            superseded.cancel();
+        }
+        if let Some(measuring) = jobs.measuring.lock().unwrap().take() {
+            measuring.cancel();
+        }
+        let prefetched = prefetcher.take(&request, &settings.get().transcode_profile);
+        if let Some(result) = prefetched {
+            let _ = app.emit(
+                TRANSCODE_FINISHED_EVENT,
+                TranscodeFinished {
+                    job_id,
+                    cancelled: false,
+                    error: None,
+                    result: Some(result),
+                    track: None,
+                },
+            );
+            return job_id;
+        }
+        running.insert(job_id, job.clone());
+    }
+
+    std::thread::spawn(move || {
+        let ctx = JobContext {
+            app: &app,
+            job_id: Some(job_id),
+            job: &job,
+        };
+        // Without ffmpeg the track cannot play, but what was read of it is still sent.
+        let mut track = None;
+        let prepared = read_track(&ctx, &request).and_then(|read| {
+            let Some(read) = read else {
+                return Ok(None);
+            };
+            if let Err(e) = ffmpeg::resolve() {
+                track = Some(read.info);
+                return Err(e);
+            }
+            make_playable(&ctx, read)
+        });
+        let finished = match prepared {
+            Ok(Some(result)) => TranscodeFinished {
+                job_id,
+                cancelled: false,
+                error: None,
+                result: Some(result),
+                track: None,
+            },
+            Ok(None) => TranscodeFinished {
+                job_id,
+                cancelled: true,
+                error: None,
+                result: None,
+                track: None,
+            },
+            // A failure caused by killing ffmpeg is reported as a cancellation.
+            Err(_) if job.is_cancelled() => TranscodeFinished {
+                job_id,
+                cancelled: true,
+                error: None,
+                result: None,
+                track: None,
+            },
+            Err(e) => TranscodeFinished {
+                job_id,
+                cancelled: false,
+                error: Some(e),
+                result: None,
+                track,
+            },
+        };
+        let jobs = app.state::<TranscodeJobs>();
+        jobs.running.lock().unwrap().remove(&job_id);
+        let _ = app.emit(TRANSCODE_FINISHED_EVENT, finished.clone());
+
+        if let Some(prepared) = finished.result {
+            let mode = app
+                .state::<SettingsState>()
+                .get()
+                .transcode_profile
+                .replay_gain;
+            if mode != GainMode::Off && prepared.loudness.gain(mode).is_none() {
+                let measuring = Arc::new(Job::default());
+                if let Some(previous) = jobs.measuring.lock().unwrap().replace(measuring.clone()) {
+                    previous.cancel();
+                }
+                measure_playing(&app, &measuring, request, &prepared);
+            }
+        }
+    });
+
+    job_id
+}
+
+/// Measures the loudness of a track that started playing without it, reporting the result
+/// with a `loudness-measured` event so the player can adjust its volume.
+fn measure_playing(app: &AppHandle, job: &Job, request: TrackRequest, prepared: &PreparedFile) {
+    let ctx = JobContext {
+        app,
+        job_id: None,
+        job,
+    };
+    let measured = decode_path(&request.path).and_then(|path| {
+        let key = TrackKey::new(
+            &path,
+            request.start,
+            request.end,
+            &prepared.info.metadata.tags,
+        )?;
+        let duration = prepared.info.properties.duration;
+        Ok(
+            measure_loudness(&ctx, &path, request.start, request.end, duration, &key)?
+                .map(|_| stored_loudness(app, prepared.loudness, &key)),
+        )
+    });
+    let loudness = match measured {
+        Ok(Some(loudness)) => loudness,
+        Ok(None) => return,
+        Err(_) if job.is_cancelled() => return,
+        Err(e) => {
+            log::warn!("Failed to measure loudness of {}: {}", request.path, e);
+            return;
+        }
+    };
+    let profile = app.state::<SettingsState>().get().transcode_profile;
+    let _ = app.emit(
+        loudness::LOUDNESS_MEASURED_EVENT,
+        LoudnessMeasured {
+            path: request.path,
+            start: request.start,
+            end: request.end,
+            loudness,
+            playback_gain: profile.playback_gain(&loudness),
+        },
+    );
+}
+
+/// Cancels a running prepare job, killing its ffmpeg process. Returns `false` if the job
+/// already finished.
+#[tauri::command]
+pub fn cancel_transcode_job(job_id: u64, jobs: State<'_, TranscodeJobs>) -> bool {
+    match jobs.running.lock().unwrap().get(&job_id) {
+        Some(job) => {
+            job.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Prepares `tracks` (the next entries of the play queue, in order) in the background so
+/// that `start_transcode_job` can return them instantly. Each is fully transcoded into the
+/// transcode cache, one at a time, at low priority and only while no foreground job is
+/// running. Replaces any earlier prefetch; results for tracks no longer listed are dropped.
+#[tauri::command]
+pub fn prefetch_tracks(
+    tracks: Vec<TrackRequest>,
+    app: AppHandle,
+    prefetcher: State<'_, Prefetcher>,
+) {
+    let job = Arc::new(Job::default());
+    if let Some(previous) = prefetcher.job.lock().unwrap().replace(job.clone()) {
+        previous.cancel();
+    }
+    prefetcher
+        .ready
+        .lock()
+        .unwrap()
+        .retain(|(r, _)| tracks.contains(r));
+
+    std::thread::spawn(move || {
+        let ctx = JobContext {
+            app: &app,
+            job_id: None,
+            job: &job,
+        };
+        let prefetcher = app.state::<Prefetcher>();
+        let jobs = app.state::<TranscodeJobs>();
+        for request in tracks {
+            if prefetcher.is_ready(&request) {
+                continue;
+            }
+            // Leave the machine to whatever the user is waiting for.
+            while !jobs.running.lock().unwrap().is_empty() && !job.is_cancelled() {
+                std::thread::sleep(PREFETCH_POLL_INTERVAL);
+            }
+            if job.is_cancelled() {
+                return;
+            }
+            match prepare(&ctx, &request) {
+                Ok(Some(prepared)) => prefetcher.ready.lock().unwrap().push((request, prepared)),
+                Ok(None) => return,
+                Err(_) if job.is_cancelled() => return,
+                Err(e) => log::warn!("Failed to prefetch {}: {}", request.path, e),
+            }
+        }
+    });
+}
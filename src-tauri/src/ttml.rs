@@ -0,0 +1,396 @@
+use crate::lyrics::{LyricLine, LyricWord, Lyrics};
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+
+/// What a `ttm:role` attribute makes of a span.
+#[derive(Clone, Copy, PartialEq)]
+enum Role {
+    /// `x-translation`: the line in another language.
+    Translation,
+    /// `x-roman` and friends: the line in Latin script.
+    Romanization,
+    /// Anything else (`x-bg` backing vocals, ...): its timed spans are sung words.
+    Other,
+}
+
+fn role(value: &str) -> Role {
+    match value {
+        "x-translation" => Role::Translation,
+        "x-roman" | "x-romanization" | "x-transliteration" => Role::Romanization,
+        _ => Role::Other,
+    }
+}
+
+/// A `<span>` being read inside a `<p>`.
+struct Span {
+    role: Role,
+    begin: Option<f64>,
+    end: Option<f64>,
+    /// Inside a translation or romanization span, so its text belongs to that.
+    alternative: bool,
+    text: String,
+}
+
+impl Span {
+    fn collects_text(&self) -> bool {
+        self.alternative || (self.begin.is_some() && self.end.is_some())
+    }
+}
+
+/// A `<p>` (one lyric line) being read.
+#[derive(Default)]
+struct Paragraph {
+    begin: Option<f64>,
+    /// `itunes:key`, which `<iTunesMetadata>` translations refer to.
+    key: Option<String>,
+    words: Vec<LyricWord>,
+    /// Text outside timed spans: the whole line in line-timed files.
+    text: String,
+    translation: Option<String>,
+    romanization: Option<String>,
+    /// Whitespace was seen since the last word, so the next one starts after a space.
+    space: bool,
+}
+
+impl Paragraph {
+    fn push_text(&mut self, text: &str) {
+        if text.trim().is_empty() {
+            self.space |= !text.is_empty();
+        }
+        self.text.push_str(text);
+    }
+
+    fn push_word(&mut self, text: &str, start: f64, end: f64) {
+        let word = text.trim();
+        if word.is_empty() {
+            self.space |= !text.is_empty();
+            return;
+        }
+        if let Some(previous) = self.words.last_mut() {
+            if self.space || text.starts_with(char::is_whitespace) {
+                previous.text.push(' ');
+            }
+        }
+        self.words.push(LyricWord {
+            text: word.to_string(),
+            start_time: start,
+            end_time: end,
+        });
+        self.space = text.ends_with(char::is_whitespace);
+    }
+
+    fn finish(self) -> Option<(Option<String>, LyricLine)> {
+        let time = self
+            .begin
+            .or_else(|| self.words.first().map(|w| w.start_time))?;
+        let text = if self.words.is_empty() {
+            collapse(&self.text)
+        } else {
+            self.words.iter().map(|w| w.text.as_str()).collect()
+        };
+        if text.is_empty() {
+            return None;
+        }
+        let line = LyricLine {
+            time,
+            text,
+            words: self.words,
+            translation: self.translation,
+            romanization: self.romanization,
+        };
+        Some((self.key, line))
+    }
+}
+
+/// Trims and collapses runs of whitespace (line breaks and indentation of the XML).
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Parses a TTML time expression: clock time (`1:02.5`, `00:01:02.500`) or an offset
+/// with a unit (`62.5s`, `62500ms`, `1.5m`, `0.1h`). A bare number is seconds.
+fn parse_time(value: &str) -> Option<f64> {
+    let value = value.trim();
+    if value.contains(':') {
+        return value.split(':').try_fold(0.0, |total, part| {
+            Some(total * 60.0 + part.parse::<f64>().ok().filter(|p| *p >= 0.0)?)
+        });
+    }
+    let (number, scale) = if let Some(ms) = value.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(h) = value.strip_suffix('h') {
+        (h, 3600.0)
+    } else if let Some(m) = value.strip_suffix('m') {
+        (m, 60.0)
+    } else {
+        (value.strip_suffix('s').unwrap_or(value), 1.0)
+    };
+    let seconds = number.parse::<f64>().ok()? * scale;
+    (seconds.is_finite() && seconds >= 0.0).then_some(seconds)
+}
+
+/// Value of the attribute with local name `name`, whatever its namespace prefix.
+fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name)
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.into_owned())
+}
+
+/// Parser state between events.
+#[derive(Default)]
+struct TtmlReader {
+    paragraph: Option<Paragraph>,
+    spans: Vec<Span>,
+    /// Inside `<translation>` or `<transliteration>` in the head: which one.
+    metadata: Option<Role>,
+    /// `<text for="...">` being read there: the key and the text so far.
+    metadata_text: Option<(String, String)>,
+    translations: HashMap<String, String>,
+    romanizations: HashMap<String, String>,
+    lines: Vec<(Option<String>, LyricLine)>,
+}
+
+impl TtmlReader {
+    fn start(&mut self, element: &BytesStart) {
+        match element.local_name().as_ref() {
+            b"p" => {
+                self.paragraph = Some(Paragraph {
+                    begin: attribute(element, b"begin").and_then(|v| parse_time(&v)),
+                    key: attribute(element, b"key"),
+                    ..Paragraph::default()
+                });
+                self.spans.clear();
+            }
+            b"span" if self.paragraph.is_some() => {
+                let role = attribute(element, b"role").map_or(Role::Other, |v| role(&v));
+                let alternative =
+                    role != Role::Other || self.spans.last().is_some_and(|s| s.alternative);
+                self.spans.push(Span {
+                    role,
+                    begin: attribute(element, b"begin").and_then(|v| parse_time(&v)),
+                    end: attribute(element, b"end").and_then(|v| parse_time(&v)),
+                    alternative,
+                    text: String::new(),
+                });
+            }
+            b"translation" => self.metadata = Some(Role::Translation),
+            b"transliteration" => self.metadata = Some(Role::Romanization),
+            b"text" if self.metadata.is_some() => {
+                if let Some(key) = attribute(element, b"for") {
+                    self.metadata_text = Some((key, String::new()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Some((_, metadata_text)) = &mut self.metadata_text {
+            metadata_text.push_str(text);
+            return;
+        }
+        let Some(paragraph) = &mut self.paragraph else {
+            return;
+        };
+        match self.spans.last_mut() {
+            Some(span) if span.collects_text() => span.text.push_str(text),
+            _ => paragraph.push_text(text),
+        }
+    }
+
+    fn end(&mut self, name: &[u8]) {
+        match name {
+            b"p" => {
+                self.spans.clear();
+                if let Some(line) = self.paragraph.take().and_then(Paragraph::finish) {
+                    self.lines.push(line);
+                }
+            }
+            b"span" => {
+                let (Some(span), Some(paragraph)) = (self.spans.pop(), &mut self.paragraph) else {
+                    return;
+                };
+                match (span.role, self.spans.last_mut()) {
+                    (Role::Translation, _) => paragraph.translation = Some(collapse(&span.text)),
+                    (Role::Romanization, _) => paragraph.romanization = Some(collapse(&span.text)),
+                    // Syllables of a translation or romanization make up its text.
+                    (Role::Other, Some(parent)) if span.alternative => {
+                        parent.text.push_str(&span.text)
+                    }
+                    (Role::Other, _) => match (span.begin, span.end) {
+                        (Some(begin), Some(end)) => paragraph.push_word(&span.text, begin, end),
+                        _ => paragraph.push_text(&span.text),
+                    },
+                }
+            }
+            b"translation" | b"transliteration" => self.metadata = None,
+            b"text" => {
+                if let (Some((key, text)), Some(role)) = (self.metadata_text.take(), self.metadata)
+                {
+                    let map = match role {
+                        Role::Romanization => &mut self.romanizations,
+                        _ => &mut self.translations,
+                    };
+                    // Several translations: keep the first language.
+                    map.entry(key).or_insert_with(|| collapse(&text));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(self) -> Lyrics {
+        let TtmlReader {
+            translations,
+            romanizations,
+            lines,
+            ..
+        } = self;
+        let mut lines: Vec<LyricLine> = lines
+            .into_iter()
+            .map(|(key, mut line)| {
+                if let Some(key) = key {
+                    if line.translation.is_none() {
+                        line.translation = translations.get(&key).cloned();
+                    }
+                    if line.romanization.is_none() {
+                        line.romanization = romanizations.get(&key).cloned();
+                    }
+                }
+                line
+            })
+            .collect();
+        lines.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Lyrics {
+            lines,
+            ..Lyrics::default()
+        }
+    }
+}
+
+/// Parses TTML lyrics as Apple Music serves them: one `<p>` per line, word or syllable
+/// timing from timed `<span>`s, and translations and romanizations either as spans with
+/// a `ttm:role` or in the `<iTunesMetadata>` of the head, keyed by `itunes:key`.
+pub fn parse(text: &str) -> Lyrics {
+    let mut reader = Reader::from_str(text);
+    let mut state = TtmlReader::default();
+    loop {
+        let event = match reader.read_event() {
+            Ok(event) => event,
+            Err(e) => {
+                log::warn!("Invalid TTML at byte {}: {}", reader.buffer_position(), e);
+                break;
+            }
+        };
+        match event {
+            Event::Start(e) => state.start(&e),
+            Event::Empty(e) if e.local_name().as_ref() == b"br" => state.text(" "),
+            Event::End(e) => state.end(e.local_name().as_ref()),
+            Event::Text(e) => {
+                if let Ok(text) = e.decode() {
+                    state.text(&text);
+                }
+            }
+            Event::CData(e) => {
+                if let Ok(text) = e.decode() {
+                    state.text(&text);
+                }
+            }
+            Event::GeneralRef(e) => {
+                if let Ok(Some(c)) = e.resolve_char_ref() {
+                    state.text(c.encode_utf8(&mut [0; 4]));
+                } else if let Ok(name) = e.decode() {
+                    state.text(resolve_predefined_entity(&name).unwrap_or_default());
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    state.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str, start_time: f64, end_time: f64) -> LyricWord {
+        LyricWord {
+            text: text.to_string(),
+            start_time,
+            end_time,
+        }
+    }
+
+    #[test]
+    fn time_expressions() {
+        assert_eq!(parse_time("1:02.5"), Some(62.5));
+        assert_eq!(parse_time("00:01:02.500"), Some(62.5));
+        assert_eq!(parse_time("62.5s"), Some(62.5));
+        assert_eq!(parse_time("62500ms"), Some(62.5));
+        assert_eq!(parse_time("1.5m"), Some(90.0));
+        assert_eq!(parse_time("0.5h"), Some(1800.0));
+        assert_eq!(parse_time("3"), Some(3.0));
+        assert_eq!(parse_time("-1s"), None);
+        assert_eq!(parse_time("1:-2"), None);
+    }
+
+    #[test]
+    fn word_timed_spans_with_roles() {
+        let lyrics = parse(
+            r#"<tt xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div>
+            <p begin="2.0" end="4.0"><span begin="2.0" end="2.5">Hel</span><span begin="2.5" end="3.0">lo</span> <span begin="3.0" end="4.0">world</span>
+                <span ttm:role="x-translation">你好 世界</span>
+                <span ttm:role="x-roman"><span begin="2.0" end="3.0">ni hao</span></span>
+                <span ttm:role="x-bg"><span begin="3.5" end="4.0">(oh)</span></span>
+            </p>
+            <p begin="00:00:01.000">Line &amp; timed<br/>only</p>
+            </div></body></tt>"#,
+        );
+        assert_eq!(lyrics.lines.len(), 2);
+        let line = &lyrics.lines[0];
+        assert_eq!(line.time, 1.0);
+        assert_eq!(line.text, "Line & timed only");
+        assert!(line.words.is_empty());
+        let line = &lyrics.lines[1];
+        assert_eq!(line.time, 2.0);
+        assert_eq!(line.text, "Hello world (oh)");
+        assert_eq!(
+            line.words,
+            [
+                word("Hel", 2.0, 2.5),
+                word("lo ", 2.5, 3.0),
+                word("world ", 3.0, 4.0),
+                word("(oh)", 3.5, 4.0),
+            ]
+        );
+        assert_eq!(line.translation.as_deref(), Some("你好 世界"));
+        assert_eq!(line.romanization.as_deref(), Some("ni hao"));
+    }
+
+    #[test]
+    fn head_metadata_by_key() {
+        let lyrics = parse(
+            r#"<tt><head><metadata><iTunesMetadata>
+                <translations>
+                    <translation lang="en"><text for="L1">Hello</text></translation>
+                    <translation lang="fr"><text for="L1">Bonjour</text></translation>
+                </translations>
+                <transliterations>
+                    <transliteration><text for="L1">kon'nichiwa</text></transliteration>
+                </transliterations>
+            </iTunesMetadata></metadata></head>
+            <body><p begin="1s" itunes:key="L1">こんにちは</p><p begin="2s">
+            </p></body></tt>"#,
+        );
+        assert_eq!(lyrics.lines.len(), 1);
+        assert_eq!(lyrics.lines[0].text, "こんにちは");
+        assert_eq!(lyrics.lines[0].translation.as_deref(), Some("Hello"));
+        assert_eq!(lyrics.lines[0].romanization.as_deref(), Some("kon'nichiwa"));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::library::{LibraryState, TrackRecord};
+use notify_debouncer_full::notify::event::{ModifyKind, RenameMode};
+use notify_debouncer_full::notify::{EventKind, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult, Debouncer, RecommendedCache};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+pub const TRACK_ADDED_EVENT: &str = "track-added";
+pub const TRACK_REMOVED_EVENT: &str = "track-removed";
+pub const TRACK_MOVED_EVENT: &str = "track-moved";
+
+/// How long the filesystem has to stay quiet before a burst (e.g. an album being
+/// copied in) is processed as one batch.
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct TrackRemoved {
+    path: String,
+    /// Set for cue tracks, which share `path` with the other tracks of their rip.
+    start: Option<f64>,
+}
+
+impl From<&TrackRecord> for TrackRemoved {
+    fn from(record: &TrackRecord) -> Self {
+        TrackRemoved {
+            path: record.path.clone(),
+            start: record.start,
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct TrackMoved {
+    from: String,
+    to: String,
+    name: String,
+    start: Option<f64>,
+}
+
+/// Watches every registered library root and keeps the index in sync with disk.
+pub struct LibraryWatcher {
+    debouncer: Mutex<Option<Debouncer<RecommendedWatcher, RecommendedCache>>>,
+}
+
+impl LibraryWatcher {
+    /// Creates the watcher and registers all roots already known to the library.
+    /// If the platform watcher cannot be created, the library simply stays static.
+    pub fn start(app: &AppHandle) -> Self {
+        let handle = app.clone();
+        let debouncer = new_debouncer(
+            DEBOUNCE_TIMEOUT,
+            None,
+            move |result: DebounceEventResult| handle_events(&handle, result),
+        );
+        let watcher = LibraryWatcher {
+            debouncer: Mutex::new(match debouncer {
+                Ok(d) => Some(d),
+                Err(e) => {
+                    log::warn!("Filesystem watcher unavailable: {}", e);
+                    None
+                }
+            }),
+        };
+        for root in app.state::<LibraryState>().roots() {
+            watcher.watch(&root);
+        }
+        watcher
+    }
+
+    pub fn watch(&self, root: &str) {
+        if let Some(debouncer) = self.debouncer.lock().unwrap().as_mut() {
+            if let Err(e) = debouncer.watch(Path::new(root), RecursiveMode::Recursive) {
+                log::warn!("Failed to watch library root {}: {}", root, e);
+            }
+        }
+    }
+
+    pub fn unwatch(&self, root: &str) {
+        if let Some(debouncer) = self.debouncer.lock().unwrap().as_mut() {
+            // Unwatching a root that was never watched (e.g. it did not exist) is harmless.
+            let _ = debouncer.unwatch(Path::new(root));
+        }
+    }
+}
+
+fn handle_events(app: &AppHandle, result: DebounceEventResult) {
+    let events = match result {
+        Ok(events) => events,
+        Err(errors) => {
+            for e in errors {
+                log::warn!("Filesystem watcher error: {}", e);
+            }
+            return;
+        }
+    };
+
+    let library = app.state::<LibraryState>();
+    let mut changed = false;
+
+    for event in events {
+        match event.kind {
+            EventKind::Create(_) => {
+                for path in &event.paths {
+                    changed |= added(app, &library, path);
+                }
+            }
+            EventKind::Remove(_) => {
+                for path in &event.paths {
+                    changed |= removed(app, &library, path);
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                changed |= moved(app, &library, &event.paths[0], &event.paths[1]);
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                for path in &event.paths {
+                    changed |= removed(app, &library, path);
+                }
+            }
+            EventKind::Modify(_) => {
+                // Covers `RenameMode::To`/`Any` as well as content writes: whatever now
+                // exists at the path gets (re)indexed, whatever vanished gets dropped.
+                for path in &event.paths {
+                    changed |= if path.exists() {
+                        added(app, &library, path)
+                    } else {
+                        removed(app, &library, path)
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if changed {
+        if let Err(e) = library.persist() {
+            log::warn!("{}", e);
+        }
+    }
+}
+
+fn added(app: &AppHandle, library: &LibraryState, path: &Path) -> bool {
+    let changes = library.index_path(path);
+    for record in &changes.removed {
+        let _ = app.emit(TRACK_REMOVED_EVENT, TrackRemoved::from(record));
+    }
+    for record in &changes.added {
+        let _ = app.emit(TRACK_ADDED_EVENT, record);
+    }
+    // Re-probed (modified) files are not reported but still need persisting.
+    !changes.added.is_empty() || !changes.removed.is_empty() || changes.updated > 0
+}
+
+fn removed(app: &AppHandle, library: &LibraryState, path: &Path) -> bool {
+    let records = library.remove_path(path);
+    for record in &records {
+        let _ = app.emit(TRACK_REMOVED_EVENT, TrackRemoved::from(record));
+    }
+    // Deleting a cue sheet turns its rip back into a single track.
+    let orphaned: HashSet<&str> = records
+        .iter()
+        .filter(|r| r.cue_sheet.is_some())
+        .map(|r| r.path.as_str())
+        .collect();
+    for audio in orphaned {
+        if Path::new(audio).exists() {
+            added(app, library, Path::new(audio));
+        }
+    }
+    !records.is_empty()
+}
+
+fn moved(app: &AppHandle, library: &LibraryState, from: &Path, to: &Path) -> bool {
+    let (moves, dropped) = library.move_path(from, to);
+    for record in &dropped {
+        let _ = app.emit(TRACK_REMOVED_EVENT, TrackRemoved::from(record));
+    }
+    if moves.is_empty() && dropped.is_empty() {
+        // Nothing indexed was renamed, e.g. a temp file renamed into place by a copy tool.
+        return added(app, library, to);
+    }
+    for (old, record) in &moves {
+        let _ = app.emit(
+            TRACK_MOVED_EVENT,
+            TrackMoved {
+                from: old.path.clone(),
+                to: record.path.clone(),
+                name: record.name.clone(),
+                start: record.start,
+            },
+        );
+    }
+    // The destination may also hold files that were never indexed (e.g. renamed from an
+    // unsupported extension), so index whatever is there now.
+    added(app, library, to);
+    true
+}
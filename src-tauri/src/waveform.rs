@@ -0,0 +1,304 @@
+use crate::cache::{CacheKey, TranscodeCache};
+use crate::error::AppError;
+use crate::pcm::{CurrentJob, Decode};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+pub const WAVEFORM_READY_EVENT: &str = "waveform-ready";
+
+/// Buckets per channel when the frontend does not ask for a specific number.
+const DEFAULT_BUCKETS: u32 = 2000;
+/// Upper bound on requested buckets, so a bogus request cannot allocate without limit.
+const MAX_BUCKETS: u32 = 20_000;
+/// Channels beyond this are downmixed; a waveform bar has no room for surround.
+const MAX_CHANNELS: u32 = 2;
+/// Peaks do not need more than this; DSD and other very high rates are resampled.
+const MAX_SAMPLE_RATE: u32 = 96_000;
+/// Frames summarised into one min/max pair while decoding, before the final downsampling.
+const BLOCK_FRAMES: usize = 256;
+
+/// Min/max envelope of one channel, as fractions of full scale (-1.0 to 1.0).
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelPeaks {
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Waveform {
+    pub buckets: u32,
+    /// Seconds covered by the buckets.
+    pub duration: f64,
+    pub channels: Vec<ChannelPeaks>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct WaveformReady {
+    /// The track as passed to `request_waveform`.
+    path: String,
+    start: Option<f64>,
+    end: Option<f64>,
+    waveform: Option<Waveform>,
+    error: Option<AppError>,
+}
+
+/// Per-channel min/max of consecutive blocks of `BLOCK_FRAMES` frames.
+struct Envelope {
+    blocks: Vec<ChannelPeaks>,
+    /// Min/max of the block being filled, per channel.
+    current: Vec<(i16, i16)>,
+    frames_in_block: usize,
+}
+
+impl Envelope {
+    fn new(channels: usize) -> Self {
+        Envelope {
+            blocks: vec![ChannelPeaks::default(); channels],
+            current: vec![(i16::MAX, i16::MIN); channels],
+            frames_in_block: 0,
+        }
+    }
+
+    /// Adds interleaved 16-bit little-endian frames.
+    fn push(&mut self, frames: &[u8]) {
+        let channels = self.current.len();
+        for frame in frames.chunks_exact(channels * 2) {
+            for (c, sample) in frame.chunks_exact(2).enumerate() {
+                let sample = i16::from_le_bytes([sample[0], sample[1]]);
+                let (min, max) = &mut self.current[c];
+                *min = (*min).min(sample);
+                *max = (*max).max(sample);
+            }
+            self.frames_in_block += 1;
+            if self.frames_in_block == BLOCK_FRAMES {
+                self.flush();
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.frames_in_block == 0 {
+            return;
+        }
+        for (peaks, (min, max)) in self.blocks.iter_mut().zip(&mut self.current) {
+            peaks.min.push(f32::from(*min) / 32768.0);
+            peaks.max.push(f32::from(*max) / 32768.0);
+            (*min, *max) = (i16::MAX, i16::MIN);
+        }
+        self.frames_in_block = 0;
+    }
+
+    /// Reduces the blocks to `buckets` min/max pairs per channel.
+    fn finish(mut self, buckets: u32, duration: f64) -> Waveform {
+        self.flush();
+        let buckets_len = buckets as usize;
+        let channels = self
+            .blocks
+            .into_iter()
+            .map(|block| {
+                let len = block.min.len();
+                let mut peaks = ChannelPeaks::default();
+                for b in 0..buckets_len {
+                    let start = b * len / buckets_len;
+                    let end = ((b + 1) * len / buckets_len).max(start + 1).min(len);
+                    if start >= end {
+                        peaks.min.push(0.0);
+                        peaks.max.push(0.0);
+                        continue;
+                    }
+                    peaks
+                        .min
+                        .push(block.min[start..end].iter().copied().fold(0.0, f32::min));
+                    peaks
+                        .max
+                        .push(block.max[start..end].iter().copied().fold(0.0, f32::max));
+                }
+                peaks
+            })
+            .collect();
+        Waveform {
+            buckets,
+            duration,
+            channels,
+        }
+    }
+}
+
+/// The waveform computation in progress, cancelled when another track is requested.
+#[derive(Default)]
+pub struct WaveformJobs {
+    current: CurrentJob,
+}
+
+/// Decodes the track and computes its envelope. `Ok(None)` means it was cancelled.
+fn compute(
+    path: &Path,
+    start: Option<f64>,
+    end: Option<f64>,
+    buckets: u32,
+    cancelled: &AtomicBool,
+) -> Result<Option<Waveform>, AppError> {
+    let decode = Decode::new(path, start, end, MAX_CHANNELS, MAX_SAMPLE_RATE);
+    let mut envelope = Envelope::new(decode.channels as usize);
+    let frames = match decode.run(cancelled, |frames| envelope.push(frames))? {
+        Some(frames) => frames,
+        None => return Ok(None),
+    };
+    let duration = frames as f64 / f64::from(decode.sample_rate);
+    Ok(Some(envelope.finish(buckets, duration)))
+}
+
+/// Returns the min/max peak envelope of a track (`buckets` pairs per channel, at most two
+/// channels) if it is in the transcode cache already. Otherwise decodes the track in the
+/// background at low priority, caches the result and delivers it with a `waveform-ready`
+/// event, and returns `None`. A request for another track cancels the previous one.
+/// `path` is URL-encoded like for `start_transcode_job`.
+#[tauri::command]
+pub fn request_waveform(
+    path: String,
+    start: Option<f64>,
+    end: Option<f64>,
+    buckets: Option<u32>,
+    app: AppHandle,
+    jobs: State<'_, WaveformJobs>,
+    cache: State<'_, TranscodeCache>,
+) -> Result<Option<Waveform>, AppError> {
+    let buckets = buckets.unwrap_or(DEFAULT_BUCKETS).clamp(1, MAX_BUCKETS);
+    let decoded = urlencoding::decode(&path)
+        .map_err(|e| AppError::invalid_argument(format!("Failed to decode path: {}", e)))?
+        .into_owned();
+    let key = CacheKey::new(
+        Path::new(&decoded),
+        start,
+        end,
+        &format!("peaks-{}", buckets),
+    )?;
+    if let Some(waveform) = cache.get_json(&key) {
+        return Ok(Some(waveform));
+    }
+
+    let cancelled = jobs.current.start();
+    std::thread::spawn(move || {
+        let result = compute(Path::new(&decoded), start, end, buckets, &cancelled);
+        let (waveform, error) = match result {
+            Ok(Some(waveform)) => (Some(waveform), None),
+            Ok(None) => return,
+            Err(e) => (None, Some(e)),
+        };
+        if let Some(waveform) = &waveform {
+            if let Err(e) = app.state::<TranscodeCache>().insert_json(&key, waveform) {
+                log::warn!("Failed to cache waveform: {}", e);
+            }
+        }
+        let _ = app.emit(
+            WAVEFORM_READY_EVENT,
+            WaveformReady {
+                path,
+                start,
+                end,
+                waveform,
+                error,
+            },
+        );
+    });
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Interleaved 16-bit little-endian PCM, as the decoder delivers it.
+    fn pcm(samples: impl IntoIterator<Item = i16>) -> Vec<u8> {
+        samples.into_iter().flat_map(i16::to_le_bytes).collect()
+    }
+
+    /// One mono block of `BLOCK_FRAMES` frames swinging between `min` and `max`.
+    fn block(min: i16, max: i16) -> Vec<u8> {
+        pcm((0..BLOCK_FRAMES).map(|i| if i % 2 == 0 { min } else { max }))
+    }
+
+    #[test]
+    fn one_bucket_per_block() {
+        let mut envelope = Envelope::new(1);
+        envelope.push(&block(-16384, 8192));
+        envelope.push(&block(-8192, 16384));
+        envelope.push(&block(0, 0));
+        envelope.push(&block(-32768, 32767));
+        let waveform = envelope.finish(4, 1.0);
+
+        assert_eq!(waveform.buckets, 4);
+        let peaks = &waveform.channels[0];
+        assert_eq!(peaks.min, [-0.5, -0.25, 0.0, -1.0]);
+        assert_eq!(peaks.max, [0.25, 0.5, 0.0, 32767.0 / 32768.0]);
+    }
+
+    #[test]
+    fn buckets_merge_blocks_per_channel() {
+        let mut envelope = Envelope::new(2);
+        // Left rises to half scale over the first half, right dips in the second half.
+        let frames = (0..BLOCK_FRAMES * 8).flat_map(|i| {
+            let first_half = i < BLOCK_FRAMES * 4;
+            [
+                if first_half { 16384 } else { 0 },
+                if first_half { 0 } else { -16384 },
+            ]
+        });
+        // Delivered in chunks that do not line up with the blocks.
+        for chunk in pcm(frames).chunks(4 * 100) {
+            envelope.push(chunk);
+        }
+        let waveform = envelope.finish(2, 1.0);
+
+        assert_eq!(waveform.channels.len(), 2);
+        let (left, right) = (&waveform.channels[0], &waveform.channels[1]);
+        assert_eq!(left.max, [0.5, 0.0]);
+        assert_eq!(left.min, [0.0, 0.0]);
+        assert_eq!(right.max, [0.0, 0.0]);
+        assert_eq!(right.min, [0.0, -0.5]);
+    }
+
+    #[test]
+    fn last_partial_block_is_kept() {
+        let mut envelope = Envelope::new(1);
+        envelope.push(&block(-1, 1));
+        envelope.push(&block(-1, 1));
+        envelope.push(&pcm([-32768; 10]));
+        let waveform = envelope.finish(3, 1.0);
+
+        let peaks = &waveform.channels[0];
+        assert_eq!(peaks.min.len(), 3);
+        assert_eq!(peaks.min[2], -1.0);
+        assert_eq!(peaks.max[2], 0.0);
+    }
+
+    #[test]
+    fn more_buckets_than_blocks() {
+        let mut envelope = Envelope::new(1);
+        envelope.push(&block(-16384, 0));
+        envelope.push(&block(0, 16384));
+        let waveform = envelope.finish(8, 1.0);
+
+        let peaks = &waveform.channels[0];
+        assert_eq!(peaks.min, [-0.5, -0.5, -0.5, -0.5, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(peaks.max, [0.0, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn silence_and_empty_input() {
+        let mut envelope = Envelope::new(1);
+        envelope.push(&pcm([0; BLOCK_FRAMES * 3]));
+        let waveform = envelope.finish(3, 1.0);
+        assert_eq!(waveform.channels[0].min, [0.0; 3]);
+        assert_eq!(waveform.channels[0].max, [0.0; 3]);
+
+        let waveform = Envelope::new(2).finish(5, 0.0);
+        assert_eq!(waveform.channels.len(), 2);
+        assert_eq!(waveform.channels[1].min, [0.0; 5]);
+    }
+}
@@ -1,5 +1,6 @@
 // ES 模块导入
 import { invoke, convertFileSrc } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { WebviewWindow } from '@tauri-apps/api/webviewWindow';
 import { open as dialogOpen, ask } from '@tauri-apps/plugin-dialog';
 // Import shell.open from plugin-shell to launch default system browser
@@ -110,6 +111,26 @@ const bgBlurSetting = document.getElementById('bg-blur-setting');
 const playerCardBgBlurRange = document.getElementById('player-card-bg-blur-range');
 const playerCardBgBlurContainer = document.getElementById('player-card-bg-blur-container');
 
+// Backend Settings Elements
+const transcodeCacheLimitInput = document.getElementById('transcode-cache-limit-input');
+const transcodeContainerSelect = document.getElementById('transcode-container-select');
+const transcodeSampleRateSelect = document.getElementById('transcode-sample-rate-select');
+const transcodeSampleRateContainer = document.getElementById('transcode-sample-rate-container');
+const transcodeBitDepthSelect = document.getElementById('transcode-bit-depth-select');
+const transcodeBitDepthContainer = document.getElementById('transcode-bit-depth-container');
+const transcodeDitherToggle = document.getElementById('transcode-dither-toggle');
+const transcodeDitherContainer = document.getElementById('transcode-dither-container');
+const transcodeChannelsSelect = document.getElementById('transcode-channels-select');
+const transcodeOpusBitrateInput = document.getElementById('transcode-opus-bitrate-input');
+const transcodeOpusBitrateContainer = document.getElementById('transcode-opus-bitrate-container');
+const replayGainSelect = document.getElementById('replay-gain-select');
+const bakeReplayGainToggle = document.getElementById('bake-replay-gain-toggle');
+const bakeReplayGainContainer = document.getElementById('bake-replay-gain-container');
+const lyricsFoldersInput = document.getElementById('lyrics-folders-input');
+const lyricsExtensionsInput = document.getElementById('lyrics-extensions-input');
+const lyricsLanguagesInput = document.getElementById('lyrics-languages-input');
+const preferEmbeddedLyricsToggle = document.getElementById('prefer-embedded-lyrics-toggle');
+
 /**
  * Wrap ASCII/latin sequences with span.latin so他们使用英文字体
  * @param {string} text raw text line
@@ -134,14 +155,33 @@ loadingOverlay.classList.add('ui-hidden');
     let artworkUrl = null;
 // 当前正在播放的临时缓存文件路径，用于后续删除
 let currentAudioCachePath = null;
+// 正在边转码边播放的流 id（stream:// 协议）
+let currentStreamId = null;
+// 当前曲目的请求参数（与 start_transcode_job 一致），用于匹配后到的响度测量结果
+let currentTrackRequest = null;
 let parsedLyrics = [];
 let currentLyricIndex = -1;
+// 无时间轴的纯文本歌词：整页静态显示，不随播放滚动
+let lyricsUnsynced = false;
 // State for lyrics display mode
-// 0: off, 1: translation only, 2: bilingual (orig/trans), 3: bilingual-reversed (trans/orig), 4: original only, 5: text only, 6: text only (reversed)
+// 0: off, 1: translation only, 2: bilingual (orig/trans), 3: bilingual-reversed (trans/orig), 4: original only, 5: text only, 6: text only (reversed), 7: romanized (orig/romanization/trans)
 let lyricsDisplayMode = 0;
 let currentDominantColorRGB = null; // Store dominant color for player card background
 let currentBgMode = 'static'; // 'static' or 'silk'
 
+// 支持的音频扩展名，启动时从后端 get_default_audio_extensions 获取
+let audioExtensions = [];
+let audioFilePattern = /$^/;
+
+async function loadAudioExtensions() {
+    try {
+        audioExtensions = await invoke('get_default_audio_extensions');
+        audioFilePattern = new RegExp(`\\.(${audioExtensions.join('|')})$`, 'i');
+    } catch (e) {
+        console.error('Failed to load audio extensions:', e);
+    }
+}
+
 // Playlist State
 let playlist = [];
 let currentPlaylistIndex = -1;
@@ -513,6 +553,117 @@ Object.entries(fontSelectorMap).forEach(([type, selector]) => {
     });
 });
 
+// 后端设置（settings.json）。set_settings 会整体替换设置，因此始终回写完整对象。
+let backendSettings = null;
+
+async function saveBackendSettings() {
+    try {
+        await invoke('set_settings', { settings: backendSettings });
+    } catch (e) {
+        console.error('Failed to save settings:', e);
+        alert(`保存设置失败：${describeError(e)}`);
+    }
+}
+
+/**
+ * Loads the backend settings and wires up the controls that edit them.
+ */
+async function setupBackendSettings() {
+    try {
+        backendSettings = await invoke('get_settings');
+    } catch (e) {
+        console.error('Failed to load settings:', e);
+        return;
+    }
+
+    // 转码缓存上限，以 MB 显示；留空表示使用默认值
+    const cacheLimit = backendSettings.transcodeCacheLimit;
+    transcodeCacheLimitInput.value = cacheLimit == null ? '' : Math.round(cacheLimit / (1024 * 1024));
+    transcodeCacheLimitInput.addEventListener('change', () => {
+        const mb = parseInt(transcodeCacheLimitInput.value, 10);
+        backendSettings.transcodeCacheLimit = Number.isFinite(mb) && mb >= 0 ? mb * 1024 * 1024 : null;
+        saveBackendSettings();
+    });
+
+    // 转码配置：只对之后准备的曲目生效
+    const profile = backendSettings.transcodeProfile;
+    const sampleRate = profile.sampleRate == null ? '' : String(profile.sampleRate);
+    if (!Array.from(transcodeSampleRateSelect.options).some(opt => opt.value === sampleRate)) {
+        // settings.json 中手动填写的采样率
+        transcodeSampleRateSelect.add(new Option(`${sampleRate / 1000} kHz`, sampleRate));
+    }
+    transcodeContainerSelect.value = profile.container;
+    transcodeSampleRateSelect.value = sampleRate;
+    transcodeBitDepthSelect.value = profile.bitDepth;
+    transcodeDitherToggle.checked = profile.dither;
+    transcodeChannelsSelect.value = profile.channels;
+    transcodeOpusBitrateInput.value = profile.opusBitrate;
+    replayGainSelect.value = profile.replayGain;
+    bakeReplayGainToggle.checked = profile.bakeReplayGain;
+    updateTranscodeSettingsVisibility();
+
+    const onProfileChange = () => {
+        const bitrate = parseInt(transcodeOpusBitrateInput.value, 10);
+        Object.assign(profile, {
+            container: transcodeContainerSelect.value,
+            sampleRate: transcodeSampleRateSelect.value ? parseInt(transcodeSampleRateSelect.value, 10) : null,
+            bitDepth: transcodeBitDepthSelect.value,
+            dither: transcodeDitherToggle.checked,
+            channels: transcodeChannelsSelect.value,
+            opusBitrate: Number.isFinite(bitrate) ? Math.min(510, Math.max(6, bitrate)) : profile.opusBitrate,
+            replayGain: replayGainSelect.value,
+            bakeReplayGain: bakeReplayGainToggle.checked,
+        });
+        transcodeOpusBitrateInput.value = profile.opusBitrate;
+        updateTranscodeSettingsVisibility();
+        saveBackendSettings();
+    };
+    [transcodeContainerSelect, transcodeSampleRateSelect, transcodeBitDepthSelect, transcodeChannelsSelect, replayGainSelect].forEach(sel => {
+        sel.addEventListener('change', onProfileChange);
+        setupCustomSelect(sel);
+    });
+    transcodeDitherToggle.addEventListener('change', onProfileChange);
+    transcodeOpusBitrateInput.addEventListener('change', onProfileChange);
+    bakeReplayGainToggle.addEventListener('change', onProfileChange);
+
+    // 歌词文件查找：逗号分隔的列表，留空表示使用默认值
+    const lyricsSearch = backendSettings.lyricsSearch;
+    const listInputs = [
+        [lyricsFoldersInput, 'folders'],
+        [lyricsExtensionsInput, 'extensions'],
+        [lyricsLanguagesInput, 'languages'],
+    ];
+    listInputs.forEach(([input, key]) => {
+        input.value = lyricsSearch[key].join(',');
+        input.addEventListener('change', () => {
+            const items = input.value.split(/[,，]/).map(item => item.trim()).filter(Boolean);
+            if (key === 'languages' || items.length > 0) {
+                lyricsSearch[key] = key === 'extensions'
+                    ? items.map(ext => ext.replace(/^\./, '').toLowerCase())
+                    : items;
+            } else {
+                delete lyricsSearch[key];
+            }
+            saveBackendSettings();
+        });
+    });
+    preferEmbeddedLyricsToggle.checked = lyricsSearch.preferEmbedded;
+    preferEmbeddedLyricsToggle.addEventListener('change', () => {
+        lyricsSearch.preferEmbedded = preferEmbeddedLyricsToggle.checked;
+        saveBackendSettings();
+    });
+}
+
+// Opus 固定 48 kHz 且没有位深；其他格式没有码率；关闭响度均衡时没有可写入的增益
+function updateTranscodeSettingsVisibility() {
+    const isOpus = transcodeContainerSelect.value === 'opus';
+    transcodeSampleRateContainer.classList.toggle('hidden', isOpus);
+    transcodeBitDepthContainer.classList.toggle('hidden', isOpus);
+    transcodeDitherContainer.classList.toggle('hidden', isOpus);
+    transcodeOpusBitrateContainer.classList.toggle('hidden', !isOpus);
+    bakeReplayGainContainer.classList.toggle('hidden', replayGainSelect.value === 'off');
+}
+
 /**
  * Sets up all event listeners and initial state for the settings panel.
  */
@@ -1195,7 +1346,145 @@ const appWindow = WebviewWindow.getCurrent();
 
 let activeLoadToken = 0;
 
-async function handleFile(filePath, autoPlay = true) {
+// 将后端返回的结构化错误 { code, message, path?, exitStatus?, stderr? } 转为可读提示
+function describeError(error) {
+    if (!error || typeof error !== 'object') return String(error);
+    switch (error.code) {
+        case 'ffmpegMissing': return '未找到 ffmpeg，请在设置中指定 ffmpeg 路径或下载 ffmpeg。';
+        case 'fileNotFound': return `文件不存在：${error.path}`;
+        case 'permissionDenied': return `没有访问权限：${error.path}`;
+        case 'unsupportedFormat': return `不支持或已损坏的音频文件：${error.path}`;
+        default: return error.message;
+    }
+}
+
+// 当前后台转码任务 id（用于取消）
+let activeTranscodeJobId = null;
+
+// 在后台准备音频（读取元数据、转码或开始流式转码），并在 Loading 遮罩上显示进度。
+// 任务被取消（或被更新的请求取代）时返回 null。
+async function prepareAudio(filePath, range) {
+    const finishedJobs = new Map(); // jobId -> transcode-finished payload
+    let jobId = null;
+    let settle;
+    const finished = new Promise(resolve => { settle = resolve; });
+    const tryFinish = () => {
+        if (jobId !== null && finishedJobs.has(jobId)) settle(finishedJobs.get(jobId));
+    };
+
+    const unlisteners = await Promise.all([
+        listen('transcode-progress', ({ payload }) => {
+            if (payload.jobId !== jobId || payload.stage === 'metadata') return;
+            const percent = payload.progress == null ? '' : ` ${Math.round(payload.progress * 100)}%`;
+            const label = payload.stage === 'loudness' ? 'Measuring Loudness...' : 'Transcoding Audio...';
+            showLoading(`${label}${percent}`);
+        }),
+        listen('transcode-finished', ({ payload }) => {
+            finishedJobs.set(payload.jobId, payload);
+            tryFinish();
+        }),
+    ]);
+
+    try {
+        jobId = await invoke('start_transcode_job', {
+            path: encodeURIComponent(filePath),
+            start: range?.start ?? null,
+            end: range?.end ?? null,
+        });
+        activeTranscodeJobId = jobId;
+        tryFinish();
+        const result = await finished;
+        // 缺少 ffmpeg 时附带已读取的曲目信息
+        if (result.error) throw result.track ? { ...result.error, track: result.track } : result.error;
+        return result.cancelled ? null : result.result;
+    } finally {
+        unlisteners.forEach(unlisten => unlisten());
+        if (activeTranscodeJobId === jobId) activeTranscodeJobId = null;
+    }
+}
+
+// 按后端给出的 ReplayGain 增益调整音量；<audio> 只能衰减，正增益按原音量播放
+function applyPlaybackGain(gain) {
+    audioPlayer.volume = gain == null ? 1 : Math.min(1, Math.pow(10, gain / 20));
+}
+
+// 播放开始后才测量出的响度：仅在仍是当前曲目时应用
+function setupLoudnessListener() {
+    listen('loudness-measured', ({ payload }) => {
+        if (!currentTrackRequest) return;
+        if (payload.path !== currentTrackRequest.path || payload.start !== currentTrackRequest.start) return;
+        applyPlaybackGain(payload.playbackGain);
+    });
+}
+
+function cancelActiveTranscode() {
+    if (activeTranscodeJobId !== null) {
+        invoke('cancel_transcode_job', { jobId: activeTranscodeJobId }).catch(() => {});
+    }
+}
+
+// 显示曲目的标签、封面和歌词
+function showTrackInfo(info) {
+    lyricsUnsynced = info.lyrics?.unsynced ?? false;
+    lyricsLinesContainer.classList.toggle('unsynced', lyricsUnsynced);
+    if (info.lyrics) {
+        parsedLyrics = buildLyricLines(info.lyrics);
+        console.log(`Parsed ${parsedLyrics.length} lines of lyrics from`, info.lyrics.source);
+        noLyricsMessage.classList.toggle('hidden', parsedLyrics.length > 0);
+        renderAllLyricsOnce();
+    } else {
+        console.log('No embedded lyrics found from backend.');
+        parsedLyrics = [];
+        renderAllLyricsOnce();
+        noLyricsMessage.classList.remove('hidden');
+    }
+    updateLyrics(0);
+
+    artistNameEl.textContent = info.metadata.artist || 'Unknown Artist';
+    songTitleEl.textContent = info.metadata.title || 'Unknown Title';
+
+    // Manually trigger check after new text is set.
+    // A small timeout helps ensure scrollWidth is updated.
+    setTimeout(() => {
+        // applyMarquee(songTitleEl);
+        // applyMarquee(artistNameEl);
+    }, 100);
+
+    if (info.albumArtBase64) {
+        const mimeType = info.metadata.mimeType || 'image/jpeg';
+        artworkUrl = `data:${mimeType};base64,${info.albumArtBase64}`;
+        albumArt.src = artworkUrl;
+        albumArt.style.display = 'block';
+        
+        // Always analyze image to get dominant color for background
+        analyzeImageAndApplyColors(artworkUrl);
+
+        // If adaptive color is disabled, ensure custom color is applied
+        if (!adaptiveColorToggle.checked) {
+            updateAdaptiveColors();
+        }
+    } else {
+        // No artwork found.
+        artworkUrl = '';
+        albumArt.src = '';
+        albumArt.style.display = 'none';
+        applyAdaptiveColors({ text: '#ffffff' });
+    }
+
+    // After updating artworkUrl, refresh all backgrounds
+    updateBackgrounds();
+}
+
+// 从文件选择页切换到播放器界面
+function showPlayer() {
+    fileSelectContainer.classList.add('hidden');
+    githubLink.classList.add('hidden');
+    settingsBtn.classList.add('hidden'); // Hide settings icon
+    playerWrapper.classList.remove('hidden');
+}
+
+// range: 可选的 { start, end }（秒），用于 CUE 分轨的整轨音频
+async function handleFile(filePath, autoPlay = true, range = null) {
     if (!filePath) {
         // This case handles when the user cancels the dialog
         console.log("File selection was cancelled.");
@@ -1219,69 +1508,44 @@ async function handleFile(filePath, autoPlay = true) {
             invoke('cleanup_cached_file', { path: currentAudioCachePath }).catch(() => {});
             currentAudioCachePath = null;
         }
+        if (currentStreamId !== null) {
+            invoke('close_audio_stream', { id: currentStreamId }).catch(() => {});
+            currentStreamId = null;
+        }
 
-        const result = await invoke('prepare_audio_file', { path: encodeURIComponent(filePath) });
-
-        // 将返回的临时文件路径转换为可以在 WebView 中访问的 asset URL
-        const audioUrl = convertFileSrc(result.cachePath);
-        currentAudioCachePath = result.cachePath;
-
-        console.log('处理结果:', result);
-
-        if (result.lyrics) {
-            const lyricText = result.lyrics;
-            parsedLyrics = parseLRC(lyricText);
-            console.log(`Parsed ${parsedLyrics.length} lines of lyrics.`);
-            noLyricsMessage.classList.toggle('hidden', parsedLyrics.length > 0);
-            renderAllLyricsOnce();
-        } else {
-            console.log('No embedded lyrics found from backend.');
-            parsedLyrics = [];
-            renderAllLyricsOnce();
-            noLyricsMessage.classList.remove('hidden');
-        }
-        updateLyrics(0);
-
-        artistNameEl.textContent = result.metadata.artist || 'Unknown Artist';
-        songTitleEl.textContent = result.metadata.title || 'Unknown Title';
-
-        // Manually trigger check after new text is set.
-        // A small timeout helps ensure scrollWidth is updated.
-        setTimeout(() => {
-            // applyMarquee(songTitleEl);
-            // applyMarquee(artistNameEl);
-        }, 100);
-
-        if (result.albumArtBase64) {
-            const mimeType = result.metadata.mimeType || 'image/jpeg';
-            artworkUrl = `data:${mimeType};base64,${result.albumArtBase64}`;
-            albumArt.src = artworkUrl;
-            albumArt.style.display = 'block';
-            
-            // Always analyze image to get dominant color for background
-            analyzeImageAndApplyColors(artworkUrl);
+        const result = await prepareAudio(filePath, range);
+        // 已被更新的请求取代：丢弃结果，Loading 由新的请求负责
+        if (currentToken !== activeLoadToken) {
+            if (result?.streamId != null) invoke('close_audio_stream', { id: result.streamId }).catch(() => {});
+            return;
+        }
+        if (!result) {
+            hideLoading();
+            return;
+        }
 
-            // If adaptive color is disabled, ensure custom color is applied
-            if (!adaptiveColorToggle.checked) {
-                updateAdaptiveColors();
-            }
+        // 已缓存的完整文件通过 asset URL 播放；未转码完成时通过 stream:// 协议边转码边播放
+        let audioUrl;
+        if (result.cachePath) {
+            audioUrl = convertFileSrc(result.cachePath);
+            currentAudioCachePath = result.cachePath;
         } else {
-            // No artwork found.
-            artworkUrl = '';
-            albumArt.src = '';
-            albumArt.style.display = 'none';
-            applyAdaptiveColors({ text: '#ffffff' });
+            audioUrl = convertFileSrc(String(result.streamId), 'stream');
+            currentStreamId = result.streamId;
         }
 
-        // After updating artworkUrl, refresh all backgrounds
-        updateBackgrounds();
+        console.log('处理结果:', result);
+        currentTrackRequest = {
+            path: encodeURIComponent(filePath),
+            start: range?.start ?? null,
+        };
+        applyPlaybackGain(result.playbackGain);
+
+        showTrackInfo(result);
 
         // 设置音频并等待 metadata，确保进度条和时长已就绪
         const finalizeTransition = () => {
-            fileSelectContainer.classList.add('hidden');
-            githubLink.classList.add('hidden');
-            settingsBtn.classList.add('hidden'); // Hide settings icon
-            playerWrapper.classList.remove('hidden');
+            showPlayer();
             hideLoading();
         };
 
@@ -1315,8 +1579,21 @@ async function handleFile(filePath, autoPlay = true) {
 
     } catch (error) {
         console.error('处理音频时出错:', error);
-        alert(`Error: ${error}`);
+        if (currentToken !== activeLoadToken) return;
         hideLoading();
+        if (error?.code === 'ffmpegMissing') {
+            // 没有 ffmpeg 无法播放，但仍显示后端读到的标签、封面和歌词
+            if (error.track) {
+                showTrackInfo(error.track);
+                showPlayer();
+            }
+            // 用户同意下载且成功后，重新加载当前文件
+            if (await ensureFfmpeg() && currentToken === activeLoadToken) {
+                return handleFile(filePath, autoPlay, range);
+            }
+        } else {
+            alert(`Error: ${describeError(error)}`);
+        }
     } finally {
         // hideLoading 会在 finalizeTransition 中处理
     }
@@ -1324,6 +1601,63 @@ async function handleFile(filePath, autoPlay = true) {
 
 // === Playlist Logic ===
 
+// 当前后台扫描任务 id（用于取消）
+let activeScanJobId = null;
+
+// 在后台扫描文件夹：逐批接收文件并在 Loading 遮罩上显示进度。
+// 扫描被取消时返回 null。
+async function runLibraryScan(path) {
+    const batches = new Map(); // jobId -> files
+    const finishedJobs = new Map(); // jobId -> scan-finished payload
+    let jobId = null;
+    let settle;
+    const finished = new Promise(resolve => { settle = resolve; });
+    const tryFinish = () => {
+        if (jobId !== null && finishedJobs.has(jobId)) settle(finishedJobs.get(jobId));
+    };
+
+    const unlisteners = await Promise.all([
+        listen('scan-batch', ({ payload }) => {
+            if (!batches.has(payload.jobId)) batches.set(payload.jobId, []);
+            batches.get(payload.jobId).push(...payload.files);
+        }),
+        listen('scan-progress', ({ payload }) => {
+            if (payload.jobId !== jobId) return;
+            payload.errors.forEach(e => console.warn('Scan error:', e.path, e.message));
+            showLoading(`Scanning Folder... ${payload.filesMatched} files (${payload.directoriesVisited} folders)`);
+        }),
+        listen('scan-finished', ({ payload }) => {
+            finishedJobs.set(payload.jobId, payload);
+            tryFinish();
+        }),
+    ]);
+
+    try {
+        jobId = await invoke('start_library_scan', { path });
+        activeScanJobId = jobId;
+        tryFinish();
+        const result = await finished;
+        if (result.error) throw new Error(describeError(result.error));
+        if (result.cancelled) return null;
+        const files = batches.get(jobId) || [];
+        files.sort((a, b) => {
+            const x = a.name.toLowerCase();
+            const y = b.name.toLowerCase();
+            return x < y ? -1 : x > y ? 1 : 0;
+        });
+        return files;
+    } finally {
+        unlisteners.forEach(unlisten => unlisten());
+        activeScanJobId = null;
+    }
+}
+
+function cancelActiveScan() {
+    if (activeScanJobId !== null) {
+        invoke('cancel_library_scan', { jobId: activeScanJobId }).catch(() => {});
+    }
+}
+
 async function importFolder() {
     try {
         const selected = await dialogOpen({
@@ -1332,9 +1666,14 @@ async function importFolder() {
         });
         
         if (selected) {
-            showLoading('Scanning Folder...');
-            // Call backend to scan folder
-            const files = await invoke('scan_music_folder', { path: selected });
+            cancelActiveScan();
+            showLoading('Scanning Folder... (click to cancel)');
+            // Scan in the background; files stream in as batches
+            const files = await runLibraryScan(selected);
+            if (files === null) {
+                hideLoading();
+                return;
+            }
             
             if (files && files.length > 0) {
                 // Append or Replace? Let's Replace for now as it's cleaner
@@ -1358,7 +1697,7 @@ async function importFolder() {
                     });
                     
                     // Load file but pause
-                    await handleFile(track.path, false);
+                    await handleFile(track.path, false, track);
                 }
             } else {
                 alert('No supported audio files found in this folder.');
@@ -1422,6 +1761,71 @@ function deleteTrackAtIndex(index, event) {
     renderPlaylist();
 }
 
+// 检查 ffmpeg；找不到时询问用户是否下载（不会自动下载）。返回 ffmpeg 是否可用。
+async function ensureFfmpeg() {
+    const status = await invoke('get_ffmpeg_status', { refresh: false });
+    if (status.tools) return true;
+    console.warn('ffmpeg unavailable:', status.error?.message);
+    const confirmed = await ask('未找到 ffmpeg，无法播放音频。是否现在下载？（约 80 MB）', { title: '聆境（Soundscape）', kind: 'warning' });
+    if (!confirmed) return false;
+
+    let settle;
+    const finished = new Promise(resolve => { settle = resolve; });
+    const unlisteners = await Promise.all([
+        listen('ffmpeg-download-progress', ({ payload }) => {
+            const mb = (payload.downloadedBytes / 1048576).toFixed(1);
+            showLoading(payload.stage === 'downloading' ? `Downloading ffmpeg... ${mb} MB` : `Installing ffmpeg (${payload.stage})...`);
+        }),
+        listen('ffmpeg-download-finished', ({ payload }) => settle(payload)),
+    ]);
+    try {
+        showLoading('Downloading ffmpeg...');
+        if (!await invoke('download_ffmpeg')) return false;
+        const result = await finished;
+        if (result.error) alert(describeError(result.error));
+        return !!result.tools;
+    } finally {
+        unlisteners.forEach(unlisten => unlisten());
+        hideLoading();
+    }
+}
+
+// CUE 分轨共享同一个音频文件路径，需要再比较起始时间
+function isSameTrack(track, path, start) {
+    return track.path === path && (track.start ?? null) === (start ?? null);
+}
+
+// 后端文件监视器：导入的文件夹在磁盘上发生变化时同步播放列表
+function setupLibraryWatchListeners() {
+    listen('track-added', ({ payload }) => {
+        // 只追加到来自同一音乐库根目录的播放列表
+        const belongsToPlaylist = playlist.some(t => t.path.startsWith(payload.root));
+        if (!belongsToPlaylist || playlist.some(t => isSameTrack(t, payload.path, payload.start))) return;
+        const track = { path: payload.path, name: payload.name };
+        if (payload.start != null) {
+            track.start = payload.start;
+            if (payload.end != null) track.end = payload.end;
+        }
+        playlist.push(track);
+        savePlaylistState();
+        renderPlaylist();
+    });
+
+    listen('track-removed', ({ payload }) => {
+        const index = playlist.findIndex(t => isSameTrack(t, payload.path, payload.start));
+        if (index !== -1) deleteTrackAtIndex(index);
+    });
+
+    listen('track-moved', ({ payload }) => {
+        const track = playlist.find(t => isSameTrack(t, payload.from, payload.start));
+        if (!track) return;
+        track.path = payload.to;
+        track.name = payload.name;
+        savePlaylistState();
+        renderPlaylist();
+    });
+}
+
 // 拖拽相关变量
 let dragSrcEl = null;
 let dragSrcIndex = -1;
@@ -1760,7 +2164,30 @@ async function playTrackAtIndex(index) {
         else item.classList.remove('active');
     });
     
-    await handleFile(track.path);
+    await handleFile(track.path, true, track);
+    prefetchUpcoming();
+}
+
+// 在后台预先准备队列中接下来的几首，切歌时可立即播放
+const PREFETCH_COUNT = 2;
+
+function prefetchUpcoming() {
+    if (playlist.length === 0 || currentPlaylistIndex < 0) return;
+    const tracks = [];
+    for (let i = 1; i <= PREFETCH_COUNT && i < playlist.length; i++) {
+        let index = currentPlaylistIndex + i;
+        if (index >= playlist.length) {
+            if (playMode === 'play-list') break;
+            index %= playlist.length;
+        }
+        const track = playlist[index];
+        tracks.push({
+            path: encodeURIComponent(track.path),
+            start: track.start ?? null,
+            end: track.end ?? null,
+        });
+    }
+    invoke('prefetch_tracks', { tracks }).catch(e => console.warn('Prefetch failed:', e));
 }
 
 function togglePlayMode() {
@@ -1850,6 +2277,11 @@ function setupPlaylist() {
     importFolderBtn.addEventListener('click', importFolder);
     importFolderBtnEmpty.addEventListener('click', importFolder);
     clearPlaylistBtn.addEventListener('click', clearPlaylist); // New
+    setupLibraryWatchListeners();
+    setupLoudnessListener();
+    ensureFfmpeg().catch(e => console.error('Error checking ffmpeg:', e));
+    loadingOverlay.addEventListener('click', cancelActiveScan);
+    loadingOverlay.addEventListener('click', cancelActiveTranscode);
     
     playModeBtn.addEventListener('click', togglePlayMode);
     
@@ -1924,7 +2356,7 @@ audioPlayer.addEventListener('timeupdate', () => {
         try {
             const selected = await dialogOpen({
                 multiple: false,
-            filters: [{ name: 'Audio', extensions: ['mp3', 'flac', 'wav', 'm4a'] }]
+            filters: [{ name: 'Audio', extensions: audioExtensions }]
             });
             if (selected) {
                 await handleFile(selected);
@@ -1996,7 +2428,7 @@ document.body.addEventListener('drop', async (e) => {
         
         // 查找音频文件
         const files = Array.from(e.dataTransfer.files);
-        const audioFile = files.find(f => /\.(mp3|wav|flac|m4a)$/i.test(f.name));
+        const audioFile = files.find(f => audioFilePattern.test(f.name));
         
         if (audioFile) {
             // 对于 Tauri，我们需要文件路径而非 File 对象
@@ -2009,7 +2441,7 @@ document.body.addEventListener('drop', async (e) => {
                 alert('无法获取文件路径。请使用文件选择器导入音频。');
             }
         } else {
-            alert('请拖入有效的音频文件 (mp3, wav, flac, m4a)');
+            alert(`请拖入有效的音频文件 (${audioExtensions.join(', ')})`);
         }
     }
 });
@@ -2133,13 +2565,15 @@ function resetPlayerUI() {
 
     // Clear and hide lyrics
     parsedLyrics = [];
+    lyricsUnsynced = false;
     lyricsLinesContainer.innerHTML = '';
+    lyricsLinesContainer.classList.remove('unsynced');
     noLyricsMessage.classList.add('hidden');
     currentLyricIndex = -1;
     document.body.classList.remove(
         'lyrics-active', 'lyrics-mode-translation', 'lyrics-mode-bilingual', 
         'lyrics-mode-bilingual-reversed', 'lyrics-mode-original', 'lyrics-mode-text-only', 
-        'lyrics-mode-text-only-reversed', 'cover-mode'
+        'lyrics-mode-text-only-reversed', 'lyrics-mode-romanized', 'cover-mode'
     );
     lyricsContainer.classList.add('hidden');
     coverModeLyricsContainer.classList.add('hidden');
@@ -2163,6 +2597,8 @@ function toggleLyrics() {
     // UPDATED: A song is considered to have translations only if there's at least one
     // translated line that isn't just metadata.
     const hasTranslation = parsedLyrics.some(l => l.translation && !metaRegex.test(l.text));
+    // 后端为日/中/韩歌词生成的罗马音（或歌词文件自带的注音）
+    const hasRomanization = parsedLyrics.some(l => l.romanization);
 
     // If there's no translation, just toggle between off (0) and original (4).
     if (!hasTranslation) {
@@ -2172,6 +2608,11 @@ function toggleLyrics() {
             4: 5, // Original -> Text Only
             5: 0, // Text Only -> Off
         };
+        if (hasRomanization) {
+            // Original -> Romanized -> Text Only
+            nextModeMap[4] = 7;
+            nextModeMap[7] = 5;
+        }
         lyricsDisplayMode = nextModeMap[lyricsDisplayMode] ?? 0;
     } else {
         // Cycle with new modes: Off(0) -> Bilingual(2) -> Reversed(3) -> Original(4) -> Translation(1) -> Text Only(5) -> Text Only Reversed(6) -> Off(0)
@@ -2184,6 +2625,11 @@ function toggleLyrics() {
             5: 6, // Text Only -> Text Only Reversed
             6: 0  // Text Only Reversed -> Off
         };
+        if (hasRomanization) {
+            // Bilingual -> Romanized -> Bilingual Reversed
+            nextModeMap[2] = 7;
+            nextModeMap[7] = 3;
+        }
         lyricsDisplayMode = nextModeMap[lyricsDisplayMode] ?? 0; // Default to Off if state is weird
     }
 
@@ -2194,7 +2640,7 @@ function toggleLyrics() {
     lyricsContainer.classList.toggle('hidden', !lyricsActive);
 
     // Remove all mode classes before adding the new one
-    document.body.classList.remove('lyrics-active', 'lyrics-mode-translation', 'lyrics-mode-bilingual', 'lyrics-mode-bilingual-reversed', 'lyrics-mode-original', 'lyrics-mode-text-only', 'lyrics-mode-text-only-reversed');
+    document.body.classList.remove('lyrics-active', 'lyrics-mode-translation', 'lyrics-mode-bilingual', 'lyrics-mode-bilingual-reversed', 'lyrics-mode-original', 'lyrics-mode-text-only', 'lyrics-mode-text-only-reversed', 'lyrics-mode-romanized');
 
     if (lyricsActive) {
         document.body.classList.add('lyrics-active');
@@ -2223,6 +2669,10 @@ function toggleLyrics() {
                 modeString = 'text-only-reversed';
                 document.body.classList.add('lyrics-mode-text-only-reversed');
                 break;
+            case 7: // Romanized (orig/romanization/trans)
+                modeString = 'romanized';
+                document.body.classList.add('lyrics-mode-romanized');
+                break;
         }
         // Now that we have the correct mode string, show the indicator.
         showLyricsModeIndicator(modeString);
@@ -2241,168 +2691,19 @@ function toggleLyrics() {
 // All marquee-related JavaScript has been removed for simplicity.
 // Text will now wrap by default based on CSS rules.
 
-function parseLRC(lrcText) {
-    const lines = lrcText.split(/\r\n|\n|\r/);
-    const timeRegex = /\[(\d{2}):(\d{2})[.:](\d{2,3})\]/;
-    // Regex for checking multiple timestamps (global)
-    const globalTimeRegex = /\[(\d{2}):(\d{2})[.:](\d{2,3})\]/g;
-    const intermediate = [];
-
-    for (const line of lines) {
-        // Check if line has multiple timestamps (indicating word-by-word)
-        const allMatches = [...line.matchAll(globalTimeRegex)];
-        
-        if (allMatches.length > 1) {
-            const wordData = parseWordByWordLine(line);
-            if (wordData) {
-                intermediate.push({
-                    time: wordData.startTime,
-                    text: wordData.fullText,
-                    isWordByWord: true,
-                    words: wordData.words
-                });
-                continue;
-            }
-        }
-
-        // Standard LRC parsing
-        const match = line.match(timeRegex);
-        if (match) {
-            const minutes = parseInt(match[1], 10);
-            const seconds = parseInt(match[2], 10);
-            const milliseconds = parseInt(match[3].padEnd(3, '0'), 10);
-            const time = minutes * 60 + seconds + milliseconds / 1000;
-            const text = line.replace(timeRegex, '').trim();
-            if (text) {
-                intermediate.push({ time, text, isWordByWord: false });
-            }
-        }
-    }
-
-    if (intermediate.length === 0) return [];
-
-    const finalLyrics = [];
-    for (let i = 0; i < intermediate.length; i++) {
-        const current = intermediate[i];
-        const next = i + 1 < intermediate.length ? intermediate[i + 1] : null;
-
-        // Translation logic: if next line has same time, treat as translation
-        if (next && Math.abs(next.time - current.time) < 0.01) {
-            // Note: If 'current' is word-by-word, 'next' (translation) usually isn't, 
-            // or if it is, we currently don't support word-by-word translation syncing yet 
-            // (plan said "Translation line keeps sentence-by-sentence").
-            // So we just take next.text as translation.
-            
-            finalLyrics.push({ 
-                time: current.time, 
-                text: current.text, 
-                translation: next.text,
-                isWordByWord: current.isWordByWord,
-                words: current.words
-            });
-            i++; // Skip next line
-        } else {
-            finalLyrics.push({ 
-                time: current.time, 
-                text: current.text, 
-                translation: null,
-                isWordByWord: current.isWordByWord,
-                words: current.words
-            });
-        }
-    }
-    
-    // Post-process: Calculate duration for the last word in each word-by-word line
-    // based on the next line's start time (if available).
-    for (let i = 0; i < finalLyrics.length; i++) {
-        const current = finalLyrics[i];
-        if (current.isWordByWord && current.words.length > 0) {
-            const lastWord = current.words[current.words.length - 1];
-            // If last word duration is unset or just defaulted
-            // Try to cap it with next line time
-            const nextLine = i + 1 < finalLyrics.length ? finalLyrics[i+1] : null;
-            if (nextLine) {
-                // If the calculated end time exceeds next line start, trim it?
-                // Or just use next line start as the hard limit for the last word?
-                // The parser logic sets endTime based on next word. 
-                // For the very last word, we need a duration.
-                // Let's ensure it has *some* duration.
-                if (nextLine.time > lastWord.startTime) {
-                    lastWord.endTime = nextLine.time;
-                } else {
-                    // Fallback: +0.5s or +1s
-                    lastWord.endTime = lastWord.startTime + 1.0;
-                }
-            } else {
-                 // No next line, end of song
-                 lastWord.endTime = lastWord.startTime + 2.0; 
-            }
-        }
-    }
-
-    return finalLyrics;
-}
-
 /**
- * Parses a line with multiple timestamps into words
+ * Turns the lyrics timeline parsed by the backend into display lines.
+ * The backend pairs each line with its translation and romanization.
  */
-function parseWordByWordLine(line) {
-    const timeRegex = /\[(\d{2}):(\d{2})[.:](\d{2,3})\]/g;
-    const matches = [...line.matchAll(timeRegex)];
-    if (matches.length === 0) return null;
-
-    const words = [];
-    let fullText = "";
-    
-    // First timestamp defines line start time
-    let lineStartTime = 0;
-
-    for (let i = 0; i < matches.length; i++) {
-        const match = matches[i];
-        const minutes = parseInt(match[1], 10);
-        const seconds = parseInt(match[2], 10);
-        const milliseconds = parseInt(match[3].padEnd(3, '0'), 10);
-        const startTime = minutes * 60 + seconds + milliseconds / 1000;
-        
-        if (i === 0) lineStartTime = startTime;
-
-        // Content is between current match end and next match start (or line end)
-        const startIdx = match.index + match[0].length;
-        const endIdx = i < matches.length - 1 ? matches[i+1].index : line.length;
-        const text = line.substring(startIdx, endIdx);
-        
-        // Even if text is empty, we record it (could be spacing or just time anchor)
-        // But for display, empty text spans might be invisible. 
-        // We will filter or handle empty strings in renderer? 
-        // Better to keep them for timing continuity.
-        
-        fullText += text;
-
-        // Determine end time
-        let endTime;
-        if (i < matches.length - 1) {
-            const nextMatch = matches[i+1];
-            const nMin = parseInt(nextMatch[1], 10);
-            const nSec = parseInt(nextMatch[2], 10);
-            const nMs = parseInt(nextMatch[3].padEnd(3, '0'), 10);
-            endTime = nMin * 60 + nSec + nMs / 1000;
-        } else {
-            // Placeholder, will be fixed in post-process
-            endTime = startTime + 0.5; 
-        }
-
-        words.push({
-            text,
-            startTime,
-            endTime
-        });
-    }
-
-    return {
-        startTime: lineStartTime,
-        fullText: fullText.trim(), // Trim full text for cleaner display if it has bounding spaces
-        words
-    };
+function buildLyricLines(lyrics) {
+    return (lyrics?.lines ?? []).map(line => ({
+        time: line.time,
+        text: line.text,
+        translation: line.translation ?? null,
+        romanization: line.romanization ?? null,
+        isWordByWord: line.words.length > 0,
+        words: line.words
+    }));
 }
 
 /**
@@ -2569,7 +2870,7 @@ function updateCoverModeLyrics() {
 }
 
 function updateLyrics(currentTime, forceRecalc = false) {
-    if (parsedLyrics.length === 0) {
+    if (parsedLyrics.length === 0 || lyricsUnsynced) {
         return;
     }
 
@@ -2695,7 +2996,7 @@ function renderAllLyricsOnce() {
                 wSpan.textContent = word.text; 
                 // Note: fixProblemGlyphs is already done on full text, but words are raw.
                 // We should probably apply it to words too if they match? 
-                // Actually parseWordByWordLine extracts raw text.
+                // Word text comes from the backend as written in the file.
                 // Let's just assume words are fine or apply fix if needed.
                 wSpan.textContent = fixProblemGlyphs(word.text);
                 
@@ -2715,6 +3016,12 @@ function renderAllLyricsOnce() {
         }
 
         li.appendChild(originalSpan);
+
+        const romanizationSpan = document.createElement('span');
+        romanizationSpan.className = 'romanized-lyric';
+        romanizationSpan.lang = 'en';
+        romanizationSpan.textContent = line.romanization || '';
+        li.appendChild(romanizationSpan);
         
         const translationSpan = document.createElement('span');
         translationSpan.className = 'translated-lyric';
@@ -2755,7 +3062,8 @@ function showLyricsModeIndicator(mode) {
         'bilingual': { icon: 'Aあ<br>译', text: '双语模式' },
         'bilingual-reversed': { icon: '译<br>Aあ', text: '双语模式 (反转)' },
         'text-only': { icon: '文', text: '纯文字模式' },
-        'text-only-reversed': { icon: '译<br>文', text: '纯文字模式 (反转)' }
+        'text-only-reversed': { icon: '译<br>文', text: '纯文字模式 (反转)' },
+        'romanized': { icon: 'Aあ<br>a', text: '罗马音模式' }
     };
 
     const config = modeMap[mode] || { icon: '?', text: '未知模式' };
@@ -2774,6 +3082,8 @@ function showLyricsModeIndicator(mode) {
 document.addEventListener('DOMContentLoaded', () => {
     // All initial setup calls can go here.
     setupSettings();
+    setupBackendSettings();
+    loadAudioExtensions();
     // NEW: Ensure functions are defined before calling
     if (typeof setupPlaylist === 'function') setupPlaylist(); 
     if (typeof loadPlaylistState === 'function') loadPlaylistState(); 